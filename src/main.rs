@@ -1,7 +1,7 @@
 use anyhow::Result;
 use bytes::Bytes;
 use clap::Parser;
-use futures_util::{SinkExt, StreamExt};
+use futures_util::{FutureExt, SinkExt, StreamExt};
 use kanal::{unbounded_async, AsyncSender};
 use tokio::net::{TcpListener, TcpStream};
 use tokio_util::codec::Framed;
@@ -9,11 +9,23 @@ use uuid::Uuid;
 
 mod redis;
 use redis::{
-    protocol::{RespProtocol, Value},
-    server::{RedisServer, Request, ServerRole},
+    protocol::{ProtocolLimits, RedisError, RespProtocol, Value},
+    server::{
+        EvictionPolicy, GlobalStore, KeyspaceEvents, MaxMemory, RedisServer, Request, ServerRole,
+        DEFAULT_DATABASES,
+    },
+    utils::{alloc::CountingAllocator, parse_memory_size},
 };
 
 use std::path::PathBuf;
+use std::sync::Arc;
+
+// Requests sent to the workers in one batch. The rest of a longer pipeline stays buffered
+// and goes in the batches that follow, so one client can't grow a batch without bound
+const MAX_BATCH_REQUESTS: usize = 4096;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
 
 #[derive(Parser)]
 struct Cli {
@@ -28,20 +40,70 @@ struct Cli {
 
     #[arg(long)]
     pub dbfilename: Option<PathBuf>,
+
+    #[arg(long, default_value_t = DEFAULT_DATABASES)]
+    pub databases: usize,
+
+    #[arg(long, default_value = "0", value_parser = memory_size)]
+    pub maxmemory: usize,
+
+    #[arg(long, default_value = "noeviction", value_parser = eviction_policy)]
+    pub maxmemory_policy: EvictionPolicy,
+
+    #[arg(long, default_value_t = 5)]
+    pub maxmemory_samples: usize,
+
+    #[arg(long, default_value = "", value_parser = keyspace_events)]
+    pub notify_keyspace_events: KeyspaceEvents,
+
+    #[arg(long, default_value = "512mb", value_parser = memory_size)]
+    pub proto_max_bulk_len: usize,
+
+    #[arg(long, default_value_t = i32::MAX as usize)]
+    pub proto_max_multibulk_len: usize,
+
+    #[arg(long, default_value = "1gb", value_parser = memory_size)]
+    pub client_query_buffer_limit: usize,
+}
+
+fn memory_size(s: &str) -> Result<usize, String> {
+    parse_memory_size(s).map_err(|_| format!("invalid memory size '{s}'"))
+}
+
+fn eviction_policy(s: &str) -> Result<EvictionPolicy, String> {
+    EvictionPolicy::parse(s).map_err(|e| e.to_string())
+}
+
+fn keyspace_events(s: &str) -> Result<KeyspaceEvents, String> {
+    KeyspaceEvents::parse(s).map_err(|e| e.to_string())
 }
 
 struct ConnectionHandler {
     id: Bytes,
     stream: Framed<TcpStream, RespProtocol>,
     request_channel: AsyncSender<Request>,
+    store: Arc<GlobalStore>,
 }
 
 impl ConnectionHandler {
-    pub fn new(stream: TcpStream, node_channel: AsyncSender<Request>) -> Self {
+    pub fn new(
+        stream: TcpStream,
+        node_channel: AsyncSender<Request>,
+        limits: ProtocolLimits,
+        store: Arc<GlobalStore>,
+    ) -> Self {
         Self {
             id: Bytes::from(Uuid::new_v4().to_string()),
-            stream: Framed::new(stream, RespProtocol),
+            stream: Framed::new(stream, RespProtocol::new(limits)),
             request_channel: node_channel,
+            store,
+        }
+    }
+
+    // State kept for the client outside the connection would otherwise outlive it
+    fn disconnect(&self) {
+        if let Err(err) = self.store.disconnect(&self.id) {
+            eprintln!("error cleaning up connection: {err:#?}");
         }
     }
 
@@ -53,21 +115,25 @@ impl ConnectionHandler {
                 incoming_value = self.stream.next() => {
                     match incoming_value {
                         Some(Ok(value)) => {
-                            self.request_channel.send((value, self.id.clone(), tx.clone())).await?;
+                            let (requests, decode_error) = self.buffered_requests(value);
+                            self.request_channel.send((requests, self.id.clone(), tx.clone())).await?;
                             let Ok(response) = rx.recv().await else {
                                 self.stream.send(Value::error("error occurrec receiving value".into())).await?;
                                 continue;
                             };
 
-                            for r in response {
-                                self.stream.send(r).await?;
+                            self.write_response(response).await?;
+
+                            if let Some(e) = decode_error {
+                                self.protocol_error(e).await?;
+                                break;
                             }
                         },
                         Some(Err(e)) => {
-                            eprintln!("{e:#?}");
+                            self.protocol_error(e).await?;
                             break;
                         }
-                        None => continue,
+                        None => break,
                     }
                 }
 
@@ -77,15 +143,54 @@ impl ConnectionHandler {
                         continue;
                     };
 
-                    for r in response {
-                        self.stream.send(r).await?;
-                    }
+                    self.write_response(response).await?;
                 }
             }
         }
 
         Ok(())
     }
+
+    // Collects the requests that can be decoded without waiting on the socket so a
+    // pipeline is sent to the workers in as few batches as possible
+    fn buffered_requests(&mut self, first: Value) -> (Vec<Value>, Option<RedisError>) {
+        let mut requests = vec![first];
+        while requests.len() < MAX_BATCH_REQUESTS {
+            let Some(Some(frame)) = self.stream.next().now_or_never() else {
+                break;
+            };
+
+            match frame {
+                Ok(value) => requests.push(value),
+                Err(e) => return (requests, Some(e)),
+            }
+        }
+
+        (requests, None)
+    }
+
+    // Malformed or oversized requests get an error reply before the connection is closed
+    async fn protocol_error(&mut self, err: RedisError) -> Result<()> {
+        eprintln!("{err:#?}");
+        if let RedisError::IOError(_) = err {
+            return Ok(());
+        }
+
+        self.stream
+            .send(Value::error(format!("ERR {err}").into()))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn write_response(&mut self, response: Vec<Value>) -> Result<()> {
+        for r in response {
+            self.stream.feed(r).await?;
+        }
+
+        self.stream.flush().await?;
+        Ok(())
+    }
 }
 
 fn determine_server_role(replica: Option<String>) -> ServerRole {
@@ -120,21 +225,35 @@ async fn main() -> Result<()> {
     let listener = TcpListener::bind(addr).await.unwrap();
     let (tx, rx) = unbounded_async::<Request>();
 
+    let limits = ProtocolLimits {
+        max_bulk_len: args.proto_max_bulk_len,
+        max_multibulk_len: args.proto_max_multibulk_len,
+        query_buffer_limit: args.client_query_buffer_limit,
+    };
+
     let working_dir = args.dir;
     let dbfile = args.dbfilename;
     let role = determine_server_role(args.replicaof);
-    let mut server = RedisServer::new(role, args.port)?;
+    let mut server = RedisServer::new(role, args.port, args.databases)?;
     server.init(working_dir, dbfile)?;
+    server.configure_limits(&limits)?;
+    server.configure_eviction(MaxMemory {
+        limit: args.maxmemory,
+        policy: args.maxmemory_policy,
+        samples: args.maxmemory_samples.max(1),
+    })?;
+    server.configure_notifications(args.notify_keyspace_events)?;
     server.start(rx);
 
     loop {
         if let Ok(stream) = listener.accept().await {
             let (stream, _) = stream;
-            let mut handler = ConnectionHandler::new(stream, tx.clone());
+            let mut handler = ConnectionHandler::new(stream, tx.clone(), limits, server.store());
             tokio::task::spawn(async move {
                 if let Err(err) = handler.handle_connection().await {
                     eprintln!("connection error occurred: {err:#?}");
                 };
+                handler.disconnect();
             });
         }
     }
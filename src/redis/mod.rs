@@ -2,6 +2,6 @@ pub mod protocol;
 mod rdb;
 pub mod server;
 mod stores;
-mod utils;
+pub mod utils;
 
 use protocol::{RedisCommand, RedisError};
@@ -4,7 +4,7 @@ use bytes::{Bytes, BytesMut};
 use std::str;
 use tokio_util::codec::{Decoder, Encoder};
 
-use super::{RedisError, Value};
+use super::{format_double, RedisError, Value};
 
 type ProtocolResult = Result<Option<(usize, InterimValue)>, RedisError>;
 
@@ -26,47 +26,82 @@ impl Phrase {
 
 enum InterimValue {
     String(Phrase),
+    Error(Phrase),
     NullString,
     Integer(i64),
     Array(Vec<InterimValue>),
     NullArray,
+    Map(Vec<(InterimValue, InterimValue)>),
+    Set(Vec<InterimValue>),
+    Double(f64),
+    Boolean(bool),
+    BigNumber(Phrase),
+    Verbatim(Phrase, Phrase),
+    Null,
+    Push(Vec<InterimValue>),
 }
 
 impl InterimValue {
     fn into_value(self, buf: &Bytes) -> Value {
         match self {
             Self::String(s) => Value::String(s.as_bytes(buf)),
-            Self::Array(arr) => {
-                Value::Array(arr.into_iter().map(|item| item.into_value(buf)).collect())
-            }
+            Self::Error(e) => Value::Error(e.as_bytes(buf)),
+            Self::Array(arr) => Value::Array(Self::into_values(arr, buf)),
             Self::Integer(int) => Value::Integer(int),
             Self::NullString => Value::NullString,
             Self::NullArray => Value::NullArray,
+            Self::Map(pairs) => Value::Map(
+                pairs
+                    .into_iter()
+                    .map(|(k, v)| (k.into_value(buf), v.into_value(buf)))
+                    .collect(),
+            ),
+            Self::Set(set) => Value::Set(Self::into_values(set, buf)),
+            Self::Double(double) => Value::Double(double),
+            Self::Boolean(boolean) => Value::Boolean(boolean),
+            Self::BigNumber(num) => Value::BigNumber(num.as_bytes(buf)),
+            Self::Verbatim(format, s) => Value::Verbatim(format.as_bytes(buf), s.as_bytes(buf)),
+            Self::Null => Value::Null,
+            Self::Push(push) => Value::Push(Self::into_values(push, buf)),
         }
     }
+
+    fn into_values(values: Vec<InterimValue>, buf: &Bytes) -> Vec<Value> {
+        values
+            .into_iter()
+            .map(|item| item.into_value(buf))
+            .collect()
+    }
 }
 
-fn parse(buf: &BytesMut, mut pos: usize) -> ProtocolResult {
-    if buf.is_empty() {
-        return Ok(None);
+fn parse(buf: &BytesMut, mut pos: usize, limits: &ProtocolLimits, depth: usize) -> ProtocolResult {
+    if depth > MAX_NESTING_DEPTH {
+        return Err(RedisError::Protocol("too many nested aggregates".into()));
     }
 
     loop {
+        if pos >= buf.len() {
+            return Ok(None);
+        }
+
         let idx = pos + 1;
         match buf[pos] {
             b'+' => return simple_string(buf, idx),
-            b'$' => return bulk_string(buf, idx),
+            b'-' => return simple_error(buf, idx),
+            b'$' => return bulk_string(buf, idx, limits),
             b':' => return integer(buf, idx),
-            b'*' => return array(buf, idx),
-            b'\r' | b'\n' => {
-                if buf[pos] == b'\r' {
-                    pos += 2;
-                } else {
-                    pos += 1;
-                }
-
-                continue;
-            }
+            b'*' => return array(buf, idx, limits, depth),
+            b'_' => return null(buf, idx),
+            b',' => return double(buf, idx),
+            b'#' => return boolean(buf, idx),
+            b'(' => return big_number(buf, idx),
+            b'!' => return bulk_error(buf, idx, limits),
+            b'=' => return verbatim_string(buf, idx, limits),
+            b'%' => return map(buf, idx, limits, depth),
+            b'~' => return set(buf, idx, limits, depth),
+            b'>' => return push(buf, idx, limits, depth),
+            // Stray line endings between elements are skipped a byte at a time
+            b'\r' | b'\n' => pos += 1,
             b => return Err(RedisError::InvalidProtocolByte(b as char)),
         }
     }
@@ -106,49 +141,353 @@ fn simple_string(buf: &BytesMut, pos: usize) -> ProtocolResult {
     Ok(word(buf, pos).map(|(pos, phrase)| (pos, InterimValue::String(phrase))))
 }
 
-fn bulk_string(buf: &BytesMut, pos: usize) -> ProtocolResult {
+fn simple_error(buf: &BytesMut, pos: usize) -> ProtocolResult {
+    Ok(word(buf, pos).map(|(pos, phrase)| (pos, InterimValue::Error(phrase))))
+}
+
+// Length prefixed blob shared by bulk strings, bulk errors and verbatim strings
+fn blob(
+    buf: &BytesMut,
+    pos: usize,
+    limits: &ProtocolLimits,
+) -> Result<Option<(usize, Option<Phrase>)>, RedisError> {
     match int(buf, pos)? {
-        Some((next, -1)) => Ok(Some((next, InterimValue::NullString))),
+        Some((next, -1)) => Ok(Some((next, None))),
+        Some((_, size)) if size as u64 > limits.max_bulk_len as u64 => {
+            Err(RedisError::Protocol("invalid bulk length".into()))
+        }
         Some((next, size)) if size >= 0 => {
             let total_size = next + size as usize;
             if buf.len() < total_size + 2 {
                 return Ok(None);
             }
 
-            let bulk_string = InterimValue::String(Phrase(next, total_size));
-            Ok(Some((total_size + 2, bulk_string)))
+            Ok(Some((total_size + 2, Some(Phrase(next, total_size)))))
         }
         Some((_, invalid)) => Err(RedisError::InvalidSize(invalid)),
         None => Ok(None),
     }
 }
 
+// The RDB file sent for a full resync is a bulk string without the trailing CRLF
+fn rdb(buf: &BytesMut, limits: &ProtocolLimits) -> Result<Option<(usize, Phrase)>, RedisError> {
+    if buf[0] != b'$' {
+        return Err(RedisError::InvalidProtocolByte(buf[0] as char));
+    }
+
+    match int(buf, 1)? {
+        Some((_, size)) if size < 0 || size as u64 > limits.max_bulk_len as u64 => {
+            Err(RedisError::InvalidSize(size))
+        }
+        Some((next, size)) => {
+            let end = next + size as usize;
+            Ok((buf.len() >= end).then_some((end, Phrase(next, end))))
+        }
+        None => Ok(None),
+    }
+}
+
+fn bulk_string(buf: &BytesMut, pos: usize, limits: &ProtocolLimits) -> ProtocolResult {
+    Ok(blob(buf, pos, limits)?.map(|(next, phrase)| match phrase {
+        Some(phrase) => (next, InterimValue::String(phrase)),
+        None => (next, InterimValue::NullString),
+    }))
+}
+
+fn bulk_error(buf: &BytesMut, pos: usize, limits: &ProtocolLimits) -> ProtocolResult {
+    match blob(buf, pos, limits)? {
+        Some((next, Some(phrase))) => Ok(Some((next, InterimValue::Error(phrase)))),
+        Some((_, None)) => Err(RedisError::InvalidSize(-1)),
+        None => Ok(None),
+    }
+}
+
+fn verbatim_string(buf: &BytesMut, pos: usize, limits: &ProtocolLimits) -> ProtocolResult {
+    match blob(buf, pos, limits)? {
+        // Verbatim strings are prefixed with a three byte format and a colon e.g. 'txt:'
+        Some((next, Some(Phrase(start, end)))) if end - start >= 4 => {
+            let format = Phrase(start, start + 3);
+            let phrase = Phrase(start + 4, end);
+            Ok(Some((next, InterimValue::Verbatim(format, phrase))))
+        }
+        Some((_, _)) => Err(RedisError::UnexpectedValue),
+        None => Ok(None),
+    }
+}
+
+fn null(buf: &BytesMut, pos: usize) -> ProtocolResult {
+    Ok(word(buf, pos).map(|(pos, _)| (pos, InterimValue::Null)))
+}
+
+fn double(buf: &BytesMut, pos: usize) -> ProtocolResult {
+    match word(buf, pos) {
+        Some((next, phrase)) => {
+            let s = str::from_utf8(phrase.as_slice(buf)).map_err(|_| RedisError::NumberParse)?;
+            let double = s.parse::<f64>().map_err(|_| RedisError::NumberParse)?;
+            Ok(Some((next, InterimValue::Double(double))))
+        }
+        None => Ok(None),
+    }
+}
+
+fn boolean(buf: &BytesMut, pos: usize) -> ProtocolResult {
+    match word(buf, pos) {
+        Some((next, phrase)) => match phrase.as_slice(buf) {
+            b"t" => Ok(Some((next, InterimValue::Boolean(true)))),
+            b"f" => Ok(Some((next, InterimValue::Boolean(false)))),
+            _ => Err(RedisError::UnexpectedValue),
+        },
+        None => Ok(None),
+    }
+}
+
+fn big_number(buf: &BytesMut, pos: usize) -> ProtocolResult {
+    Ok(word(buf, pos).map(|(pos, phrase)| (pos, InterimValue::BigNumber(phrase))))
+}
+
 fn integer(buf: &BytesMut, pos: usize) -> ProtocolResult {
     Ok(int(buf, pos)?.map(|(pos, int)| (pos, InterimValue::Integer(int))))
 }
 
-fn array(buf: &BytesMut, pos: usize) -> ProtocolResult {
+// Parses `count` consecutive values, used by all aggregate types
+fn aggregate(
+    buf: &BytesMut,
+    pos: usize,
+    count: i64,
+    limits: &ProtocolLimits,
+    depth: usize,
+) -> Result<Option<(usize, Vec<InterimValue>)>, RedisError> {
+    if count as u64 > limits.max_multibulk_len as u64 {
+        return Err(RedisError::Protocol("invalid multibulk length".into()));
+    }
+
+    // The declared length is untrusted so only a bounded amount is allocated up front
+    let count = count as usize;
+    let mut values = Vec::with_capacity(count.min(MAX_PREALLOCATED_VALUES));
+    let mut current_idx = pos;
+    for _ in 0..count {
+        match parse(buf, current_idx, limits, depth + 1)? {
+            Some((new_pos, value)) => {
+                current_idx = new_pos;
+                values.push(value);
+            }
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some((current_idx, values)))
+}
+
+fn array(buf: &BytesMut, pos: usize, limits: &ProtocolLimits, depth: usize) -> ProtocolResult {
     match int(buf, pos)? {
         None => Ok(None),
         Some((next, -1)) => Ok(Some((next, InterimValue::NullArray))),
         Some((pos, total_size)) if total_size >= 0 => {
-            let mut values = Vec::with_capacity(total_size as usize);
-            let mut current_idx = pos;
-            for _ in 0..total_size {
-                match parse(buf, current_idx)? {
-                    Some((new_pos, value)) => {
-                        current_idx = new_pos;
-                        values.push(value);
+            Ok(aggregate(buf, pos, total_size, limits, depth)?
+                .map(|(next, values)| (next, InterimValue::Array(values))))
+        }
+        Some((_, invalid)) => Err(RedisError::InvalidSize(invalid)),
+    }
+}
+
+fn map(buf: &BytesMut, pos: usize, limits: &ProtocolLimits, depth: usize) -> ProtocolResult {
+    match int(buf, pos)? {
+        None => Ok(None),
+        Some((pos, total_size)) if total_size >= 0 => {
+            Ok(
+                aggregate(buf, pos, total_size.saturating_mul(2), limits, depth)?.map(
+                    |(next, values)| {
+                        let mut pairs = Vec::with_capacity(values.len() / 2);
+                        let mut values = values.into_iter();
+                        while let (Some(k), Some(v)) = (values.next(), values.next()) {
+                            pairs.push((k, v));
+                        }
+
+                        (next, InterimValue::Map(pairs))
+                    },
+                ),
+            )
+        }
+        Some((_, invalid)) => Err(RedisError::InvalidSize(invalid)),
+    }
+}
+
+fn set(buf: &BytesMut, pos: usize, limits: &ProtocolLimits, depth: usize) -> ProtocolResult {
+    match int(buf, pos)? {
+        None => Ok(None),
+        Some((pos, total_size)) if total_size >= 0 => {
+            Ok(aggregate(buf, pos, total_size, limits, depth)?
+                .map(|(next, values)| (next, InterimValue::Set(values))))
+        }
+        Some((_, invalid)) => Err(RedisError::InvalidSize(invalid)),
+    }
+}
+
+fn push(buf: &BytesMut, pos: usize, limits: &ProtocolLimits, depth: usize) -> ProtocolResult {
+    match int(buf, pos)? {
+        None => Ok(None),
+        Some((pos, total_size)) if total_size >= 0 => {
+            Ok(aggregate(buf, pos, total_size, limits, depth)?
+                .map(|(next, values)| (next, InterimValue::Push(values))))
+        }
+        Some((_, invalid)) => Err(RedisError::InvalidSize(invalid)),
+    }
+}
+
+#[inline]
+fn is_type_byte(b: u8) -> bool {
+    matches!(
+        b,
+        b'+' | b'-'
+            | b'$'
+            | b':'
+            | b'*'
+            | b'_'
+            | b','
+            | b'#'
+            | b'('
+            | b'!'
+            | b'='
+            | b'%'
+            | b'~'
+            | b'>'
+    )
+}
+
+// Inline commands are plain text lines (e.g. from telnet / netcat) such as `SET a "b c"`
+fn inline(
+    buf: &BytesMut,
+    pos: usize,
+    limits: &ProtocolLimits,
+) -> Result<Option<(usize, Value)>, RedisError> {
+    let Some(end) = memchr::memchr(b'\n', &buf[pos..]) else {
+        if buf.len() - pos > MAX_INLINE_LEN.min(limits.query_buffer_limit) {
+            return Err(RedisError::Protocol("too big inline request".into()));
+        }
+
+        return Ok(None);
+    };
+
+    let mut line = &buf[pos..pos + end];
+    if line.last() == Some(&b'\r') {
+        line = &line[..line.len() - 1];
+    }
+
+    let args = split_args(line)?
+        .into_iter()
+        .map(|arg| Value::String(arg.into()))
+        .collect();
+
+    Ok(Some((pos + end + 1, Value::Array(args))))
+}
+
+fn hex_digit(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+// Splits a line into arguments following the same rules as Redis's `sdssplitargs`
+fn split_args(line: &[u8]) -> Result<Vec<Vec<u8>>, RedisError> {
+    let mut args = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+
+        if i >= line.len() {
+            return Ok(args);
+        }
+
+        let mut current = Vec::new();
+        let mut in_double_quotes = false;
+        let mut in_single_quotes = false;
+
+        loop {
+            let Some(&c) = line.get(i) else {
+                if in_double_quotes || in_single_quotes {
+                    return Err(RedisError::UnbalancedQuotes);
+                }
+
+                break;
+            };
+
+            if in_double_quotes {
+                match c {
+                    b'\\' if i + 3 < line.len() && line[i + 1] == b'x' => {
+                        match (hex_digit(line[i + 2]), hex_digit(line[i + 3])) {
+                            (Some(hi), Some(lo)) => {
+                                current.push(hi * 16 + lo);
+                                i += 3;
+                            }
+                            _ => current.push(c),
+                        }
+                    }
+                    b'\\' if i + 1 < line.len() => {
+                        i += 1;
+                        current.push(match line[i] {
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b'b' => 0x08,
+                            b'a' => 0x07,
+                            other => other,
+                        });
+                    }
+                    b'"' => {
+                        // Closing quote must be followed by a space or nothing at all
+                        if line.get(i + 1).is_some_and(|n| !n.is_ascii_whitespace()) {
+                            return Err(RedisError::UnbalancedQuotes);
+                        }
+
+                        i += 1;
+                        break;
+                    }
+                    _ => current.push(c),
+                }
+            } else if in_single_quotes {
+                match c {
+                    b'\\' if line.get(i + 1) == Some(&b'\'') => {
+                        i += 1;
+                        current.push(b'\'');
                     }
-                    None => return Ok(None),
+                    b'\'' => {
+                        if line.get(i + 1).is_some_and(|n| !n.is_ascii_whitespace()) {
+                            return Err(RedisError::UnbalancedQuotes);
+                        }
+
+                        i += 1;
+                        break;
+                    }
+                    _ => current.push(c),
+                }
+            } else {
+                match c {
+                    b' ' | b'\n' | b'\r' | b'\t' | b'\0' => break,
+                    b'"' => in_double_quotes = true,
+                    b'\'' => in_single_quotes = true,
+                    _ => current.push(c),
                 }
             }
-            Ok(Some((current_idx, InterimValue::Array(values))))
+
+            i += 1;
         }
-        Some((_, invalid)) => Err(RedisError::InvalidSize(invalid)),
+
+        args.push(current);
     }
 }
 
+fn write_aggregate(prefix: &[u8], len: usize, dst: &mut BytesMut) {
+    dst.extend_from_slice(prefix);
+    dst.extend_from_slice(len.to_string().as_bytes());
+    dst.extend_from_slice(b"\r\n");
+}
+
 fn write_value(value: Value, dst: &mut BytesMut) {
     match value {
         Value::SimpleString(ss) => {
@@ -169,9 +508,7 @@ fn write_value(value: Value, dst: &mut BytesMut) {
             dst.extend_from_slice(b"\r\n");
         }
         Value::Array(arr) => {
-            dst.extend_from_slice(b"*");
-            dst.extend_from_slice(arr.len().to_string().as_bytes());
-            dst.extend_from_slice(b"\r\n");
+            write_aggregate(b"*", arr.len(), dst);
             for v in arr {
                 write_value(v, dst);
             }
@@ -190,26 +527,140 @@ fn write_value(value: Value, dst: &mut BytesMut) {
             dst.extend_from_slice(&e);
             dst.extend_from_slice(b"\r\n");
         }
+        Value::Map(pairs) => {
+            write_aggregate(b"%", pairs.len(), dst);
+            for (k, v) in pairs {
+                write_value(k, dst);
+                write_value(v, dst);
+            }
+        }
+        Value::Set(set) => {
+            write_aggregate(b"~", set.len(), dst);
+            for v in set {
+                write_value(v, dst);
+            }
+        }
+        Value::Push(push) => {
+            write_aggregate(b">", push.len(), dst);
+            for v in push {
+                write_value(v, dst);
+            }
+        }
+        Value::Double(d) => {
+            dst.extend_from_slice(b",");
+            dst.extend_from_slice(format_double(d).as_bytes());
+            dst.extend_from_slice(b"\r\n");
+        }
+        Value::Boolean(b) => {
+            dst.extend_from_slice(if b { b"#t\r\n" } else { b"#f\r\n" });
+        }
+        Value::BigNumber(n) => {
+            dst.extend_from_slice(b"(");
+            dst.extend_from_slice(&n);
+            dst.extend_from_slice(b"\r\n");
+        }
+        Value::Verbatim(format, s) => {
+            write_aggregate(b"=", format.len() + 1 + s.len(), dst);
+            dst.extend_from_slice(&format);
+            dst.extend_from_slice(b":");
+            dst.extend_from_slice(&s);
+            dst.extend_from_slice(b"\r\n");
+        }
+        Value::Null => dst.extend_from_slice(b"_\r\n"),
+    }
+}
+
+const MAX_NESTING_DEPTH: usize = 128;
+const MAX_PREALLOCATED_VALUES: usize = 1024;
+const MAX_INLINE_LEN: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ProtocolLimits {
+    pub max_bulk_len: usize,
+    pub max_multibulk_len: usize,
+    pub query_buffer_limit: usize,
+}
+
+impl Default for ProtocolLimits {
+    fn default() -> Self {
+        Self {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_multibulk_len: i32::MAX as usize,
+            query_buffer_limit: 1024 * 1024 * 1024,
+        }
     }
 }
 
-pub struct RespProtocol;
+#[derive(Debug, Default, Clone)]
+pub struct RespProtocol {
+    limits: ProtocolLimits,
+    expect_rdb: bool,
+}
+
+impl RespProtocol {
+    pub fn new(limits: ProtocolLimits) -> Self {
+        Self {
+            limits,
+            expect_rdb: false,
+        }
+    }
+
+    /// Decodes the next frame as the RDB file that follows a FULLRESYNC
+    pub fn expect_rdb(&mut self) {
+        self.expect_rdb = true;
+    }
+}
 
 impl Decoder for RespProtocol {
     type Item = Value;
     type Error = RedisError;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        if src.is_empty() {
-            return Ok(None);
-        }
+        // Loops rather than recursing past blank lines, so a flood of them can't exhaust the stack
+        loop {
+            if src.is_empty() {
+                return Ok(None);
+            }
+
+            let start = src
+                .iter()
+                .position(|b| *b != b'\r' && *b != b'\n')
+                .unwrap_or(src.len());
+
+            if self.expect_rdb {
+                return match rdb(src, &self.limits)? {
+                    Some((pos, Phrase(start, end))) => {
+                        let data = src.split_to(pos).freeze();
+                        self.expect_rdb = false;
+                        Ok(Some(Value::Rdb(data.slice(start..end))))
+                    }
+                    None => Ok(None),
+                };
+            }
 
-        match parse(src, 0)? {
-            Some((pos, value)) => {
-                let data = src.split_to(pos);
-                Ok(Some(value.into_value(&data.freeze())))
+            if start < src.len() && !is_type_byte(src[start]) {
+                let Some((pos, value)) = inline(src, start, &self.limits)? else {
+                    return Ok(None);
+                };
+
+                let _ = src.split_to(pos);
+                match value {
+                    // Empty lines are ignored rather than treated as commands
+                    Value::Array(ref args) if args.is_empty() => continue,
+                    value => return Ok(Some(value)),
+                }
             }
-            None => Ok(None),
+
+            return match parse(src, 0, &self.limits, 0)? {
+                Some((pos, value)) => {
+                    let data = src.split_to(pos);
+                    Ok(Some(value.into_value(&data.freeze())))
+                }
+                None if src.len() > self.limits.query_buffer_limit => {
+                    Err(RedisError::Protocol("query buffer limit exceeded".into()))
+                }
+                None => Ok(None),
+            };
         }
     }
 }
@@ -232,7 +683,7 @@ mod protocol_tests {
     #[tokio::test]
     async fn multi_array_proto_test() {
         let input = b"*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n*1\r\n+PING\r\n";
-        let mut reader = FramedRead::new(&input[..], RespProtocol);
+        let mut reader = FramedRead::new(&input[..], RespProtocol::default());
         let frame1 = reader.next().await;
         let frame2 = reader.next().await;
 
@@ -246,17 +697,198 @@ mod protocol_tests {
     #[tokio::test]
     async fn incomplete_proto_test() {
         let input = b"*2\r\n$4\r\nEC";
-        let mut reader = FramedRead::new(&input[..], RespProtocol);
+        let mut reader = FramedRead::new(&input[..], RespProtocol::default());
         let frame1 = reader.next().await;
         assert!(frame1.is_some());
         assert!(frame1.unwrap().is_err());
+
+        // A frame cut off right after an element boundary is still just incomplete
+        let mut codec = RespProtocol::default();
+        for input in [&b"*2\r\n"[..], b"*2\r\n$4\r\nECHO\r\n"] {
+            let mut buf = BytesMut::from(input);
+            assert!(matches!(codec.decode(&mut buf), Ok(None)));
+        }
+    }
+
+    #[tokio::test]
+    async fn resp3_proto_test() {
+        let input = b"%2\r\n+proto\r\n:3\r\n$4\r\nmode\r\n~2\r\n#t\r\n,1.5\r\n>2\r\n_\r\n=8\r\ntxt:abcd\r\n";
+        let mut reader = FramedRead::new(&input[..], RespProtocol::default());
+        let map = reader.next().await.unwrap().unwrap();
+        assert_eq!(
+            map,
+            Value::Map(vec![
+                (Value::String("proto".into()), Value::Integer(3)),
+                (
+                    Value::String("mode".into()),
+                    Value::Set(vec![Value::Boolean(true), Value::Double(1.5)])
+                ),
+            ])
+        );
+
+        let push = reader.next().await.unwrap().unwrap();
+        assert_eq!(
+            push,
+            Value::Push(vec![
+                Value::Null,
+                Value::Verbatim("txt".into(), "abcd".into())
+            ])
+        );
+    }
+
+    #[test]
+    fn resp3_round_trip_test() {
+        let value = Value::Map(vec![
+            (Value::String("score".into()), Value::Double(f64::INFINITY)),
+            (Value::String("big".into()), Value::BigNumber("1234".into())),
+        ]);
+
+        let mut buf = BytesMut::new();
+        RespProtocol::default()
+            .encode(value.clone(), &mut buf)
+            .unwrap();
+        assert_eq!(
+            &buf[..],
+            b"%2\r\n$5\r\nscore\r\n,inf\r\n$3\r\nbig\r\n(1234\r\n"
+        );
+        assert_eq!(
+            RespProtocol::default().decode(&mut buf).unwrap(),
+            Some(value.clone())
+        );
+
+        assert_eq!(
+            value.into_resp2(),
+            Value::Array(vec![
+                Value::String("score".into()),
+                Value::String("inf".into()),
+                Value::String("big".into()),
+                Value::String("1234".into()),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn inline_proto_test() {
+        let input = b"PING\r\n\r\nSET  key \"hello \\\"world\\\"\\x21\"\n  get 'it''s'\r\n";
+        let mut reader = FramedRead::new(&input[..], RespProtocol::default());
+
+        let ping = reader.next().await.unwrap().unwrap();
+        assert_eq!(ping, Value::Array(vec![Value::String("PING".into())]));
+
+        let set = reader.next().await.unwrap().unwrap();
+        assert_eq!(
+            set,
+            Value::Array(vec![
+                Value::String("SET".into()),
+                Value::String("key".into()),
+                Value::String("hello \"world\"!".into()),
+            ])
+        );
+
+        let get = reader.next().await;
+        assert!(get.unwrap().is_err());
+    }
+
+    #[test]
+    fn blank_inline_lines_test() {
+        let mut src = BytesMut::from(" \r\n".repeat(200_000).as_str());
+        src.extend_from_slice(b"PING\r\n");
+
+        let mut protocol = RespProtocol::default();
+        let ping = protocol.decode(&mut src).unwrap();
+        assert_eq!(ping, Some(Value::Array(vec![Value::String("PING".into())])));
+        assert!(src.is_empty());
+
+        src.extend_from_slice(" \t\r\n".repeat(200_000).as_bytes());
+        assert_eq!(protocol.decode(&mut src).unwrap(), None);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn inline_split_args_test() {
+        assert_eq!(
+            split_args(b"  set a 'b c' \"\\x41\\n\"").unwrap(),
+            vec![
+                b"set".to_vec(),
+                b"a".to_vec(),
+                b"b c".to_vec(),
+                b"A\n".to_vec()
+            ]
+        );
+        assert_eq!(
+            split_args(b"echo 'it\\'s'").unwrap(),
+            vec![b"echo".to_vec(), b"it's".to_vec()]
+        );
+        assert!(split_args(b"echo \"unterminated").is_err());
+        assert!(split_args(b"echo \"a\"b").is_err());
+        assert!(split_args(b"   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn protocol_limits_test() {
+        let mut codec = RespProtocol::new(ProtocolLimits {
+            max_bulk_len: 8,
+            max_multibulk_len: 2,
+            query_buffer_limit: 24,
+        });
+
+        let mut buf = BytesMut::from(&b"*2\r\n$4\r\nECHO\r\n$9\r\n"[..]);
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(RedisError::Protocol(_))
+        ));
+
+        let mut buf = BytesMut::from(&b"*2147483647\r\n"[..]);
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(RedisError::Protocol(_))
+        ));
+
+        let mut buf = BytesMut::from(&b"*2\r\n$4\r\nECHO\r\n$8\r\nabcdefg"[..]);
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(RedisError::Protocol(_))
+        ));
+
+        let mut buf = BytesMut::from(&b"*2\r\n$4\r\nECHO\r\n$8\r\nabcdefgh\r\n"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+    }
+
+    #[test]
+    fn trailing_line_ending_test() {
+        for input in [&b"*1\r\n\n"[..], b"*1\r\n\r", b"*1\r\n\r\n\r"] {
+            let mut buf = BytesMut::from(input);
+            assert_eq!(RespProtocol::default().decode(&mut buf).unwrap(), None);
+        }
+
+        let mut buf = BytesMut::from(&b"*1\r\n\r$4\r\nPING\r\n"[..]);
+        assert_eq!(
+            RespProtocol::default().decode(&mut buf).unwrap(),
+            Some(Value::Array(vec![Value::String("PING".into())]))
+        );
     }
 
     #[tokio::test]
     async fn empty_proto_test() {
         let input = b"";
-        let mut reader = FramedRead::new(&input[..], RespProtocol);
+        let mut reader = FramedRead::new(&input[..], RespProtocol::default());
         let frame = reader.next().await;
         assert!(frame.is_none());
     }
+
+    #[tokio::test]
+    async fn rdb_proto_test() {
+        let mut codec = RespProtocol::default();
+        codec.expect_rdb();
+
+        let mut buf = BytesMut::from(&b"$5\r\nREDIS*1\r\n$4\r\nPING\r\n"[..]);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Value::Rdb("REDIS".into()))
+        );
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Value::Array(vec![Value::String("PING".into())]))
+        );
+    }
 }
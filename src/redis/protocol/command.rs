@@ -0,0 +1,514 @@
+use bytes::Bytes;
+
+use super::{RedisError, Value};
+use crate::redis::utils::bytes_to_str;
+
+use AclCategory as Cat;
+use CommandFlag as Flag;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CommandType {
+    Ping,
+    Echo,
+    Get,
+    Set,
+    RPush,
+    LPush,
+    LRange,
+    RRange,
+    LLen,
+    LPop,
+    BLPop,
+    Type,
+    XAdd,
+    XRange,
+    XRead,
+    Incr,
+    Multi,
+    Exec,
+    Discard,
+    Info,
+    ReplConf,
+    Psync,
+    Wait,
+    Config,
+    Keys,
+    Subscribe,
+    Unsubscribe,
+    Publish,
+    ZAdd,
+    ZRank,
+    ZRange,
+    ZCard,
+    ZScore,
+    ZRem,
+    GeoAdd,
+    GeoPos,
+    GeoDist,
+    GeoSearch,
+    Acl,
+    Auth,
+    Hello,
+    Command,
+    Expire,
+    PExpire,
+    ExpireAt,
+    PExpireAt,
+    Ttl,
+    PTtl,
+    ExpireTime,
+    PExpireTime,
+    Persist,
+    IncrBy,
+    Decr,
+    DecrBy,
+    IncrByFloat,
+    Append,
+    StrLen,
+    GetRange,
+    SetRange,
+    GetDel,
+    GetEx,
+    GetSet,
+    MGet,
+    MSet,
+    MSetNx,
+    SetBit,
+    GetBit,
+    BitCount,
+    BitPos,
+    BitOp,
+    BitField,
+    BitFieldRo,
+    PfAdd,
+    PfCount,
+    PfMerge,
+    Del,
+    Unlink,
+    Exists,
+    Rename,
+    RenameNx,
+    Copy,
+    Touch,
+    RandomKey,
+    Scan,
+    SScan,
+    HScan,
+    ZScan,
+    Select,
+    SwapDb,
+    Move,
+    FlushDb,
+    FlushAll,
+    DbSize,
+    Object,
+    Memory,
+    Sort,
+    SortRo,
+    Dump,
+    Restore,
+}
+
+impl CommandType {
+    pub fn from_bytes(b: &Bytes) -> Result<Self, RedisError> {
+        let name = bytes_to_str(b)?.to_lowercase();
+        match CommandSpec::lookup(&name) {
+            Some(spec) => Ok(spec.cmd),
+            None => Err(RedisError::UnsupportedCommand(name)),
+        }
+    }
+
+    pub fn spec(&self) -> &'static CommandSpec {
+        COMMANDS
+            .iter()
+            .find(|spec| spec.cmd == *self)
+            .expect("every command type has an entry in the command table")
+    }
+}
+
+impl std::fmt::Display for CommandType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.spec().name)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CommandFlag {
+    Write,
+    ReadOnly,
+    DenyOom,
+    Admin,
+    PubSub,
+    NoScript,
+    Blocking,
+    Loading,
+    Stale,
+    Fast,
+    NoAuth,
+    MovableKeys,
+}
+
+impl std::fmt::Display for CommandFlag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Write => write!(f, "write"),
+            Self::ReadOnly => write!(f, "readonly"),
+            Self::DenyOom => write!(f, "denyoom"),
+            Self::Admin => write!(f, "admin"),
+            Self::PubSub => write!(f, "pubsub"),
+            Self::NoScript => write!(f, "noscript"),
+            Self::Blocking => write!(f, "blocking"),
+            Self::Loading => write!(f, "loading"),
+            Self::Stale => write!(f, "stale"),
+            Self::Fast => write!(f, "fast"),
+            Self::NoAuth => write!(f, "no_auth"),
+            Self::MovableKeys => write!(f, "movablekeys"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AclCategory {
+    Keyspace,
+    Read,
+    Write,
+    String,
+    List,
+    Set,
+    Hash,
+    SortedSet,
+    Stream,
+    Geo,
+    Bitmap,
+    HyperLogLog,
+    PubSub,
+    Admin,
+    Fast,
+    Slow,
+    Blocking,
+    Dangerous,
+    Connection,
+    Transaction,
+}
+
+impl std::fmt::Display for AclCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Keyspace => write!(f, "@keyspace"),
+            Self::Read => write!(f, "@read"),
+            Self::Write => write!(f, "@write"),
+            Self::String => write!(f, "@string"),
+            Self::List => write!(f, "@list"),
+            Self::Set => write!(f, "@set"),
+            Self::Hash => write!(f, "@hash"),
+            Self::SortedSet => write!(f, "@sortedset"),
+            Self::Stream => write!(f, "@stream"),
+            Self::Geo => write!(f, "@geo"),
+            Self::Bitmap => write!(f, "@bitmap"),
+            Self::HyperLogLog => write!(f, "@hyperloglog"),
+            Self::PubSub => write!(f, "@pubsub"),
+            Self::Admin => write!(f, "@admin"),
+            Self::Fast => write!(f, "@fast"),
+            Self::Slow => write!(f, "@slow"),
+            Self::Blocking => write!(f, "@blocking"),
+            Self::Dangerous => write!(f, "@dangerous"),
+            Self::Connection => write!(f, "@connection"),
+            Self::Transaction => write!(f, "@transaction"),
+        }
+    }
+}
+
+/// Where the keys of a command are found in its arguments
+#[derive(Debug, Copy, Clone)]
+pub enum KeySpec {
+    None,
+    /// First key, last key and step between keys. Positions are counted with the command
+    /// name at zero and a negative last key counts back from the end of the arguments
+    Range(i64, i64, i64),
+    /// Keys whose position depends on the arguments e.g. `XREAD ... STREAMS k1 k2 id1 id2`
+    Movable(fn(&[Bytes]) -> Vec<usize>),
+}
+
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub cmd: CommandType,
+    pub name: &'static str,
+    /// Positive arity is an exact argument count, negative is a minimum. Both include the name
+    pub arity: i64,
+    pub flags: &'static [CommandFlag],
+    pub keys: KeySpec,
+    pub acl_categories: &'static [AclCategory],
+    pub group: &'static str,
+    pub summary: &'static str,
+}
+
+impl CommandSpec {
+    pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+        COMMANDS
+            .iter()
+            .find(|spec| spec.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn has_flag(&self, flag: CommandFlag) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    /// Checks the number of arguments, excluding the command name, against the arity
+    pub fn arity_matches(&self, args: usize) -> bool {
+        let total = args as i64 + 1;
+        if self.arity >= 0 {
+            total == self.arity
+        } else {
+            total >= -self.arity
+        }
+    }
+
+    /// Indexes into `args` (which excludes the command name) of every key in the command
+    pub fn key_positions(&self, args: &[Bytes]) -> Vec<usize> {
+        match self.keys {
+            KeySpec::None => Vec::new(),
+            KeySpec::Range(first, last, step) => {
+                let total = args.len() as i64 + 1;
+                let last = if last < 0 { total + last } else { last };
+                let mut positions = Vec::new();
+                let mut pos = first;
+                while pos <= last && pos < total {
+                    positions.push(pos as usize - 1);
+                    pos += step;
+                }
+
+                positions
+            }
+            KeySpec::Movable(find) => find(args),
+        }
+    }
+
+    pub fn keys<'a>(&self, args: &'a [Bytes]) -> Vec<&'a Bytes> {
+        self.key_positions(args)
+            .into_iter()
+            .filter_map(|pos| args.get(pos))
+            .collect()
+    }
+
+    /// Reply for COMMAND / COMMAND INFO
+    pub fn info(&self) -> Value {
+        let (first, last, step) = match self.keys {
+            KeySpec::Range(first, last, step) => (first, last, step),
+            KeySpec::None | KeySpec::Movable(_) => (0, 0, 0),
+        };
+
+        let flags = self
+            .flags
+            .iter()
+            .map(|flag| Value::SimpleString(flag.to_string().into()))
+            .collect();
+
+        let categories = self
+            .acl_categories
+            .iter()
+            .map(|cat| Value::SimpleString(cat.to_string().into()))
+            .collect();
+
+        Value::Array(vec![
+            Value::String(self.name.into()),
+            Value::Integer(self.arity),
+            Value::Set(flags),
+            Value::Integer(first),
+            Value::Integer(last),
+            Value::Integer(step),
+            Value::Set(categories),
+            Value::Array(vec![]),
+            Value::Array(vec![]),
+            Value::Array(vec![]),
+        ])
+    }
+
+    /// Reply for COMMAND DOCS
+    pub fn docs(&self) -> Value {
+        Value::Map(vec![
+            (
+                Value::String("summary".into()),
+                Value::String(self.summary.into()),
+            ),
+            (
+                Value::String("group".into()),
+                Value::String(self.group.into()),
+            ),
+        ])
+    }
+}
+
+fn xread_keys(args: &[Bytes]) -> Vec<usize> {
+    let Some(streams) = args
+        .iter()
+        .position(|arg| arg.eq_ignore_ascii_case(b"STREAMS"))
+    else {
+        return Vec::new();
+    };
+
+    let count = (args.len() - streams - 1) / 2;
+    (streams + 1..streams + 1 + count).collect()
+}
+
+macro_rules! command {
+    ($cmd:ident, $name:literal, $arity:expr, [$($flag:ident),*], $keys:expr, [$($cat:ident),*], $group:literal, $summary:literal) => {
+        CommandSpec {
+            cmd: CommandType::$cmd,
+            name: $name,
+            arity: $arity,
+            flags: &[$(Flag::$flag),*],
+            keys: $keys,
+            acl_categories: &[$(Cat::$cat),*],
+            group: $group,
+            summary: $summary,
+        }
+    };
+}
+
+const NO_KEYS: KeySpec = KeySpec::None;
+const FIRST_KEY: KeySpec = KeySpec::Range(1, 1, 1);
+
+#[rustfmt::skip]
+pub static COMMANDS: &[CommandSpec] = &[
+    command!(Ping, "ping", -1, [Fast], NO_KEYS, [Fast, Connection], "connection", "Returns the server's liveliness response."),
+    command!(Echo, "echo", 2, [Fast], NO_KEYS, [Fast, Connection], "connection", "Returns the given string."),
+    command!(Get, "get", 2, [ReadOnly, Fast], FIRST_KEY, [Read, String, Fast], "string", "Returns the string value of a key."),
+    command!(Set, "set", -3, [Write, DenyOom], FIRST_KEY, [Write, String, Slow], "string", "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist."),
+    command!(RPush, "rpush", -3, [Write, DenyOom, Fast], FIRST_KEY, [Write, List, Fast], "list", "Appends one or more elements to a list. Creates the key if it doesn't exist."),
+    command!(LPush, "lpush", -3, [Write, DenyOom, Fast], FIRST_KEY, [Write, List, Fast], "list", "Prepends one or more elements to a list. Creates the key if it doesn't exist."),
+    command!(LRange, "lrange", 4, [ReadOnly], FIRST_KEY, [Read, List, Slow], "list", "Returns a range of elements from a list."),
+    command!(RRange, "rrange", 4, [ReadOnly], FIRST_KEY, [Read, List, Slow], "list", "Returns a range of elements from a list."),
+    command!(LLen, "llen", 2, [ReadOnly, Fast], FIRST_KEY, [Read, List, Fast], "list", "Returns the length of a list."),
+    command!(LPop, "lpop", -2, [Write, Fast], FIRST_KEY, [Write, List, Fast], "list", "Returns the first elements in a list after removing it. Deletes the list if the last element was popped."),
+    command!(BLPop, "blpop", -3, [Write, Blocking], KeySpec::Range(1, -2, 1), [Write, List, Slow, Blocking], "list", "Removes and returns the first element in a list. Blocks until an element is available otherwise."),
+    command!(Type, "type", 2, [ReadOnly, Fast], FIRST_KEY, [Keyspace, Read, Fast], "generic", "Determines the type of value stored at a key."),
+    command!(XAdd, "xadd", -5, [Write, DenyOom, Fast], FIRST_KEY, [Write, Stream, Fast], "stream", "Appends a new message to a stream. Creates the key if it doesn't exist."),
+    command!(XRange, "xrange", -4, [ReadOnly], FIRST_KEY, [Read, Stream, Slow], "stream", "Returns the messages from a stream within a range of IDs."),
+    command!(XRead, "xread", -4, [ReadOnly, Blocking, MovableKeys], KeySpec::Movable(xread_keys), [Read, Stream, Slow, Blocking], "stream", "Returns messages from multiple streams with IDs greater than the ones requested. Blocks until a message is available otherwise."),
+    command!(Incr, "incr", 2, [Write, DenyOom, Fast], FIRST_KEY, [Write, String, Fast], "string", "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist."),
+    command!(Multi, "multi", 1, [NoScript, Loading, Stale, Fast], NO_KEYS, [Fast, Transaction], "transactions", "Starts a transaction."),
+    command!(Exec, "exec", 1, [NoScript, Loading, Stale], NO_KEYS, [Slow, Transaction], "transactions", "Executes all commands in a transaction."),
+    command!(Discard, "discard", 1, [NoScript, Loading, Stale, Fast], NO_KEYS, [Fast, Transaction], "transactions", "Discards a transaction."),
+    command!(Info, "info", -1, [Loading, Stale], NO_KEYS, [Slow, Dangerous], "server", "Returns information and statistics about the server."),
+    command!(ReplConf, "replconf", -1, [Admin, NoScript, Loading, Stale], NO_KEYS, [Admin, Slow, Dangerous], "server", "An internal command for configuring the replication stream."),
+    command!(Psync, "psync", -3, [Admin, NoScript], NO_KEYS, [Admin, Slow, Dangerous], "server", "An internal command used in replication."),
+    command!(Wait, "wait", 3, [NoScript], NO_KEYS, [Slow, Connection], "generic", "Blocks until the asynchronous replication of all preceding write commands sent by the connection is completed."),
+    command!(Config, "config", -2, [Admin, NoScript, Loading, Stale], NO_KEYS, [Admin, Slow, Dangerous], "server", "A container for server configuration commands."),
+    command!(Keys, "keys", 2, [ReadOnly], NO_KEYS, [Keyspace, Read, Slow, Dangerous], "generic", "Returns all key names that match a pattern."),
+    command!(Subscribe, "subscribe", -2, [PubSub, NoScript, Loading, Stale], NO_KEYS, [PubSub, Slow], "pubsub", "Listens for messages published to channels."),
+    command!(Unsubscribe, "unsubscribe", -1, [PubSub, NoScript, Loading, Stale], NO_KEYS, [PubSub, Slow], "pubsub", "Stops listening to messages posted to channels."),
+    command!(Publish, "publish", 3, [PubSub, Loading, Stale, Fast], NO_KEYS, [PubSub, Fast], "pubsub", "Posts a message to a channel."),
+    command!(ZAdd, "zadd", -4, [Write, DenyOom, Fast], FIRST_KEY, [Write, SortedSet, Fast], "sorted-set", "Adds one or more members to a sorted set, or updates their scores. Creates the key if it doesn't exist."),
+    command!(ZRank, "zrank", -3, [ReadOnly, Fast], FIRST_KEY, [Read, SortedSet, Fast], "sorted-set", "Returns the index of a member in a sorted set ordered by ascending scores."),
+    command!(ZRange, "zrange", -4, [ReadOnly], FIRST_KEY, [Read, SortedSet, Slow], "sorted-set", "Returns members in a sorted set within a range of indexes."),
+    command!(ZCard, "zcard", 2, [ReadOnly, Fast], FIRST_KEY, [Read, SortedSet, Fast], "sorted-set", "Returns the number of members in a sorted set."),
+    command!(ZScore, "zscore", 3, [ReadOnly, Fast], FIRST_KEY, [Read, SortedSet, Fast], "sorted-set", "Returns the score of a member in a sorted set."),
+    command!(ZRem, "zrem", -3, [Write, Fast], FIRST_KEY, [Write, SortedSet, Fast], "sorted-set", "Removes one or more members from a sorted set. Deletes the sorted set if all members were removed."),
+    command!(GeoAdd, "geoadd", -5, [Write, DenyOom], FIRST_KEY, [Write, Geo, Slow], "geo", "Adds one or more members to a geospatial index. The key is created if it doesn't exist."),
+    command!(GeoPos, "geopos", -2, [ReadOnly], FIRST_KEY, [Read, Geo, Slow], "geo", "Returns the longitude and latitude of members from a geospatial index."),
+    command!(GeoDist, "geodist", -4, [ReadOnly], FIRST_KEY, [Read, Geo, Slow], "geo", "Returns the distance between two members of a geospatial index."),
+    command!(GeoSearch, "geosearch", -7, [ReadOnly], FIRST_KEY, [Read, Geo, Slow], "geo", "Queries a geospatial index for members inside an area of a box or a circle."),
+    command!(Acl, "acl", -2, [Admin, NoScript, Loading, Stale], NO_KEYS, [Admin, Slow, Dangerous], "server", "A container for Access List Control commands."),
+    command!(Auth, "auth", -2, [NoScript, Loading, Stale, Fast, NoAuth], NO_KEYS, [Fast, Connection], "connection", "Authenticates the connection."),
+    command!(Hello, "hello", -1, [NoScript, Loading, Stale, Fast, NoAuth], NO_KEYS, [Fast, Connection], "connection", "Handshakes with the Redis server."),
+    command!(Command, "command", -1, [Loading, Stale], NO_KEYS, [Slow, Connection], "server", "Returns detailed information about all commands."),
+    command!(Expire, "expire", -3, [Write, Fast], FIRST_KEY, [Keyspace, Write, Fast], "generic", "Sets the expiration time of a key in seconds."),
+    command!(PExpire, "pexpire", -3, [Write, Fast], FIRST_KEY, [Keyspace, Write, Fast], "generic", "Sets the expiration time of a key in milliseconds."),
+    command!(ExpireAt, "expireat", -3, [Write, Fast], FIRST_KEY, [Keyspace, Write, Fast], "generic", "Sets the expiration time of a key to a Unix timestamp."),
+    command!(PExpireAt, "pexpireat", -3, [Write, Fast], FIRST_KEY, [Keyspace, Write, Fast], "generic", "Sets the expiration time of a key to a Unix milliseconds timestamp."),
+    command!(Ttl, "ttl", 2, [ReadOnly, Fast], FIRST_KEY, [Keyspace, Read, Fast], "generic", "Returns the expiration time in seconds of a key."),
+    command!(PTtl, "pttl", 2, [ReadOnly, Fast], FIRST_KEY, [Keyspace, Read, Fast], "generic", "Returns the expiration time in milliseconds of a key."),
+    command!(ExpireTime, "expiretime", 2, [ReadOnly, Fast], FIRST_KEY, [Keyspace, Read, Fast], "generic", "Returns the expiration time of a key as a Unix timestamp."),
+    command!(PExpireTime, "pexpiretime", 2, [ReadOnly, Fast], FIRST_KEY, [Keyspace, Read, Fast], "generic", "Returns the expiration time of a key as a Unix milliseconds timestamp."),
+    command!(Persist, "persist", 2, [Write, Fast], FIRST_KEY, [Keyspace, Write, Fast], "generic", "Removes the expiration time of a key."),
+    command!(IncrBy, "incrby", 3, [Write, DenyOom, Fast], FIRST_KEY, [Write, String, Fast], "string", "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist."),
+    command!(Decr, "decr", 2, [Write, DenyOom, Fast], FIRST_KEY, [Write, String, Fast], "string", "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist."),
+    command!(DecrBy, "decrby", 3, [Write, DenyOom, Fast], FIRST_KEY, [Write, String, Fast], "string", "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist."),
+    command!(IncrByFloat, "incrbyfloat", 3, [Write, DenyOom, Fast], FIRST_KEY, [Write, String, Fast], "string", "Increment the floating point value of a key by a number. Uses 0 as initial value if the key doesn't exist."),
+    command!(Append, "append", 3, [Write, DenyOom, Fast], FIRST_KEY, [Write, String, Fast], "string", "Appends a string to the value of a key. Creates the key if it doesn't exist."),
+    command!(StrLen, "strlen", 2, [ReadOnly, Fast], FIRST_KEY, [Read, String, Fast], "string", "Returns the length of a string value."),
+    command!(GetRange, "getrange", 4, [ReadOnly], FIRST_KEY, [Read, String, Slow], "string", "Returns a substring of the string stored at a key."),
+    command!(SetRange, "setrange", 4, [Write, DenyOom], FIRST_KEY, [Write, String, Slow], "string", "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist."),
+    command!(GetDel, "getdel", 2, [Write, Fast], FIRST_KEY, [Write, String, Fast], "string", "Returns the string value of a key after deleting the key."),
+    command!(GetEx, "getex", -2, [Write, Fast], FIRST_KEY, [Write, String, Fast], "string", "Returns the string value of a key after setting its expiration time."),
+    command!(GetSet, "getset", 3, [Write, DenyOom, Fast], FIRST_KEY, [Write, String, Fast], "string", "Returns the previous string value of a key after setting it to a new value."),
+    command!(MGet, "mget", -2, [ReadOnly, Fast], KeySpec::Range(1, -1, 1), [Read, String, Fast], "string", "Atomically returns the string values of one or more keys."),
+    command!(MSet, "mset", -3, [Write, DenyOom], KeySpec::Range(1, -1, 2), [Write, String, Slow], "string", "Atomically creates or modifies the string values of one or more keys."),
+    command!(MSetNx, "msetnx", -3, [Write, DenyOom], KeySpec::Range(1, -1, 2), [Write, String, Slow], "string", "Atomically modifies the string values of one or more keys only when all keys don't exist."),
+    command!(SetBit, "setbit", 4, [Write, DenyOom], FIRST_KEY, [Write, Bitmap, Slow], "bitmap", "Sets or clears the bit at offset of the string value. Creates the key if it doesn't exist."),
+    command!(GetBit, "getbit", 3, [ReadOnly, Fast], FIRST_KEY, [Read, Bitmap, Fast], "bitmap", "Returns a bit value by offset."),
+    command!(BitCount, "bitcount", -2, [ReadOnly], FIRST_KEY, [Read, Bitmap, Slow], "bitmap", "Counts the number of set bits (population counting) in a string."),
+    command!(BitPos, "bitpos", -3, [ReadOnly], FIRST_KEY, [Read, Bitmap, Slow], "bitmap", "Finds the first set (1) or clear (0) bit in a string."),
+    command!(BitOp, "bitop", -4, [Write, DenyOom], KeySpec::Range(2, -1, 1), [Write, Bitmap, Slow], "bitmap", "Performs bitwise operations on multiple strings, and stores the result."),
+    command!(BitField, "bitfield", -2, [Write, DenyOom], FIRST_KEY, [Write, Bitmap, Slow], "bitmap", "Performs arbitrary bitfield integer operations on strings."),
+    command!(BitFieldRo, "bitfield_ro", -2, [ReadOnly, Fast], FIRST_KEY, [Read, Bitmap, Fast], "bitmap", "Performs arbitrary read-only bitfield integer operations on strings."),
+    command!(PfAdd, "pfadd", -2, [Write, DenyOom, Fast], FIRST_KEY, [Write, HyperLogLog, Fast], "hyperloglog", "Adds elements to a HyperLogLog key. Creates the key if it doesn't exist."),
+    command!(PfCount, "pfcount", -2, [ReadOnly], KeySpec::Range(1, -1, 1), [Read, HyperLogLog, Slow], "hyperloglog", "Returns the approximated cardinality of the set(s) observed by the HyperLogLog key(s)."),
+    command!(PfMerge, "pfmerge", -2, [Write, DenyOom], KeySpec::Range(1, -1, 1), [Write, HyperLogLog, Slow], "hyperloglog", "Merges one or more HyperLogLog values into a single key."),
+    command!(Del, "del", -2, [Write], KeySpec::Range(1, -1, 1), [Keyspace, Write, Slow], "generic", "Deletes one or more keys."),
+    command!(Unlink, "unlink", -2, [Write, Fast], KeySpec::Range(1, -1, 1), [Keyspace, Write, Fast], "generic", "Asynchronously deletes one or more keys."),
+    command!(Exists, "exists", -2, [ReadOnly, Fast], KeySpec::Range(1, -1, 1), [Keyspace, Read, Fast], "generic", "Determines whether one or more keys exist."),
+    command!(Rename, "rename", 3, [Write], KeySpec::Range(1, 2, 1), [Keyspace, Write, Slow], "generic", "Renames a key and overwrites the destination."),
+    command!(RenameNx, "renamenx", 3, [Write, Fast], KeySpec::Range(1, 2, 1), [Keyspace, Write, Fast], "generic", "Renames a key only when the target key name doesn't exist."),
+    command!(Copy, "copy", -3, [Write, DenyOom], KeySpec::Range(1, 2, 1), [Keyspace, Write, Slow], "generic", "Copies the value of a key to a new key."),
+    command!(Touch, "touch", -2, [ReadOnly, Fast], KeySpec::Range(1, -1, 1), [Keyspace, Read, Fast], "generic", "Returns the number of existing keys out of those specified after updating the time they were last accessed."),
+    command!(RandomKey, "randomkey", 1, [ReadOnly], NO_KEYS, [Keyspace, Read, Slow], "generic", "Returns a random key name from the database."),
+    command!(Scan, "scan", -2, [ReadOnly], NO_KEYS, [Keyspace, Read, Slow], "generic", "Iterates over the key names in the database."),
+    command!(SScan, "sscan", -3, [ReadOnly], FIRST_KEY, [Read, Set, Slow], "set", "Iterates over members of a set."),
+    command!(HScan, "hscan", -3, [ReadOnly], FIRST_KEY, [Read, Hash, Slow], "hash", "Iterates over fields and values of a hash."),
+    command!(ZScan, "zscan", -3, [ReadOnly], FIRST_KEY, [Read, SortedSet, Slow], "sorted-set", "Iterates over members and scores of a sorted set."),
+    command!(Select, "select", 2, [Loading, Stale, Fast], NO_KEYS, [Keyspace, Fast], "connection", "Changes the selected database."),
+    command!(SwapDb, "swapdb", 3, [Write, Fast], NO_KEYS, [Keyspace, Write, Fast, Dangerous], "server", "Swaps two Redis databases."),
+    command!(Move, "move", 3, [Write, Fast], FIRST_KEY, [Keyspace, Write, Fast], "generic", "Moves a key to another database."),
+    command!(FlushDb, "flushdb", -1, [Write], NO_KEYS, [Keyspace, Write, Slow, Dangerous], "server", "Removes all keys from the current database."),
+    command!(FlushAll, "flushall", -1, [Write], NO_KEYS, [Keyspace, Write, Slow, Dangerous], "server", "Removes all keys from all databases."),
+    command!(DbSize, "dbsize", 1, [ReadOnly, Fast], NO_KEYS, [Keyspace, Read, Fast], "server", "Returns the number of keys in the database."),
+    command!(Object, "object", -2, [ReadOnly], NO_KEYS, [Keyspace, Read, Slow], "generic", "A container for object introspection commands."),
+    command!(Memory, "memory", -2, [ReadOnly], NO_KEYS, [Read, Slow], "server", "A container for memory diagnostics commands."),
+    command!(Sort, "sort", -2, [Write, DenyOom], FIRST_KEY, [Write, Set, SortedSet, List, Slow, Dangerous], "generic", "Sorts the elements in a list, a set, or a sorted set, optionally storing the result."),
+    command!(SortRo, "sort_ro", -2, [ReadOnly], FIRST_KEY, [Read, Set, SortedSet, List, Slow, Dangerous], "generic", "Returns the sorted elements of a list, a set, or a sorted set."),
+    command!(Dump, "dump", 2, [ReadOnly], FIRST_KEY, [Keyspace, Read, Slow], "generic", "Returns a serialized representation of the value stored at a key."),
+    command!(Restore, "restore", -4, [Write, DenyOom], FIRST_KEY, [Keyspace, Write, Slow, Dangerous], "generic", "Creates a key from the serialized representation of a value."),
+];
+
+#[cfg(test)]
+mod command_tests {
+    use super::*;
+
+    #[test]
+    fn table_lookup() {
+        assert_eq!(
+            CommandType::from_bytes(&"GeT".into()).unwrap(),
+            CommandType::Get
+        );
+        assert!(CommandType::from_bytes(&"nope".into()).is_err());
+
+        for spec in COMMANDS {
+            assert_eq!(spec.cmd.spec().name, spec.name);
+        }
+    }
+
+    #[test]
+    fn key_positions() {
+        let args: Vec<Bytes> = vec!["a".into(), "b".into(), "0".into()];
+        assert_eq!(
+            CommandType::BLPop.spec().keys(&args),
+            vec![&args[0], &args[1]]
+        );
+
+        let args: Vec<Bytes> = vec![
+            "COUNT".into(),
+            "2".into(),
+            "streams".into(),
+            "s1".into(),
+            "s2".into(),
+            "0".into(),
+            "0".into(),
+        ];
+        assert_eq!(
+            CommandType::XRead.spec().keys(&args),
+            vec![&args[3], &args[4]]
+        );
+        assert!(CommandType::Ping.spec().keys(&args).is_empty());
+    }
+}
@@ -2,144 +2,31 @@ use bytes::Bytes;
 use thiserror::Error;
 
 mod codec;
+mod command;
 use super::utils::bytes_to_str;
 pub use codec::*;
+pub use command::*;
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub enum CommandType {
-    Ping,
-    Echo,
-    Get,
-    Set,
-    RPush,
-    LPush,
-    LRange,
-    RRange,
-    LLen,
-    LPop,
-    BLPop,
-    Type,
-    XAdd,
-    XRange,
-    XRead,
-    Incr,
-    Multi,
-    Exec,
-    Discard,
-    Info,
-    ReplConf,
-    Psync,
-    Wait,
-    Config,
-    Keys,
-    Subscribe,
-    Unsubscribe,
-    Publish,
-    ZAdd,
-    ZRank,
-    ZRange,
-    ZCard,
-    ZScore,
-    ZRem,
-    GeoAdd,
-    GeoPos,
-    GeoDist,
-    GeoSearch,
-    Acl,
-    Auth,
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum ProtocolVersion {
+    #[default]
+    Resp2,
+    Resp3,
 }
 
-impl CommandType {
+impl ProtocolVersion {
     pub fn from_bytes(b: &Bytes) -> Result<Self, RedisError> {
-        match bytes_to_str(b)?.to_lowercase().as_str() {
-            "ping" => Ok(Self::Ping),
-            "echo" => Ok(Self::Echo),
-            "get" => Ok(Self::Get),
-            "set" => Ok(Self::Set),
-            "rpush" => Ok(Self::RPush),
-            "lpush" => Ok(Self::LPush),
-            "lrange" => Ok(Self::LRange),
-            "rrange" => Ok(Self::RRange),
-            "llen" => Ok(Self::LLen),
-            "lpop" => Ok(Self::LPop),
-            "blpop" => Ok(Self::BLPop),
-            "type" => Ok(Self::Type),
-            "xadd" => Ok(Self::XAdd),
-            "xrange" => Ok(Self::XRange),
-            "xread" => Ok(Self::XRead),
-            "incr" => Ok(Self::Incr),
-            "multi" => Ok(Self::Multi),
-            "exec" => Ok(Self::Exec),
-            "discard" => Ok(Self::Discard),
-            "info" => Ok(Self::Info),
-            "replconf" => Ok(Self::ReplConf),
-            "psync" => Ok(Self::Psync),
-            "wait" => Ok(Self::Wait),
-            "config" => Ok(Self::Config),
-            "keys" => Ok(Self::Keys),
-            "subscribe" => Ok(Self::Subscribe),
-            "unsubscribe" => Ok(Self::Unsubscribe),
-            "publish" => Ok(Self::Publish),
-            "zadd" => Ok(Self::ZAdd),
-            "zrank" => Ok(Self::ZRank),
-            "zrange" => Ok(Self::ZRange),
-            "zcard" => Ok(Self::ZCard),
-            "zscore" => Ok(Self::ZScore),
-            "zrem" => Ok(Self::ZRem),
-            "geoadd" => Ok(Self::GeoAdd),
-            "geopos" => Ok(Self::GeoPos),
-            "geodist" => Ok(Self::GeoDist),
-            "geosearch" => Ok(Self::GeoSearch),
-            "acl" => Ok(Self::Acl),
-            "auth" => Ok(Self::Auth),
-            cmd => Err(RedisError::UnsupportedCommand(cmd.to_string())),
+        match bytes_to_str(b)? {
+            "2" => Ok(Self::Resp2),
+            "3" => Ok(Self::Resp3),
+            _ => Err(RedisError::UnsupportedProtocol),
         }
     }
-}
 
-impl std::fmt::Display for CommandType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    pub fn version(&self) -> i64 {
         match self {
-            Self::Ping => write!(f, "ping"),
-            Self::Echo => write!(f, "echo"),
-            Self::Set => write!(f, "set"),
-            Self::Get => write!(f, "get"),
-            Self::RPush => write!(f, "rpush"),
-            Self::LPush => write!(f, "lpush"),
-            Self::LRange => write!(f, "lrange"),
-            Self::RRange => write!(f, "rrange"),
-            Self::LLen => write!(f, "llen"),
-            Self::LPop => write!(f, "lpop"),
-            Self::BLPop => write!(f, "blpop"),
-            Self::Type => write!(f, "type"),
-            Self::XAdd => write!(f, "xadd"),
-            Self::XRange => write!(f, "xrange"),
-            Self::XRead => write!(f, "xread"),
-            Self::Incr => write!(f, "incr"),
-            Self::Multi => write!(f, "multi"),
-            Self::Exec => write!(f, "exec"),
-            Self::Discard => write!(f, "discard"),
-            Self::Info => write!(f, "info"),
-            Self::ReplConf => write!(f, "replconf"),
-            Self::Psync => write!(f, "psync"),
-            Self::Wait => write!(f, "wait"),
-            Self::Config => write!(f, "config"),
-            Self::Keys => write!(f, "keys"),
-            Self::Subscribe => write!(f, "subscribe"),
-            Self::Unsubscribe => write!(f, "unsubscribe"),
-            Self::Publish => write!(f, "publish"),
-            Self::ZAdd => write!(f, "zadd"),
-            Self::ZRank => write!(f, "zrank"),
-            Self::ZRange => write!(f, "zrange"),
-            Self::ZCard => write!(f, "zcard"),
-            Self::ZScore => write!(f, "zscore"),
-            Self::ZRem => write!(f, "zrem"),
-            Self::GeoAdd => write!(f, "geoadd"),
-            Self::GeoPos => write!(f, "geopos"),
-            Self::GeoDist => write!(f, "geodist"),
-            Self::GeoSearch => write!(f, "geosearch"),
-            Self::Acl => write!(f, "acl"),
-            Self::Auth => write!(f, "auth"),
+            Self::Resp2 => 2,
+            Self::Resp3 => 3,
         }
     }
 }
@@ -155,6 +42,16 @@ pub enum Value {
     NullArray,
     EmptyArray,
     Rdb(Bytes),
+
+    // RESP3 only types, downgraded with `Value::into_resp2` for older clients
+    Map(Vec<(Value, Value)>),
+    Set(Vec<Value>),
+    Double(f64),
+    Boolean(bool),
+    BigNumber(Bytes),
+    Verbatim(Bytes, Bytes),
+    Null,
+    Push(Vec<Value>),
 }
 
 impl Value {
@@ -162,21 +59,33 @@ impl Value {
         match self {
             Self::String(inner) => {
                 let repr = format!("${}\r\n\r\n", inner.len());
-                return repr.len() + inner.len();
+                repr.len() + inner.len()
             }
-            Self::SimpleString(inner) | Self::Error(inner) => {
-                return "+\r\n".len() + inner.len();
+            Self::SimpleString(inner) | Self::Error(inner) | Self::BigNumber(inner) => {
+                "+\r\n".len() + inner.len()
             }
-            Self::NullString | Self::NullArray => return "$-1\r\n".len(),
-            Self::EmptyArray => return "*0\r\n".len(),
-            Self::Integer(inner) => {
-                return format!(":{}\r\n", inner).len();
-            }
-            Self::Array(inner) => {
+            Self::NullString | Self::NullArray => "$-1\r\n".len(),
+            Self::EmptyArray => "*0\r\n".len(),
+            Self::Integer(inner) => format!(":{}\r\n", inner).len(),
+            Self::Array(inner) | Self::Set(inner) | Self::Push(inner) => {
                 let count = inner.iter().map(|s| s.size()).sum::<usize>();
-                return format!("*{}\r\n", inner.len()).len() + count;
+                format!("*{}\r\n", inner.len()).len() + count
+            }
+            Self::Rdb(inner) => format!("${}\r\n", inner.len()).len(),
+            Self::Map(inner) => {
+                let count = inner
+                    .iter()
+                    .map(|(k, v)| k.size() + v.size())
+                    .sum::<usize>();
+                format!("%{}\r\n", inner.len()).len() + count
             }
-            Self::Rdb(inner) => return format!("${}\r\n", inner.len()).len(),
+            Self::Double(inner) => format!(",{}\r\n", format_double(*inner)).len(),
+            Self::Boolean(_) => "#t\r\n".len(),
+            Self::Verbatim(format, inner) => {
+                let len = format.len() + 1 + inner.len();
+                format!("={}\r\n\r\n", len).len() + len
+            }
+            Self::Null => "_\r\n".len(),
         }
     }
 
@@ -187,6 +96,51 @@ impl Value {
     pub fn error(msg: Bytes) -> Self {
         Value::Error(msg)
     }
+
+    pub fn double(value: f64) -> Self {
+        Value::Double(value)
+    }
+
+    /// Converts any RESP3 only types into their closest RESP2 representation
+    pub fn into_resp2(self) -> Self {
+        match self {
+            Self::Map(pairs) => Self::Array(
+                pairs
+                    .into_iter()
+                    .flat_map(|(k, v)| [k.into_resp2(), v.into_resp2()])
+                    .collect(),
+            ),
+            Self::Set(inner) | Self::Push(inner) | Self::Array(inner) => {
+                Self::Array(inner.into_iter().map(Value::into_resp2).collect())
+            }
+            Self::Double(inner) => Self::String(format_double(inner).into()),
+            Self::Boolean(inner) => Self::Integer(inner as i64),
+            Self::BigNumber(inner) | Self::Verbatim(_, inner) => Self::String(inner),
+            Self::Null => Self::NullString,
+            other => other,
+        }
+    }
+
+    pub fn into_protocol(self, protocol: ProtocolVersion) -> Self {
+        match protocol {
+            ProtocolVersion::Resp2 => self.into_resp2(),
+            ProtocolVersion::Resp3 => self,
+        }
+    }
+}
+
+pub fn format_double(value: f64) -> String {
+    if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        if value.is_sign_positive() {
+            "inf".to_string()
+        } else {
+            "-inf".to_string()
+        }
+    } else {
+        format!("{value}")
+    }
 }
 
 #[derive(Debug, Default)]
@@ -216,27 +170,26 @@ impl RedisCommand {
         let Value::Array(args) = inc_cmd else {
             return Err(RedisError::UnexpectedValue);
         };
-        assert!(!args.is_empty());
 
         let Some(Value::String(cmd_bytes)) = args.first() else {
             return Err(RedisError::UnexpectedValue);
         };
 
         let cmd_type = CommandType::from_bytes(cmd_bytes)?;
-        let args = if args.len() > 1 {
-            args[1..]
-                .iter()
-                .map(|v| {
-                    let Value::String(inner) = v else {
-                        todo!();
-                    };
-
-                    inner.clone()
-                })
-                .collect()
-        } else {
-            Vec::new()
-        };
+        if !cmd_type.spec().arity_matches(args.len() - 1) {
+            return Err(RedisError::InsufficientArugments(cmd_type));
+        }
+
+        // Arguments are copied out of the read buffer. A stored value that's a slice of it
+        // would keep the whole buffer alive, and deleting the key wouldn't free the memory
+        let args = args[1..]
+            .iter()
+            .map(|v| match v {
+                Value::String(inner) => Ok(Bytes::copy_from_slice(inner)),
+                Value::Integer(int) => Ok(int.to_string().into()),
+                _ => Err(RedisError::Protocol("expected bulk string argument".into())),
+            })
+            .collect::<Result<Vec<Bytes>, RedisError>>()?;
 
         Ok(Self {
             cmd: cmd_type,
@@ -252,19 +205,19 @@ impl RedisCommand {
 
 #[derive(Debug, Error)]
 pub enum RedisError {
-    #[error("failed to parse number")]
+    #[error("value is not an integer or out of range")]
     NumberParse,
 
-    #[error("invalid size parameter detected {0}")]
+    #[error("Protocol error: invalid size {0}")]
     InvalidSize(i64),
 
-    #[error("invalid protocol byte - '{0}'")]
+    #[error("Protocol error: invalid protocol byte '{0}'")]
     InvalidProtocolByte(char),
 
     #[error("io error")]
     IOError(#[from] std::io::Error),
 
-    #[error("unexpected value")]
+    #[error("Protocol error: unexpected value")]
     UnexpectedValue,
 
     #[error("unable to convert from bytes to str")]
@@ -277,27 +230,97 @@ pub enum RedisError {
     #[error("error receiving response from channel - {0}")]
     ChannelRecvError(String),
 
-    #[error("unsupported command - '{0}'")]
+    #[error("unknown command '{0}'")]
     UnsupportedCommand(String),
 
-    #[error("insufficient arguments for '{0}'")]
+    #[error("wrong number of arguments for '{0}' command")]
     InsufficientArugments(CommandType),
 
     #[error("file read error - '{0}'")]
     FileRead(String),
 
-    #[error("read lock error occurred")]
-    ReadLock,
-
-    #[error("write lock error occurred")]
-    WriteLock,
-
     #[error("redis parse error - '{0}'")]
     RdbParse(String),
 
-    #[error("stream error - '{0}'")]
+    #[error("{0}")]
     StreamIdError(String),
 
     #[error("hex error - '{0}'")]
     HexError(String),
+
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+
+    #[error("Protocol error: unbalanced quotes in request")]
+    UnbalancedQuotes,
+
+    #[error("unsupported protocol version")]
+    UnsupportedProtocol,
+
+    #[error("syntax error")]
+    Syntax,
+
+    #[error("value is not a valid float")]
+    FloatParse,
+
+    #[error("increment or decrement would overflow")]
+    Overflow,
+
+    #[error("Operation against a key holding the wrong kind of value")]
+    WrongType,
+
+    #[error("DB index is out of range")]
+    DbOutOfRange,
+
+    #[error("source and destination objects are the same")]
+    SameObject,
+
+    #[error("no such key")]
+    NoSuchKey,
+
+    #[error("command not allowed when used memory > 'maxmemory'.")]
+    OutOfMemory,
+
+    #[error("Target key name already exists.")]
+    BusyKey,
+
+    #[error("Key is not a valid HyperLogLog string value.")]
+    InvalidHll,
+
+    #[error("Corrupted HLL object detected")]
+    CorruptHll,
+
+    #[error("Authentication required.")]
+    NoAuth,
+
+    #[error("invalid username-password pair or user is disabled")]
+    WrongPass,
+
+    #[error("{0}")]
+    Custom(String),
+
+    #[error("internal error while executing '{0}'")]
+    Internal(String),
+}
+
+impl RedisError {
+    /// Error code sent as the first word of an error reply, e.g. `-WRONGTYPE ...`
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::WrongType | Self::InvalidHll => "WRONGTYPE",
+            Self::CorruptHll => "INVALIDOBJ",
+            Self::NoAuth => "NOAUTH",
+            Self::WrongPass => "WRONGPASS",
+            Self::UnsupportedProtocol => "NOPROTO",
+            Self::OutOfMemory => "OOM",
+            Self::BusyKey => "BUSYKEY",
+            _ => "ERR",
+        }
+    }
+}
+
+impl From<RedisError> for Value {
+    fn from(err: RedisError) -> Self {
+        Value::Error(format!("{} {err}", err.code()).into())
+    }
 }
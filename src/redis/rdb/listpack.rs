@@ -0,0 +1,278 @@
+// Listpacks and intsets, the compact encodings Redis saves small collections and stream
+// nodes in. A listpack is a header with its size in bytes and number of elements, then
+// each element followed by its own length so it can be walked backwards, and 0xFF.
+// Strings that are canonical integers are always stored as integers
+
+use bytes::Bytes;
+
+const HEADER_SIZE: usize = 6;
+const END: u8 = 0xFF;
+/// Element count in the header once there are too many to count there
+const UNKNOWN_COUNT: usize = u16::MAX as usize;
+
+pub fn as_integer(value: &[u8]) -> Option<i64> {
+    let number: i64 = std::str::from_utf8(value).ok()?.parse().ok()?;
+    (number.to_string().as_bytes() == value).then_some(number)
+}
+
+#[derive(Debug, Default)]
+pub struct Listpack {
+    elements: Vec<u8>,
+    count: usize,
+}
+
+impl Listpack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes the listpack takes, header and terminator included
+    pub fn size(&self) -> usize {
+        HEADER_SIZE + self.elements.len() + 1
+    }
+
+    pub fn push(&mut self, value: &[u8]) {
+        if let Some(number) = (value.len() <= 20).then(|| as_integer(value)).flatten() {
+            return self.push_integer(number);
+        }
+
+        let start = self.elements.len();
+        let len = value.len();
+        if len < 64 {
+            self.elements.push(0x80 | len as u8);
+        } else if len < 4096 {
+            self.elements.push(0xE0 | (len >> 8) as u8);
+            self.elements.push(len as u8);
+        } else {
+            self.elements.push(0xF0);
+            self.elements.extend_from_slice(&(len as u32).to_le_bytes());
+        }
+        self.elements.extend_from_slice(value);
+        self.finish_element(start);
+    }
+
+    pub fn push_integer(&mut self, number: i64) {
+        let start = self.elements.len();
+        if (0..=127).contains(&number) {
+            self.elements.push(number as u8);
+        } else if (-4096..=4095).contains(&number) {
+            let number = (number & 0x1FFF) as u16;
+            self.elements.push(0xC0 | (number >> 8) as u8);
+            self.elements.push(number as u8);
+        } else if (i16::MIN as i64..=i16::MAX as i64).contains(&number) {
+            self.elements.push(0xF1);
+            self.elements
+                .extend_from_slice(&(number as i16).to_le_bytes());
+        } else if (-(1 << 23)..(1 << 23)).contains(&number) {
+            self.elements.push(0xF2);
+            self.elements
+                .extend_from_slice(&(number as i32).to_le_bytes()[..3]);
+        } else if (i32::MIN as i64..=i32::MAX as i64).contains(&number) {
+            self.elements.push(0xF3);
+            self.elements
+                .extend_from_slice(&(number as i32).to_le_bytes());
+        } else {
+            self.elements.push(0xF4);
+            self.elements.extend_from_slice(&number.to_le_bytes());
+        }
+        self.finish_element(start);
+    }
+
+    /// Appends the element's length, seven bits a byte with the most significant first.
+    /// All but the first byte have the high bit set, telling a reader going backwards
+    /// there's more to read
+    fn finish_element(&mut self, start: usize) {
+        let len = self.elements.len() - start;
+        let size = backlen_size(len);
+        for i in (0..size).rev() {
+            let byte = ((len >> (7 * i)) & 127) as u8;
+            self.elements
+                .push(if i + 1 < size { byte | 128 } else { byte });
+        }
+        self.count += 1;
+    }
+
+    /// Overwrites the first element with another integer below 128, so one that takes as
+    /// little room. A stream node starts with its number of entries, only known at the end
+    pub fn set_first_small(&mut self, number: u8) {
+        debug_assert!(number < 128 && self.elements.first().is_some_and(|&first| first < 128));
+        self.elements[0] = number;
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.size());
+        bytes.extend_from_slice(&(self.size() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.count.min(UNKNOWN_COUNT) as u16).to_le_bytes());
+        bytes.extend_from_slice(&self.elements);
+        bytes.push(END);
+        bytes
+    }
+}
+
+fn backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+fn int_at(bytes: &[u8], at: usize, width: usize) -> Option<i64> {
+    let raw = bytes.get(at..at + width)?;
+    let mut buf = [0; 8];
+    buf[..width].copy_from_slice(raw);
+    // Shifted up and back down to extend the sign
+    let shift = 64 - 8 * width as u32;
+    Some((i64::from_le_bytes(buf) << shift) >> shift)
+}
+
+/// Elements of a listpack, integers given as their decimal strings. None if it's corrupt
+pub fn decode(bytes: &[u8]) -> Option<Vec<Bytes>> {
+    let total = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+    let count = u16::from_le_bytes(bytes.get(4..6)?.try_into().ok()?) as usize;
+    if total != bytes.len() || total <= HEADER_SIZE || bytes.last() != Some(&END) {
+        return None;
+    }
+
+    let mut elements = Vec::new();
+    let mut at = HEADER_SIZE;
+    while bytes[at] != END {
+        let first = bytes[at];
+        let (element, len) = match first {
+            0x00..=0x7F => (Bytes::from(first.to_string()), 1),
+            0x80..=0xBF => {
+                let len = (first & 0x3F) as usize;
+                (
+                    Bytes::copy_from_slice(bytes.get(at + 1..at + 1 + len)?),
+                    1 + len,
+                )
+            }
+            0xC0..=0xDF => {
+                let raw = ((first as i64 & 0x1F) << 8) | *bytes.get(at + 1)? as i64;
+                let number = if raw >= 1 << 12 { raw - (1 << 13) } else { raw };
+                (Bytes::from(number.to_string()), 2)
+            }
+            0xE0..=0xEF => {
+                let len = ((first as usize & 0x0F) << 8) | *bytes.get(at + 1)? as usize;
+                (
+                    Bytes::copy_from_slice(bytes.get(at + 2..at + 2 + len)?),
+                    2 + len,
+                )
+            }
+            0xF0 => {
+                let len = int_at(bytes, at + 1, 4)? as u32 as usize;
+                (
+                    Bytes::copy_from_slice(bytes.get(at + 5..at + 5 + len)?),
+                    5 + len,
+                )
+            }
+            0xF1..=0xF4 => {
+                let width = [2, 3, 4, 8][(first - 0xF1) as usize];
+                (
+                    Bytes::from(int_at(bytes, at + 1, width)?.to_string()),
+                    1 + width,
+                )
+            }
+            _ => return None,
+        };
+
+        at += len + backlen_size(len);
+        if at >= bytes.len() {
+            return None;
+        }
+        elements.push(element);
+    }
+
+    (at == bytes.len() - 1 && (count == UNKNOWN_COUNT || count == elements.len()))
+        .then_some(elements)
+}
+
+/// Integers sorted and packed at the smallest width that holds all of them
+pub fn encode_intset(numbers: &[i64]) -> Vec<u8> {
+    let mut numbers = numbers.to_vec();
+    numbers.sort_unstable();
+    let fits = |min: i64, max: i64| numbers.iter().all(|n| (min..=max).contains(n));
+    let width = if fits(i16::MIN as i64, i16::MAX as i64) {
+        2
+    } else if fits(i32::MIN as i64, i32::MAX as i64) {
+        4
+    } else {
+        8
+    };
+
+    let mut bytes = Vec::with_capacity(8 + numbers.len() * width);
+    bytes.extend_from_slice(&(width as u32).to_le_bytes());
+    bytes.extend_from_slice(&(numbers.len() as u32).to_le_bytes());
+    for number in numbers {
+        bytes.extend_from_slice(&number.to_le_bytes()[..width]);
+    }
+    bytes
+}
+
+pub fn decode_intset(bytes: &[u8]) -> Option<Vec<i64>> {
+    let width = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+    let len = u32::from_le_bytes(bytes.get(4..8)?.try_into().ok()?) as usize;
+    if ![2, 4, 8].contains(&width) || bytes.len() != 8 + len * width {
+        return None;
+    }
+
+    (0..len)
+        .map(|i| int_at(bytes, 8 + i * width, width))
+        .collect()
+}
+
+#[cfg(test)]
+mod listpack_tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let long = "x".repeat(5000);
+        let values = [
+            "a",
+            "127",
+            "-1",
+            "4095",
+            "-4097",
+            "70000",
+            "-9000000",
+            "3000000000",
+            "007",
+            &long,
+        ];
+        let mut listpack = Listpack::new();
+        for value in values {
+            listpack.push(value.as_bytes());
+        }
+        let bytes = listpack.into_bytes();
+        assert_eq!(
+            decode(&bytes).unwrap(),
+            values.map(|v| Bytes::from(v.to_string()))
+        );
+
+        assert!(decode(&bytes[..bytes.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn same_bytes_as_redis() {
+        // RPUSH list a 1 then DEBUG LISTPACK
+        let mut listpack = Listpack::new();
+        listpack.push(b"a");
+        listpack.push(b"1");
+        assert_eq!(
+            listpack.into_bytes(),
+            [0x0c, 0, 0, 0, 0x02, 0, 0x81, b'a', 0x02, 0x01, 0x01, 0xff]
+        );
+
+        assert_eq!(
+            encode_intset(&[3, 1, -2]),
+            [2, 0, 0, 0, 3, 0, 0, 0, 0xfe, 0xff, 1, 0, 3, 0]
+        );
+        assert_eq!(
+            decode_intset(&encode_intset(&[1, 1 << 40])).unwrap(),
+            [1, 1 << 40]
+        );
+    }
+}
@@ -0,0 +1,222 @@
+// LZF as Redis uses it to compress strings in RDB files and DUMP payloads. Compression
+// follows liblzf with Redis' settings (HLOG 16, VERY_FAST) so the output is the same bytes
+// Redis produces. Literal runs are a control byte `len - 1` below 32 followed by the
+// bytes, back references a control byte holding the length and the high bits of the offset
+
+const HLOG: u32 = 16;
+const HSIZE: usize = 1 << HLOG;
+const MAX_LIT: usize = 1 << 5;
+const MAX_OFF: usize = 1 << 13;
+const MAX_REF: usize = (1 << 8) + (1 << 3);
+
+fn first(input: &[u8], at: usize) -> u32 {
+    ((input[at] as u32) << 8) | input[at + 1] as u32
+}
+
+fn next(hval: u32, input: &[u8], at: usize) -> u32 {
+    (hval << 8) | input[at + 2] as u32
+}
+
+fn index(hval: u32) -> usize {
+    ((hval >> (3 * 8 - HLOG)).wrapping_sub(hval.wrapping_mul(5)) as usize) & (HSIZE - 1)
+}
+
+/// Compresses `input`, returning None if the result doesn't fit in `max_len` bytes
+pub fn compress(input: &[u8], max_len: usize) -> Option<Vec<u8>> {
+    if input.is_empty() || max_len == 0 {
+        return None;
+    }
+
+    // Positions are stored plus one so zero means the slot is empty
+    let mut table = vec![0usize; HSIZE];
+    let mut out = Vec::with_capacity(max_len);
+    let mut lit = 0;
+    out.push(0); // start run
+
+    let end = input.len();
+    let mut ip = 0;
+    let mut hval = if end >= 2 { first(input, 0) } else { 0 };
+    while ip + 2 < end {
+        hval = next(hval, input, ip);
+        let slot = index(hval);
+        let reference = table[slot].checked_sub(1);
+        table[slot] = ip + 1;
+
+        let matched = reference.filter(|&r| {
+            r > 0
+                && ip - r - 1 < MAX_OFF
+                && input[r + 2] == input[ip + 2]
+                && input[r..r + 2] == input[ip..ip + 2]
+        });
+
+        let Some(reference) = matched else {
+            if out.len() >= max_len {
+                return None;
+            }
+
+            lit += 1;
+            out.push(input[ip]);
+            ip += 1;
+            if lit == MAX_LIT {
+                let at = out.len() - lit - 1;
+                out[at] = (lit - 1) as u8; // stop run
+                lit = 0;
+                out.push(0); // start run
+            }
+            continue;
+        };
+
+        let offset = ip - reference - 1;
+        let max_ref = (end - ip - 2).min(MAX_REF);
+        if out.len() + 3 + 1 >= max_len && out.len() - usize::from(lit == 0) + 3 + 1 >= max_len {
+            return None;
+        }
+
+        let at = out.len() - lit - 1;
+        out[at] = (lit.max(1) - 1) as u8; // stop run
+        if lit == 0 {
+            out.pop(); // undo run if length is zero
+        }
+
+        // liblzf unrolls the first 16 comparisons without checking the bound, which can
+        // make a match run past it near the end of the input. Kept for identical output
+        let mut len = 2;
+        let mut mismatched = false;
+        if max_ref > 16 {
+            for _ in 0..16 {
+                len += 1;
+                if input[reference + len] != input[ip + len] {
+                    mismatched = true;
+                    break;
+                }
+            }
+        }
+        if !mismatched {
+            loop {
+                len += 1;
+                if len >= max_ref || input[reference + len] != input[ip + len] {
+                    break;
+                }
+            }
+        }
+
+        len -= 2; // now the number of bytes matched minus one
+        ip += 1;
+
+        if len < 7 {
+            out.push(((offset >> 8) + (len << 5)) as u8);
+        } else {
+            out.push(((offset >> 8) + (7 << 5)) as u8);
+            out.push((len - 7) as u8);
+        }
+        out.push(offset as u8);
+
+        lit = 0;
+        out.push(0); // start run
+
+        ip += len + 1;
+        if ip + 2 >= end {
+            break;
+        }
+
+        ip -= 2;
+        hval = first(input, ip);
+        for _ in 0..2 {
+            hval = next(hval, input, ip);
+            table[index(hval)] = ip + 1;
+            ip += 1;
+        }
+    }
+
+    if out.len() + 3 > max_len {
+        return None;
+    }
+
+    while ip < end {
+        lit += 1;
+        out.push(input[ip]);
+        ip += 1;
+        if lit == MAX_LIT {
+            let at = out.len() - lit - 1;
+            out[at] = (lit - 1) as u8;
+            lit = 0;
+            out.push(0);
+        }
+    }
+
+    let at = out.len() - lit - 1;
+    out[at] = (lit.max(1) - 1) as u8; // end run
+    if lit == 0 {
+        out.pop();
+    }
+
+    Some(out)
+}
+
+/// Decompresses `input` into `len` bytes, None if it's corrupt or doesn't come out at
+/// exactly that length
+pub fn decompress(input: &[u8], len: usize) -> Option<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(len);
+    let mut ip = 0;
+    while ip < input.len() {
+        let ctrl = input[ip] as usize;
+        ip += 1;
+
+        if ctrl < MAX_LIT {
+            let run = input.get(ip..ip + ctrl + 1)?;
+            out.extend_from_slice(run);
+            ip += ctrl + 1;
+        } else {
+            let mut run = ctrl >> 5;
+            if run == 7 {
+                run += *input.get(ip)? as usize;
+                ip += 1;
+            }
+
+            let offset = ((ctrl & 0x1f) << 8) + *input.get(ip)? as usize + 1;
+            ip += 1;
+            let start = out.len().checked_sub(offset)?;
+            // The reference may overlap what it writes, so it's copied a byte at a time
+            for i in 0..run + 2 {
+                out.push(out[start + i]);
+            }
+        }
+
+        if out.len() > len {
+            return None;
+        }
+    }
+
+    (out.len() == len).then_some(out)
+}
+
+#[cfg(test)]
+mod lzf_tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = b"hello hello hello hello hello world, aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+        let compressed = compress(text, text.len() - 4).unwrap();
+        assert!(compressed.len() < text.len());
+        assert_eq!(decompress(&compressed, text.len()).unwrap(), text);
+
+        let repeated = b"0123456789".repeat(500);
+        let compressed = compress(&repeated, repeated.len()).unwrap();
+        assert_eq!(decompress(&compressed, repeated.len()).unwrap(), repeated);
+
+        // Nothing to gain from random looking bytes
+        assert!(compress(b"abcdefghijklmnopqrstuvwxyz", 22).is_none());
+        assert!(decompress(&compressed, repeated.len() + 1).is_none());
+    }
+
+    #[test]
+    fn same_bytes_as_liblzf() {
+        // Two literals, a back reference of 26 bytes at offset 1 then the last two
+        let compressed = compress(&[b'a'; 30], 26).unwrap();
+        assert_eq!(
+            compressed,
+            [0x01, b'a', b'a', 0xe0, 0x11, 0x00, 0x01, b'a', b'a']
+        );
+    }
+}
@@ -1,13 +1,18 @@
 use bytes::Bytes;
 use nom::{
     bytes::complete::{tag, take},
-    number::complete::{be_u32, be_u8, le_i16, le_i32, le_i8, le_u32, le_u64},
+    number::complete::{be_u32, be_u64, be_u8, le_f64, le_i16, le_i32, le_i8, le_u32, le_u64},
     IResult,
 };
 
-use super::{RdbDatabase, RdbDatabaseEntry, RdbExpiry, RdbInner, RdbKeyValue, RdbValue};
+use super::listpack::{decode, decode_intset};
+use super::lzf;
+use super::{
+    RdbDatabase, RdbDatabaseEntry, RdbExpiry, RdbInner, RdbKeyValue, RdbStream, RdbValue, StreamId,
+    ValueType,
+};
 
-use std::{collections::HashMap, time::Duration};
+use std::collections::HashMap;
 
 #[derive(Debug)]
 enum LengthEncoding {
@@ -15,12 +20,16 @@ enum LengthEncoding {
     Int8,
     Int16,
     Int32,
+    Lzf,
+}
+
+fn failure<T>(input: &[u8], kind: nom::error::ErrorKind) -> IResult<&[u8], T> {
+    Err(nom::Err::Failure(nom::error::Error::new(input, kind)))
 }
 
 fn parse_length_encoding(input: &[u8]) -> IResult<&[u8], LengthEncoding> {
-    let mask = 0xCE;
     let (input, first) = be_u8(input)?;
-    let encoding = (first & mask) >> 6;
+    let encoding = first >> 6;
     match encoding {
         0x00 => Ok((input, LengthEncoding::Len((first & 0x3F) as u64))),
         0x01 => {
@@ -28,18 +37,23 @@ fn parse_length_encoding(input: &[u8]) -> IResult<&[u8], LengthEncoding> {
             let size = (((first & 0x3F) as u64) << 8) | (next as u64);
             Ok((input, LengthEncoding::Len(size)))
         }
-        0x02 => {
-            let (input, size) = be_u32(input)?;
-            Ok((input, LengthEncoding::Len(size as u64)))
-        }
+        0x02 => match first {
+            0x80 => {
+                let (input, size) = be_u32(input)?;
+                Ok((input, LengthEncoding::Len(size as u64)))
+            }
+            0x81 => {
+                let (input, size) = be_u64(input)?;
+                Ok((input, LengthEncoding::Len(size)))
+            }
+            _ => failure(input, nom::error::ErrorKind::Switch),
+        },
         0x03 => match first & 0x3F {
             0x00 => Ok((input, LengthEncoding::Int8)),
             0x01 => Ok((input, LengthEncoding::Int16)),
             0x02 => Ok((input, LengthEncoding::Int32)),
-            _ => Err(nom::Err::Failure(nom::error::Error::new(
-                input,
-                nom::error::ErrorKind::Switch,
-            ))),
+            0x03 => Ok((input, LengthEncoding::Lzf)),
+            _ => failure(input, nom::error::ErrorKind::Switch),
         },
         _ => unreachable!(),
     }
@@ -76,6 +90,300 @@ fn parse_string(input: &[u8]) -> IResult<&[u8], Bytes> {
             let (input, size) = le_i32(input)?;
             Ok((input, Bytes::from(size.to_string().into_bytes())))
         }
+        LengthEncoding::Lzf => {
+            let (input, compressed_len) = parse_length_only(input)?;
+            let (input, len) = parse_length_only(input)?;
+            let (input, compressed) = take(compressed_len)(input)?;
+            match lzf::decompress(compressed, len as usize) {
+                Some(value) => Ok((input, Bytes::from(value))),
+                None => failure(input, nom::error::ErrorKind::Verify),
+            }
+        }
+    }
+}
+
+/// Number of elements in a collection, checked against what's left of the input so a
+/// corrupt length can't make the parser allocate a huge collection up front
+fn parse_count(input: &[u8]) -> IResult<&[u8], usize> {
+    let (rest, count) = parse_length_only(input)?;
+    if count > rest.len() as u64 {
+        return failure(input, nom::error::ErrorKind::TooLarge);
+    }
+    Ok((rest, count as usize))
+}
+
+fn parse_strings(input: &[u8]) -> IResult<&[u8], Vec<Bytes>> {
+    let (mut input, count) = parse_count(input)?;
+    let mut strings = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (rest, string) = parse_string(input)?;
+        strings.push(string);
+        input = rest;
+    }
+    Ok((input, strings))
+}
+
+/// Elements of a listpack saved as a string
+fn parse_listpack(input: &[u8]) -> IResult<&[u8], Vec<Bytes>> {
+    let (rest, listpack) = parse_string(input)?;
+    match decode(&listpack) {
+        Some(elements) => Ok((rest, elements)),
+        None => failure(input, nom::error::ErrorKind::Verify),
+    }
+}
+
+fn pairs(input: &[u8], elements: Vec<Bytes>) -> IResult<&[u8], Vec<(Bytes, Bytes)>> {
+    if !elements.len().is_multiple_of(2) {
+        return failure(input, nom::error::ErrorKind::Verify);
+    }
+    let mut elements = elements.into_iter();
+    let mut pairs = Vec::new();
+    while let (Some(first), Some(second)) = (elements.next(), elements.next()) {
+        pairs.push((first, second));
+    }
+    Ok((input, pairs))
+}
+
+fn parse_score(value: &[u8]) -> Option<f64> {
+    let score: f64 = std::str::from_utf8(value).ok()?.parse().ok()?;
+    (!score.is_nan()).then_some(score)
+}
+
+fn parse_stream_id(input: &[u8]) -> IResult<&[u8], StreamId> {
+    let (input, ms) = parse_length_only(input)?;
+    let (input, seq) = parse_length_only(input)?;
+    Ok((input, (ms, seq)))
+}
+
+type StreamEntry = (StreamId, Vec<(Bytes, Bytes)>);
+
+const STREAM_DELETED: i64 = 1;
+const STREAM_SAME_FIELDS: i64 = 2;
+
+/// Entries of a stream node, the master entry's fields followed by each entry's flags, id
+/// relative to the node's and fields, the fields left out when they're the master's
+fn stream_node_entries(master: StreamId, elements: &[Bytes]) -> Option<Vec<StreamEntry>> {
+    let number = |value: &Bytes| -> Option<i64> { std::str::from_utf8(value).ok()?.parse().ok() };
+    let mut at = 0;
+    let mut next = || {
+        at += 1;
+        elements.get(at - 1)
+    };
+
+    let count = number(next()?)?;
+    let deleted = number(next()?)?;
+    let master_fields = (0..number(next()?)?)
+        .map(|_| next().cloned())
+        .collect::<Option<Vec<Bytes>>>()?;
+    if number(next()?)? != 0 {
+        return None;
+    }
+
+    let mut entries = Vec::new();
+    for _ in 0..count.checked_add(deleted)? {
+        let flags = number(next()?)?;
+        let ms = master.0.wrapping_add(number(next()?)? as u64);
+        let seq = master.1.wrapping_add(number(next()?)? as u64);
+        let fields = if flags & STREAM_SAME_FIELDS != 0 {
+            master_fields
+                .iter()
+                .map(|field| Some((field.clone(), next()?.clone())))
+                .collect::<Option<Vec<_>>>()?
+        } else {
+            (0..number(next()?)?)
+                .map(|_| Some((next()?.clone(), next()?.clone())))
+                .collect::<Option<Vec<_>>>()?
+        };
+        // Number of elements the entry took, for walking the node backwards
+        next()?;
+
+        if flags & STREAM_DELETED == 0 {
+            entries.push(((ms, seq), fields));
+        }
+    }
+
+    (at == elements.len()).then_some(entries)
+}
+
+fn parse_stream(input: &[u8], value_type: ValueType) -> IResult<&[u8], RdbValue> {
+    let (mut input, nodes) = parse_count(input)?;
+    let mut entries = Vec::new();
+    for _ in 0..nodes {
+        let (rest, key) = parse_string(input)?;
+        let (rest, elements) = parse_listpack(rest)?;
+        let node = <[u8; 16]>::try_from(&key[..]).ok().and_then(|key| {
+            let ms = u64::from_be_bytes(key[..8].try_into().ok()?);
+            let seq = u64::from_be_bytes(key[8..].try_into().ok()?);
+            stream_node_entries((ms, seq), &elements)
+        });
+        let Some(node) = node else {
+            return failure(input, nom::error::ErrorKind::Verify);
+        };
+        entries.extend(node);
+        input = rest;
+    }
+
+    let (input, _length) = parse_length_only(input)?;
+    let (mut input, last_id) = parse_stream_id(input)?;
+    if value_type != ValueType::StreamListpacks {
+        let (rest, _first_id) = parse_stream_id(input)?;
+        let (rest, _max_deleted_id) = parse_stream_id(rest)?;
+        let (rest, _entries_added) = parse_length_only(rest)?;
+        input = rest;
+    }
+
+    // Consumer groups aren't supported, they're read past and left out
+    let (mut input, groups) = parse_count(input)?;
+    for _ in 0..groups {
+        let (rest, _name) = parse_string(input)?;
+        let (mut rest, _last_id) = parse_stream_id(rest)?;
+        if value_type != ValueType::StreamListpacks {
+            (rest, _) = parse_length_only(rest)?;
+        }
+
+        let (mut rest, pending) = parse_count(rest)?;
+        for _ in 0..pending {
+            (rest, _) = take(16usize)(rest)?;
+            (rest, _) = le_u64(rest)?;
+            (rest, _) = parse_length_only(rest)?;
+        }
+
+        let (mut rest, consumers) = parse_count(rest)?;
+        for _ in 0..consumers {
+            (rest, _) = parse_string(rest)?;
+            (rest, _) = le_u64(rest)?;
+            if value_type == ValueType::StreamListpacks3 {
+                (rest, _) = le_u64(rest)?;
+            }
+            let pending;
+            (rest, pending) = parse_count(rest)?;
+            for _ in 0..pending {
+                (rest, _) = take(16usize)(rest)?;
+            }
+        }
+        input = rest;
+    }
+
+    Ok((input, RdbValue::Stream(RdbStream { entries, last_id })))
+}
+
+fn sorted_by_score(mut set: Vec<(Bytes, f64)>) -> RdbValue {
+    set.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+    RdbValue::SortedSet(set)
+}
+
+/// A value of the given type, as an RDB file or a DUMP payload holds it
+fn parse_value(input: &[u8], value_type: ValueType) -> IResult<&[u8], RdbValue> {
+    let invalid = |input| failure(input, nom::error::ErrorKind::Verify);
+    match value_type {
+        ValueType::String => {
+            let (input, value) = parse_string(input)?;
+            Ok((input, RdbValue::String(value)))
+        }
+        ValueType::List => {
+            let (input, list) = parse_strings(input)?;
+            Ok((input, RdbValue::List(list)))
+        }
+        ValueType::ListQuicklist2 => {
+            let (mut input, nodes) = parse_count(input)?;
+            let mut list = Vec::new();
+            for _ in 0..nodes {
+                let (rest, container) = parse_length_only(input)?;
+                // A plain node holds a single large element
+                let (rest, elements) = match container {
+                    1 => parse_string(rest).map(|(rest, element)| (rest, vec![element]))?,
+                    2 => parse_listpack(rest)?,
+                    _ => return invalid(input),
+                };
+                list.extend(elements);
+                input = rest;
+            }
+            Ok((input, RdbValue::List(list)))
+        }
+        ValueType::Set => {
+            let (input, set) = parse_strings(input)?;
+            Ok((input, RdbValue::Set(set)))
+        }
+        ValueType::SetIntset => {
+            let (rest, intset) = parse_string(input)?;
+            let Some(numbers) = decode_intset(&intset) else {
+                return invalid(input);
+            };
+            let set = numbers.iter().map(|n| Bytes::from(n.to_string()));
+            Ok((rest, RdbValue::Set(set.collect())))
+        }
+        ValueType::SetListpack => {
+            let (input, set) = parse_listpack(input)?;
+            Ok((input, RdbValue::Set(set)))
+        }
+        ValueType::Hash => {
+            let (mut input, count) = parse_count(input)?;
+            let mut hash = Vec::with_capacity(count);
+            for _ in 0..count {
+                let (rest, field) = parse_string(input)?;
+                let (rest, value) = parse_string(rest)?;
+                hash.push((field, value));
+                input = rest;
+            }
+            Ok((input, RdbValue::Hash(hash)))
+        }
+        ValueType::HashListpack => {
+            let (rest, elements) = parse_listpack(input)?;
+            let (rest, hash) = pairs(rest, elements)?;
+            Ok((rest, RdbValue::Hash(hash)))
+        }
+        ValueType::Zset | ValueType::Zset2 => {
+            let (mut input, count) = parse_count(input)?;
+            let mut set = Vec::with_capacity(count);
+            for _ in 0..count {
+                let (rest, member) = parse_string(input)?;
+                let (rest, score) = if value_type == ValueType::Zset2 {
+                    le_f64(rest)?
+                } else {
+                    // Scores were saved as strings, with lengths standing for nan and infinities
+                    let (rest, len) = be_u8(rest)?;
+                    match len {
+                        253 => (rest, f64::NAN),
+                        254 => (rest, f64::INFINITY),
+                        255 => (rest, f64::NEG_INFINITY),
+                        _ => {
+                            let (rest, score) = take(len)(rest)?;
+                            (rest, parse_score(score).unwrap_or(f64::NAN))
+                        }
+                    }
+                };
+                if score.is_nan() {
+                    return invalid(input);
+                }
+                set.push((member, score));
+                input = rest;
+            }
+            Ok((input, sorted_by_score(set)))
+        }
+        ValueType::ZsetListpack => {
+            let (rest, elements) = parse_listpack(input)?;
+            let (rest, members) = pairs(rest, elements)?;
+            let set = members
+                .into_iter()
+                .map(|(member, score)| Some((member, parse_score(&score)?)))
+                .collect::<Option<Vec<_>>>();
+            match set {
+                Some(set) => Ok((rest, sorted_by_score(set))),
+                None => invalid(input),
+            }
+        }
+        ValueType::StreamListpacks | ValueType::StreamListpacks2 | ValueType::StreamListpacks3 => {
+            parse_stream(input, value_type)
+        }
+    }
+}
+
+/// A value type followed by a value, what a DUMP payload holds ahead of its footer
+pub fn parse_object(input: &[u8]) -> IResult<&[u8], RdbValue> {
+    let (rest, value_type) = be_u8(input)?;
+    match ValueType::from_byte(value_type) {
+        Some(value_type) => parse_value(rest, value_type),
+        None => failure(input, nom::error::ErrorKind::Switch),
     }
 }
 
@@ -116,15 +424,11 @@ fn parse_db_stats(input: &[u8]) -> IResult<&[u8], ()> {
 fn parse_key_value(input: &[u8]) -> IResult<&[u8], RdbKeyValue> {
     let (input, expiry) = parse_expiry_time(input)?;
     let (input, value_type) = be_u8(input)?;
+    let Some(value_type) = ValueType::from_byte(value_type) else {
+        return failure(input, nom::error::ErrorKind::Switch);
+    };
     let (input, key) = parse_string(input)?;
-
-    let (input, value) = match value_type {
-        0x00 => {
-            let (input, s) = parse_string(input)?;
-            Ok((input, RdbValue::String(s)))
-        }
-        _ => unimplemented!("other types not supported yet"),
-    }?;
+    let (input, value) = parse_value(input, value_type)?;
 
     Ok((input, RdbKeyValue { expiry, key, value }))
 }
@@ -148,16 +452,12 @@ fn parse_database(input: &[u8]) -> IResult<&[u8], RdbDatabase> {
             Some(&0xFE) | Some(&0xFF) | None => break,
             _ => {
                 let (i, kv) = parse_key_value(input)?;
-                let expiry = if let Some(expiry) = kv.expiry {
-                    match expiry {
-                        RdbExpiry::Seconds(s) => Some(Duration::from_millis((s as u64) * 1000)),
-                        RdbExpiry::Milliseconds(ms) => Some(Duration::from_millis(ms)),
-                    }
-                } else {
-                    None
-                };
+                let expiry = kv.expiry.map(|expiry| match expiry {
+                    RdbExpiry::Seconds(s) => s as u64 * 1000,
+                    RdbExpiry::Milliseconds(ms) => ms,
+                });
 
-                let RdbValue::String(value) = kv.value;
+                let value = kv.value;
                 entries.insert(kv.key, RdbDatabaseEntry { expiry, value });
                 input = i;
             }
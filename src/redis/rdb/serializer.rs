@@ -0,0 +1,360 @@
+use bytes::Bytes;
+
+use super::listpack::{as_integer, encode_intset, Listpack};
+use super::lzf;
+use super::{RdbStream, RdbValue, StreamId, ValueType};
+
+// Values are written as Redis 7.4 writes them: the value type follows the encoding Redis
+// would keep the value in, and strings are stored as integers or compressed where Redis
+// would do it, so the bytes come out the same
+
+/// Longest string worth trying to store as an integer
+const INTEGER_MAX_LEN: usize = 11;
+/// Strings up to this long aren't worth compressing
+const COMPRESS_MIN_LEN: usize = 20;
+/// Size of a list's quicklist nodes, list-max-listpack-size -2
+const LIST_NODE_BYTES: usize = 8 * 1024;
+/// What Redis adds to an element's length to guess the bytes it'll take in a node
+const LIST_ELEMENT_OVERHEAD: usize = 8;
+const QUICKLIST_PACKED: u64 = 2;
+/// stream-node-max-bytes and stream-node-max-entries
+const STREAM_NODE_BYTES: usize = 4096;
+const STREAM_NODE_ENTRIES: usize = 100;
+const STREAM_SAME_FIELDS: i64 = 2;
+
+const ENCODED: u8 = 0xC0;
+const ENCODED_INT8: u8 = ENCODED;
+const ENCODED_INT16: u8 = ENCODED | 1;
+const ENCODED_INT32: u8 = ENCODED | 2;
+const ENCODED_LZF: u8 = ENCODED | 3;
+
+pub fn write_length(out: &mut Vec<u8>, len: u64) {
+    if len < 1 << 6 {
+        out.push(len as u8);
+    } else if len < 1 << 14 {
+        out.push(0x40 | (len >> 8) as u8);
+        out.push(len as u8);
+    } else if len <= u32::MAX as u64 {
+        out.push(0x80);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    } else {
+        out.push(0x81);
+        out.extend_from_slice(&len.to_be_bytes());
+    }
+}
+
+pub fn write_string(out: &mut Vec<u8>, value: &[u8]) {
+    let number = (value.len() <= INTEGER_MAX_LEN)
+        .then(|| as_integer(value))
+        .flatten();
+    if let Some(number) = number.and_then(|n| i32::try_from(n).ok()) {
+        if let Ok(number) = i8::try_from(number) {
+            out.extend_from_slice(&[ENCODED_INT8, number as u8]);
+        } else if let Ok(number) = i16::try_from(number) {
+            out.push(ENCODED_INT16);
+            out.extend_from_slice(&number.to_le_bytes());
+        } else {
+            out.push(ENCODED_INT32);
+            out.extend_from_slice(&number.to_le_bytes());
+        }
+        return;
+    }
+
+    // Only kept compressed if it saves at least 4 bytes
+    if value.len() > COMPRESS_MIN_LEN {
+        if let Some(compressed) = lzf::compress(value, value.len() - 4) {
+            out.push(ENCODED_LZF);
+            write_length(out, compressed.len() as u64);
+            write_length(out, value.len() as u64);
+            out.extend_from_slice(&compressed);
+            return;
+        }
+    }
+
+    write_length(out, value.len() as u64);
+    out.extend_from_slice(value);
+}
+
+/// A score as Redis prints it, the shortest digits that read back as the same double.
+/// Exponents are only used for very large or very small scores
+fn score_string(score: f64) -> String {
+    if score.is_nan() {
+        return "nan".to_string();
+    }
+    if score.is_infinite() {
+        return if score > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+
+    // `{:e}` gives the shortest digits as d.ddde<exp>
+    let scientific = format!("{:e}", score.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let digits = mantissa.replace('.', "");
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let ndigits = digits.len() as i32;
+    // Power of ten of the last digit
+    let k = exponent - (ndigits - 1);
+    let sign = if score < 0.0 { "-" } else { "" };
+
+    if k >= 0 && exponent.abs() < ndigits + 7 {
+        return format!("{sign}{digits}{}", "0".repeat(k as usize));
+    }
+    if k < 0 && (k > -7 || exponent.abs() < 4) {
+        let offset = ndigits + k;
+        return if offset <= 0 {
+            format!("{sign}0.{}{digits}", "0".repeat(-offset as usize))
+        } else {
+            let (whole, fraction) = digits.split_at(offset as usize);
+            format!("{sign}{whole}.{fraction}")
+        };
+    }
+
+    let fraction = if ndigits > 1 {
+        format!(".{}", &digits[1..])
+    } else {
+        String::new()
+    };
+    let exponent_sign = if exponent < 0 { '-' } else { '+' };
+    format!(
+        "{sign}{}{fraction}e{exponent_sign}{}",
+        &digits[..1],
+        exponent.abs()
+    )
+}
+
+/// Integer a score is kept as in a listpack, if it's a whole number
+fn score_integer(score: f64) -> Option<i64> {
+    let half = (i64::MAX / 2) as f64;
+    (score.fract() == 0.0 && (-half..=half).contains(&score)).then_some(score as i64)
+}
+
+fn list_nodes(list: &[Bytes], encoding: &str) -> Vec<Listpack> {
+    let mut nodes: Vec<Listpack> = Vec::new();
+    for element in list {
+        let full = nodes.last().is_none_or(|node| {
+            encoding != "listpack"
+                && node.size() + element.len() + LIST_ELEMENT_OVERHEAD > LIST_NODE_BYTES
+        });
+        if full {
+            nodes.push(Listpack::new());
+        }
+        if let Some(node) = nodes.last_mut() {
+            node.push(element);
+        }
+    }
+    nodes
+}
+
+fn stream_key(id: StreamId) -> [u8; 16] {
+    let mut key = [0; 16];
+    key[..8].copy_from_slice(&id.0.to_be_bytes());
+    key[8..].copy_from_slice(&id.1.to_be_bytes());
+    key
+}
+
+/// Entries grouped in listpacks as XADD fills them. Each node starts with a master entry
+/// holding the fields of its first entry, and entries with the same fields leave them out
+fn stream_nodes(stream: &RdbStream) -> Vec<(StreamId, Listpack)> {
+    let mut nodes: Vec<(StreamId, Listpack)> = Vec::new();
+    let mut master_fields: Vec<&Bytes> = Vec::new();
+    let mut count = 0;
+
+    for (id, fields) in &stream.entries {
+        let size: usize = fields.iter().map(|(f, v)| f.len() + v.len()).sum();
+        let full = nodes.last().is_none_or(|(_, node)| {
+            node.size() + size >= STREAM_NODE_BYTES || count >= STREAM_NODE_ENTRIES
+        });
+        if full {
+            if let Some((_, node)) = nodes.last_mut() {
+                node.set_first_small(count as u8);
+            }
+
+            let mut node = Listpack::new();
+            node.push_integer(1);
+            node.push_integer(0);
+            node.push_integer(fields.len() as i64);
+            for (field, _) in fields {
+                node.push(field);
+            }
+            node.push_integer(0);
+            master_fields = fields.iter().map(|(field, _)| field).collect();
+            nodes.push((*id, node));
+            count = 0;
+        }
+
+        let Some((master, node)) = nodes.last_mut() else {
+            continue;
+        };
+        let same_fields = master_fields.len() == fields.len()
+            && master_fields.iter().zip(fields).all(|(a, (b, _))| *a == b);
+
+        node.push_integer(if same_fields { STREAM_SAME_FIELDS } else { 0 });
+        node.push_integer(id.0.wrapping_sub(master.0) as i64);
+        node.push_integer(id.1.wrapping_sub(master.1) as i64);
+        if same_fields {
+            for (_, value) in fields {
+                node.push(value);
+            }
+            node.push_integer(fields.len() as i64 + 3);
+        } else {
+            node.push_integer(fields.len() as i64);
+            for (field, value) in fields {
+                node.push(field);
+                node.push(value);
+            }
+            node.push_integer(2 * fields.len() as i64 + 4);
+        }
+        count += 1;
+    }
+
+    if let Some((_, node)) = nodes.last_mut() {
+        node.set_first_small(count as u8);
+    }
+    nodes
+}
+
+fn write_stream(out: &mut Vec<u8>, stream: &RdbStream) {
+    let nodes = stream_nodes(stream);
+    write_length(out, nodes.len() as u64);
+    for (master, node) in nodes {
+        write_string(out, &stream_key(master));
+        write_string(out, &node.into_bytes());
+    }
+
+    let first_id = stream.entries.first().map_or((0, 0), |(id, _)| *id);
+    let ids = [stream.last_id, first_id, (0, 0)];
+    write_length(out, stream.entries.len() as u64);
+    for id in ids {
+        write_length(out, id.0);
+        write_length(out, id.1);
+    }
+    // Entries ever added, then no consumer groups
+    write_length(out, stream.entries.len() as u64);
+    write_length(out, 0);
+}
+
+/// The value type followed by the value. `encoding` is the one OBJECT ENCODING reports,
+/// which decides the type
+pub fn serialize_object(value: &RdbValue, encoding: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    match value {
+        RdbValue::String(value) => {
+            out.push(ValueType::String as u8);
+            write_string(&mut out, value);
+        }
+        RdbValue::List(list) => {
+            out.push(ValueType::ListQuicklist2 as u8);
+            let nodes = list_nodes(list, encoding);
+            write_length(&mut out, nodes.len() as u64);
+            for node in nodes {
+                write_length(&mut out, QUICKLIST_PACKED);
+                write_string(&mut out, &node.into_bytes());
+            }
+        }
+        RdbValue::Set(set) => match encoding {
+            "intset" => {
+                out.push(ValueType::SetIntset as u8);
+                let numbers: Vec<i64> = set.iter().filter_map(|m| as_integer(m)).collect();
+                write_string(&mut out, &encode_intset(&numbers));
+            }
+            "listpack" => {
+                out.push(ValueType::SetListpack as u8);
+                let mut listpack = Listpack::new();
+                for member in set {
+                    listpack.push(member);
+                }
+                write_string(&mut out, &listpack.into_bytes());
+            }
+            _ => {
+                out.push(ValueType::Set as u8);
+                write_length(&mut out, set.len() as u64);
+                for member in set {
+                    write_string(&mut out, member);
+                }
+            }
+        },
+        RdbValue::Hash(hash) => {
+            if encoding == "listpack" {
+                out.push(ValueType::HashListpack as u8);
+                let mut listpack = Listpack::new();
+                for (field, value) in hash {
+                    listpack.push(field);
+                    listpack.push(value);
+                }
+                write_string(&mut out, &listpack.into_bytes());
+            } else {
+                out.push(ValueType::Hash as u8);
+                write_length(&mut out, hash.len() as u64);
+                for (field, value) in hash {
+                    write_string(&mut out, field);
+                    write_string(&mut out, value);
+                }
+            }
+        }
+        RdbValue::SortedSet(set) => {
+            if encoding == "listpack" {
+                out.push(ValueType::ZsetListpack as u8);
+                let mut listpack = Listpack::new();
+                for (member, score) in set {
+                    listpack.push(member);
+                    match score_integer(*score) {
+                        Some(score) => listpack.push_integer(score),
+                        None => listpack.push(score_string(*score).as_bytes()),
+                    }
+                }
+                write_string(&mut out, &listpack.into_bytes());
+            } else {
+                // Highest score first so loading can add each member at the head
+                out.push(ValueType::Zset2 as u8);
+                write_length(&mut out, set.len() as u64);
+                for (member, score) in set.iter().rev() {
+                    write_string(&mut out, member);
+                    out.extend_from_slice(&score.to_le_bytes());
+                }
+            }
+        }
+        RdbValue::Stream(stream) => {
+            out.push(ValueType::StreamListpacks3 as u8);
+            write_stream(&mut out, stream);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod serializer_tests {
+    use super::*;
+
+    fn string(value: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_string(&mut out, value);
+        out
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(string(b"12"), [0xc0, 12]);
+        assert_eq!(string(b"-300"), [0xc1, 0xd4, 0xfe]);
+        assert_eq!(string(b"70000"), [0xc2, 0x70, 0x11, 0x01, 0x00]);
+        assert_eq!(string(b"012"), [3, b'0', b'1', b'2']);
+        assert_eq!(string(b"4294967296")[0], 10);
+
+        let compressed = string(&[b'a'; 30]);
+        assert_eq!(&compressed[..3], [0xc3, 9, 30]);
+
+        let mut out = Vec::new();
+        write_length(&mut out, 300);
+        write_length(&mut out, 70000);
+        assert_eq!(out, [0x41, 0x2c, 0x80, 0, 1, 0x11, 0x70]);
+    }
+
+    #[test]
+    fn scores() {
+        assert_eq!(score_string(1.5), "1.5");
+        assert_eq!(score_string(-0.001), "-0.001");
+        assert_eq!(score_string(1e-7), "1e-7");
+        assert_eq!(score_string(1.25e30), "1.25e+30");
+        assert_eq!(score_string(123456.789), "123456.789");
+        assert_eq!(score_integer(3.0), Some(3));
+        assert_eq!(score_integer(0.5), None);
+    }
+}
@@ -1,28 +1,47 @@
 use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::{collections::BTreeMap, time::Duration};
 use tokio::sync::RwLock;
 
 use bytes::Bytes;
+use futures_util::FutureExt;
 use kanal::{AsyncReceiver, AsyncSender};
 use tokio::task::JoinHandle;
 
-use super::protocol::{CommandType, RedisCommand, RedisError, Value};
-use super::stores::GlobalStore;
+use super::protocol::{
+    format_double, CommandFlag, CommandSpec, CommandType, ProtocolLimits, ProtocolVersion,
+    RedisCommand, RedisError, Value, COMMANDS,
+};
+use super::stores::{
+    bitcount, bitpos, getbit, parse_bit, parse_float, parse_offset, BitOp, BitRange, BitfieldOp,
+    Data, EventClass, ExpireOptions, KeyspaceEvent, RestoreOptions, ScanOptions, SetExpiry,
+    SetOptions, SortOptions, DEFAULT_SAMPLES,
+};
 use super::utils::{
-    bytes_to_number,
+    alloc, bytes_to_number, bytes_to_str,
     geo::{decode_latlon, encode_latlon, latlon_dist, validate_latlon},
-    validate_args_len,
+    parse_memory_size, validate_args_len,
 };
 
 mod replica;
 use replica::ReplicaMasterConnection;
 
-const WORKER_COUNT: usize = 10;
+pub use super::stores::{
+    EvictionPolicy, GlobalStore, KeyspaceEvents, MaxMemory, DEFAULT_DATABASES,
+};
 
-pub type Request = (Value, Bytes, AsyncSender<Vec<Value>>);
-type ReplicaStore = Arc<RwLock<HashMap<Bytes, AsyncSender<Vec<Value>>>>>;
+const WORKER_COUNT: usize = 10;
+const REDIS_VERSION: &str = "7.4.0";
+const ACTIVE_EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+// Values UNLINK frees in the background rather than while holding the keyspace lock
+const LAZYFREE_THRESHOLD: usize = 64;
+// Sections plain INFO and INFO all give, in the order Redis lists them
+const INFO_SECTIONS: [&str; 3] = ["memory", "stats", "replication"];
+
+pub type Request = (Vec<Value>, Bytes, AsyncSender<Vec<Value>>);
+type ReplicaStore = Arc<RwLock<Replicas>>;
 type ReplicationAcknowledger = (AsyncSender<usize>, AsyncReceiver<usize>);
 
 #[derive(Debug, PartialEq)]
@@ -49,6 +68,176 @@ impl std::fmt::Display for ServerRole {
     }
 }
 
+/// Connected replicas and the database the replication stream has them on
+#[derive(Default)]
+struct Replicas {
+    senders: HashMap<Bytes, AsyncSender<Vec<Value>>>,
+    // None once a replica has joined as it starts out on database 0 whatever the others
+    // were last sent
+    selected_db: Option<usize>,
+}
+
+impl Replicas {
+    async fn send(&mut self, db: usize, mut commands: Vec<Value>) {
+        // Writes are sent to replicas in the same order they select databases in
+        if self.selected_db != Some(db) {
+            let select = Value::Array(vec![
+                Value::String("SELECT".into()),
+                Value::String(db.to_string().into()),
+            ]);
+            commands.insert(0, select);
+            self.selected_db = Some(db);
+        }
+
+        for sender in self.senders.values() {
+            // A disconnected replica shouldn't fail the write for the client
+            let _ = sender.send(commands.clone()).await;
+        }
+    }
+}
+
+// Applies eviction settings, keeping what CONFIG GET reports in step with them
+fn set_eviction(store: &GlobalStore, max_memory: MaxMemory) -> Result<(), RedisError> {
+    let mut cfg = store.config_writer()?;
+    let settings = [
+        ("maxmemory", max_memory.limit.to_string()),
+        ("maxmemory-policy", max_memory.policy.to_string()),
+        ("maxmemory-samples", max_memory.samples.to_string()),
+    ];
+
+    for (key, value) in settings {
+        cfg.insert(&key.into(), &value.into());
+    }
+
+    store.set_max_memory(max_memory)
+}
+
+fn set_notifications(store: &GlobalStore, events: KeyspaceEvents) -> Result<(), RedisError> {
+    let mut cfg = store.config_writer()?;
+    cfg.insert(&"notify-keyspace-events".into(), &events.to_string().into());
+
+    store.set_keyspace_events(events)
+}
+
+fn command(args: impl IntoIterator<Item = Bytes>) -> Value {
+    Value::Array(args.into_iter().map(Value::String).collect())
+}
+
+// Keys removed by expiry or eviction are sent to replicas as DELs, so they only ever lose
+// keys when their master does
+fn del_command(key: Bytes) -> Value {
+    command(["DEL".into(), key])
+}
+
+// The deadline a key was given as a PEXPIREAT, or a DEL if it had already passed and the
+// key is gone
+fn absolute_expiry(store: &GlobalStore, db: usize, key: &Bytes) -> Result<Value, RedisError> {
+    let command = match store.expiry(db, key)? {
+        Some(at) => command(["PEXPIREAT".into(), key.clone(), at.to_string().into()]),
+        None => del_command(key.clone()),
+    };
+    Ok(command)
+}
+
+// Commands that replay a write that succeeded on a replica. Those that could give a
+// different result when replayed are rewritten into ones that can't, and TTLs relative to
+// now are sent as the deadline they gave so replicas don't drift by the replication lag
+fn replicated_commands(
+    store: &GlobalStore,
+    db: usize,
+    request: &RedisCommand,
+    response: &[Value],
+) -> Result<Vec<Value>, RedisError> {
+    let args = &request.args;
+    let commands = match (request.cmd, response) {
+        (_, [Value::Error(_), ..]) => vec![],
+        // Only a SORT that stores its result writes anything
+        (CommandType::Sort, _)
+            if SortOptions::parse(request.cmd, args.get(1..).unwrap_or_default())
+                .is_ok_and(|options| options.store.is_none()) =>
+        {
+            vec![]
+        }
+        (CommandType::IncrByFloat, [value @ Value::String(_)]) => vec![Value::Array(vec![
+            Value::String("SET".into()),
+            Value::String(args[0].clone()),
+            value.clone(),
+            Value::String("KEEPTTL".into()),
+        ])],
+        // A blocking pop is replayed as the pop that unblocked it
+        (CommandType::BLPop, popped) => popped
+            .iter()
+            .filter_map(|value| match value {
+                Value::Array(pair) => Some(Value::Array(vec![
+                    Value::String("LPOP".into()),
+                    pair.first()?.clone(),
+                ])),
+                _ => None,
+            })
+            .collect(),
+        (CommandType::Expire | CommandType::PExpire, [Value::Integer(0)]) => vec![],
+        (CommandType::Expire | CommandType::PExpire, _) => {
+            vec![absolute_expiry(store, db, &args[0])?]
+        }
+        (CommandType::GetEx, [Value::NullString]) => vec![],
+        (CommandType::GetEx, _) => match SetExpiry::parse_getex(&args[1..])? {
+            SetExpiry::KeepTtl => vec![],
+            SetExpiry::None => vec![command(["PERSIST".into(), args[0].clone()])],
+            _ => vec![absolute_expiry(store, db, &args[0])?],
+        },
+        (CommandType::Set, _) => {
+            let relative = args[2..]
+                .iter()
+                .any(|option| matches!(&option.to_ascii_uppercase()[..], b"EX" | b"PX"));
+            match store.expiry(db, &args[0])? {
+                Some(at) if relative => {
+                    let mut rewritten = vec!["SET".into(), args[0].clone(), args[1].clone()];
+                    let mut options = args[2..].iter();
+                    while let Some(option) = options.next() {
+                        match &option.to_ascii_uppercase()[..] {
+                            b"EX" | b"PX" => {
+                                options.next();
+                                rewritten.push("PXAT".into());
+                                rewritten.push(at.to_string().into());
+                            }
+                            _ => rewritten.push(option.clone()),
+                        }
+                    }
+                    vec![command(rewritten)]
+                }
+                _ => vec![request.raw.clone()],
+            }
+        }
+        (CommandType::Restore, _) => {
+            let absolute = args[3..]
+                .iter()
+                .any(|option| option.eq_ignore_ascii_case(b"ABSTTL"));
+            if absolute || &args[1][..] == b"0" {
+                vec![request.raw.clone()]
+            } else {
+                match store.expiry(db, &args[0])? {
+                    Some(at) => {
+                        let mut rewritten = vec![
+                            "RESTORE".into(),
+                            args[0].clone(),
+                            at.to_string().into(),
+                            args[2].clone(),
+                        ];
+                        rewritten.extend(args[3..].iter().cloned());
+                        rewritten.push("ABSTTL".into());
+                        vec![command(rewritten)]
+                    }
+                    // Restored with a TTL that had already passed
+                    None => vec![del_command(args[0].clone())],
+                }
+            }
+        }
+        _ => vec![request.raw.clone()],
+    };
+
+    Ok(commands)
+}
+
 pub struct RedisServer {
     role: Arc<ServerRole>,
     port: u16,
@@ -58,24 +247,35 @@ pub struct RedisServer {
 }
 
 impl RedisServer {
-    pub fn new(role: ServerRole, port: u16) -> Result<Self, RedisError> {
+    pub fn new(role: ServerRole, port: u16, databases: usize) -> Result<Self, RedisError> {
+        let store = GlobalStore::new(databases);
+        let count = store.database_count().to_string();
+        store
+            .config_writer()?
+            .insert(&"databases".into(), &count.into());
+
         Ok(Self {
             port,
             role: Arc::new(role),
             worker_count: WORKER_COUNT,
             pool: BTreeMap::new(),
-            store: Arc::new(GlobalStore::new()),
+            store: Arc::new(store),
         })
     }
 
+    pub fn store(&self) -> Arc<GlobalStore> {
+        Arc::clone(&self.store)
+    }
+
     pub fn start(&mut self, receiver: AsyncReceiver<Request>) {
         match self.role.replica_address() {
             Some((master_addr, master_port)) => {
                 let port = self.port;
-                let store = Arc::clone(&self.store);
+                let worker = Worker::detached(Arc::clone(&self.store), Arc::clone(&self.role));
                 tokio::task::spawn(async move {
                     let mut master_connection =
-                        ReplicaMasterConnection::new(master_addr, master_port, port, store).await?;
+                        ReplicaMasterConnection::new(master_addr, master_port, port, worker)
+                            .await?;
 
                     master_connection.replicate().await
                 });
@@ -85,7 +285,14 @@ impl RedisServer {
             }
         }
 
-        let replicas: ReplicaStore = Arc::new(RwLock::new(HashMap::new()));
+        let replicas: ReplicaStore = Arc::new(RwLock::new(Replicas::default()));
+
+        // Replicas wait for their master's DELs rather than expiring keys on their own clock
+        if self.role.replica_address().is_none() {
+            let store = Arc::clone(&self.store);
+            let replicas = Arc::clone(&replicas);
+            tokio::task::spawn(async move { active_expire(store, replicas).await });
+        }
         let acknowledger = kanal::unbounded_async::<usize>();
 
         for i in 0..self.worker_count {
@@ -95,6 +302,8 @@ impl RedisServer {
                 receiver: receiver.clone(),
                 replicas: Arc::clone(&replicas),
                 acknowledger: acknowledger.clone(),
+                db: 0,
+                events: Vec::new(),
             };
 
             let handle = tokio::task::spawn(async move { worker.start().await });
@@ -109,72 +318,225 @@ impl RedisServer {
         working_dir: Option<PathBuf>,
         dbfile: Option<PathBuf>,
     ) -> Result<(), RedisError> {
-        let mut rdb = self.store.rdb_writer()?;
         let mut cfg = self.store.config_writer()?;
 
-        match working_dir {
+        let path = match working_dir {
             Some(working_dir) => {
                 let wd = working_dir.display().to_string();
-                cfg.set(&"dir".into(), &wd.into(), None)?;
-
-                match dbfile {
-                    Some(dbfile) => {
-                        let db = dbfile.display().to_string();
-                        cfg.set(&"dbfilename".into(), &db.into(), None)?;
-                        let fp = working_dir.join(dbfile);
-                        rdb.load(Some(fp))?;
-                    }
-                    None => {
-                        rdb.load(None)?;
-                    }
-                }
-            }
-            None => {
-                rdb.load(None)?;
+                cfg.insert(&"dir".into(), &wd.into());
+
+                dbfile.map(|dbfile| {
+                    let db = dbfile.display().to_string();
+                    cfg.insert(&"dbfilename".into(), &db.into());
+                    working_dir.join(dbfile)
+                })
             }
+            None => None,
+        };
+
+        self.store.load_rdb(path)
+    }
+
+    pub fn configure_limits(&self, limits: &ProtocolLimits) -> Result<(), RedisError> {
+        let mut cfg = self.store.config_writer()?;
+        let settings = [
+            ("proto-max-bulk-len", limits.max_bulk_len),
+            ("proto-max-multibulk-len", limits.max_multibulk_len),
+            ("client-query-buffer-limit", limits.query_buffer_limit),
+        ];
+
+        for (key, value) in settings {
+            cfg.insert(&key.into(), &value.to_string().into());
         }
 
         Ok(())
     }
+
+    pub fn configure_eviction(&self, max_memory: MaxMemory) -> Result<(), RedisError> {
+        set_eviction(&self.store, max_memory)
+    }
+
+    pub fn configure_notifications(&self, events: KeyspaceEvents) -> Result<(), RedisError> {
+        set_notifications(&self.store, events)
+    }
+}
+
+// Conversion factor from the given GEO unit into metres
+fn distance_unit(unit: &Bytes) -> Result<f64, RedisError> {
+    match &unit.to_ascii_lowercase()[..] {
+        b"m" => Ok(1.0),
+        b"km" => Ok(1000.0),
+        b"ft" => Ok(0.3048),
+        b"mi" => Ok(1609.34),
+        _ => Err(RedisError::Custom(
+            "unsupported unit provided. please use M, KM, FT, MI".into(),
+        )),
+    }
+}
+
+// Index of a database given to SELECT, SWAPDB, MOVE or COPY
+fn database_index(store: &GlobalStore, arg: &Bytes) -> Result<usize, RedisError> {
+    let db = bytes_to_number::<i64>(arg)?;
+    usize::try_from(db)
+        .ok()
+        .filter(|db| *db < store.database_count())
+        .ok_or(RedisError::DbOutOfRange)
+}
+
+// Reply to TTL, PTTL, EXPIRETIME or PEXPIRETIME for a key that expires at `at`. A TTL in
+// seconds is rounded, an absolute time in seconds is rounded down as Redis does
+fn expiry_reply(cmd: CommandType, at: u64, now: u64) -> i64 {
+    let at = at as i64;
+    match cmd {
+        CommandType::Ttl => ((at - now as i64).max(0) + 500) / 1000,
+        CommandType::PTtl => (at - now as i64).max(0),
+        CommandType::ExpireTime => at / 1000,
+        _ => at,
+    }
+}
+
+// Reply shared by the SCAN family, the cursor to continue from followed by what was found
+fn scan_reply(cursor: u64, found: impl Iterator<Item = Value>) -> Value {
+    Value::Array(vec![
+        Value::String(cursor.to_string().into()),
+        Value::Array(found.collect()),
+    ])
+}
+
+// Reply to the HELP subcommand of container commands
+fn help_reply(lines: &[&str]) -> Value {
+    let lines = lines
+        .iter()
+        .map(|line| Value::SimpleString(line.to_string().into()));
+    Value::Array(lines.collect())
 }
 
 async fn replication_handler() -> Result<(), RedisError> {
     Ok(())
 }
 
+// Mirrors Redis' slow expire cycle, run 10 times a second using at most a quarter of the time
+async fn active_expire(store: Arc<GlobalStore>, replicas: ReplicaStore) -> Result<(), RedisError> {
+    let mut interval = tokio::time::interval(ACTIVE_EXPIRE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let expired = store.active_expire_cycle(ACTIVE_EXPIRE_INTERVAL / 4)?;
+        if expired.is_empty() {
+            continue;
+        }
+
+        let mut events = Vec::with_capacity(expired.len());
+        let mut replicas = replicas.write().await;
+        for (db, key) in expired {
+            events.push(KeyspaceEvent::new(db, EventClass::Expired, "expired", &key));
+            replicas.send(db, vec![del_command(key)]).await;
+        }
+        drop(replicas);
+        publish_events(&store, events).await?;
+    }
+}
+
+// Sends keyspace events to the channels the notify-keyspace-events config asks for
+async fn publish_events(store: &GlobalStore, events: Vec<KeyspaceEvent>) -> Result<(), RedisError> {
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    let config = store.keyspace_events()?;
+    let messages: Vec<_> = {
+        let pubsub = store.pubsub_reader()?;
+        events
+            .iter()
+            .flat_map(|event| config.messages(event))
+            .filter_map(|(channel, message)| Some((pubsub.get_topic(&channel)?.clone(), message)))
+            .collect()
+    };
+
+    for (topic, message) in messages {
+        // A subscriber that has gone away mustn't fail the command behind the event
+        let _ = topic.publish_message(message).await;
+    }
+
+    Ok(())
+}
+
+const MASTER_CLIENT_ID: &str = "master";
+
 pub struct Worker {
     store: Arc<GlobalStore>,
     role: Arc<ServerRole>,
     receiver: AsyncReceiver<Request>,
     replicas: ReplicaStore,
     acknowledger: ReplicationAcknowledger,
+    /// Database selected by the client whose command is running
+    db: usize,
+    /// Keyspace events of the running command, published once it finishes
+    events: Vec<KeyspaceEvent>,
 }
 
 impl Worker {
-    pub async fn start(&mut self) -> Result<(), RedisError> {
-        while let Ok((req, client_id, responder)) = self.receiver.recv().await {
-            match self
-                .process_request(req, client_id, responder.clone())
-                .await
-            {
-                Ok(response) => {
-                    responder
-                        .send(response)
-                        .await
-                        .map_err(|_| RedisError::ChannelSendError)?;
-                }
+    /// A worker that doesn't take requests from clients, used by a replica to apply the
+    /// commands sent by its master
+    fn detached(store: Arc<GlobalStore>, role: Arc<ServerRole>) -> Self {
+        let (_, receiver) = kanal::unbounded_async();
+        Self {
+            store,
+            role,
+            receiver,
+            replicas: Arc::new(RwLock::new(Replicas::default())),
+            acknowledger: kanal::unbounded_async(),
+            db: 0,
+            events: Vec::new(),
+        }
+    }
 
-                Err(e) => match e {
-                    RedisError::InsufficientArugments(cmd) => {
-                        responder
-                            .send(vec![Value::Error(
-                                format!("insufficient arugments for command '{cmd}'").into(),
-                            )])
-                            .await
-                            .map_err(|_| RedisError::ChannelSendError)?;
+    /// Applies a command from the master, the reply is only returned for logging
+    async fn apply(&mut self, request: &RedisCommand) -> Result<Vec<Value>, RedisError> {
+        let (responder, _) = kanal::unbounded_async();
+        self.run_command(request, MASTER_CLIENT_ID.into(), responder)
+            .await
+    }
+
+    pub async fn start(&mut self) -> Result<(), RedisError> {
+        while let Ok((requests, client_id, responder)) = self.receiver.recv().await {
+            let mut replies = Vec::with_capacity(requests.len());
+
+            // Pipelined requests are executed in order and their replies sent back together
+            for req in requests {
+                // A panicking command only fails its own request, the worker keeps serving
+                let request = AssertUnwindSafe(self.process_request(
+                    req,
+                    client_id.clone(),
+                    responder.clone(),
+                ))
+                .catch_unwind()
+                .await;
+
+                let response = match request {
+                    Ok(Ok(response)) => response,
+                    Ok(Err(e)) => vec![e.into()],
+                    Err(panic) => {
+                        let reason = panic
+                            .downcast_ref::<&str>()
+                            .map(|s| s.to_string())
+                            .or_else(|| panic.downcast_ref::<String>().cloned())
+                            .unwrap_or_default();
+
+                        vec![RedisError::Internal(reason).into()]
                     }
-                    _ => return Err(e),
-                },
+                };
+
+                let protocol = self.store.client_protocol(&client_id)?;
+                replies.extend(
+                    response
+                        .into_iter()
+                        .map(|value| value.into_protocol(protocol)),
+                );
+            }
+
+            // The client may have disconnected while its request was executing
+            if responder.send(replies).await.is_err() {
+                continue;
             }
         }
 
@@ -191,44 +553,42 @@ impl Worker {
 
         self.add_replica(request.cmd, client_id.clone(), responder.clone())
             .await;
-        self.replicate(&request).await?;
         let response = self.execute_command(&request, client_id, responder).await?;
 
         Ok(response)
     }
 
-    async fn replicate(&self, request: &RedisCommand) -> Result<(), RedisError> {
-        match request.cmd {
-            CommandType::Set => {
-                let replicas = self.replicas.read().await;
-                for sender in replicas.values() {
-                    sender
-                        .send(vec![request.raw.clone()])
-                        .await
-                        .map_err(|_| RedisError::ChannelSendError)?;
-                }
-            }
-            _ => {}
+    /// Sends a write that succeeded to the replicas
+    async fn propagate(
+        &self,
+        request: &RedisCommand,
+        response: &[Value],
+    ) -> Result<(), RedisError> {
+        let commands = replicated_commands(&self.store, self.db, request, response)?;
+        if !commands.is_empty() {
+            self.send_to_replicas(self.db, commands).await;
         }
 
         Ok(())
     }
 
+    async fn send_to_replicas(&self, db: usize, commands: Vec<Value>) {
+        self.replicas.write().await.send(db, commands).await;
+    }
+
     async fn add_replica(
         &mut self,
         request: CommandType,
         client_id: Bytes,
         responder: AsyncSender<Vec<Value>>,
     ) {
-        match request {
-            CommandType::Psync => {
-                let mut replicas = self.replicas.write().await;
+        if request == CommandType::Psync {
+            let mut replicas = self.replicas.write().await;
 
-                if !replicas.contains_key(&client_id) {
-                    replicas.insert(client_id.clone(), responder);
-                }
+            if !replicas.senders.contains_key(&client_id) {
+                replicas.senders.insert(client_id.clone(), responder);
+                replicas.selected_db = None;
             }
-            _ => {}
         }
     }
 
@@ -239,11 +599,11 @@ impl Worker {
     ) -> Result<Option<Vec<Value>>, RedisError> {
         let mut response = Vec::new();
         let mut txn_writer = self.store.transaction_writer()?;
-        if txn_writer.has_transaction(&client_id) {
+        if txn_writer.has_transaction(client_id) {
             match request.cmd {
                 CommandType::Exec | CommandType::Discard => return Ok(None),
                 _ => {
-                    txn_writer.add_to_transaction(&client_id, request.clone());
+                    txn_writer.add_to_transaction(client_id, request.clone());
                     response.push(Value::SimpleString("QUEUED".into()));
                     return Ok(Some(response));
                 }
@@ -261,7 +621,7 @@ impl Worker {
     ) -> Result<Option<Vec<Value>>, RedisError> {
         let subbed = {
             let ps_reader = self.store.pubsub_reader()?;
-            ps_reader.is_subscribed(&client_id)
+            ps_reader.is_subscribed(client_id)
         };
 
         if subbed {
@@ -289,15 +649,17 @@ impl Worker {
             CommandType::Subscribe => {
                 validate_args_len(request, 1)?;
                 let channel_name = &request.args[0];
+                let protocol = self.store.client_protocol(&client_id)?;
                 let mut ps_writer = self.store.pubsub_writer()?;
-                let subs = ps_writer.subscribe(channel_name.clone(), &client_id, responder);
+                let subs =
+                    ps_writer.subscribe(channel_name.clone(), &client_id, responder, protocol);
                 let msg = vec![
                     Value::String("subscribe".into()),
                     Value::String(channel_name.clone()),
                     Value::Integer(subs as i64),
                 ];
 
-                response.push(Value::Array(msg));
+                response.push(Value::Push(msg));
             }
             CommandType::Unsubscribe => {
                 validate_args_len(request, 1)?;
@@ -310,7 +672,7 @@ impl Worker {
                     Value::Integer(subs as i64),
                 ];
 
-                response.push(Value::Array(msg));
+                response.push(Value::Push(msg));
             }
             invalid => {
                 let err_msg = format!("ERR Can't execute '{invalid}' in subscribed mode");
@@ -336,17 +698,17 @@ impl Worker {
                     let password = &request.args[1];
 
                     if !user_writer.authenticate(username, password, client_id) {
-                        response.push(Value::Error(
-                            "WRONGPASS invalid username-password pair or user is disabled".into(),
-                        ));
+                        response.push(RedisError::WrongPass.into());
                     } else {
                         response.push(Value::ok());
                     }
 
                     return Ok(Some(response));
                 }
+                // HELLO can authenticate with its AUTH option so is checked when executed
+                _ if request.cmd.spec().has_flag(CommandFlag::NoAuth) => {}
                 _ => {
-                    response.push(Value::Error("NOAUTH Authentication required.".into()));
+                    response.push(RedisError::NoAuth.into());
                     return Ok(Some(response));
                 }
             }
@@ -376,122 +738,527 @@ impl Worker {
             return Ok(resp);
         }
 
+        if let Some(resp) = self.check_memory(request).await? {
+            return Ok(resp);
+        }
+
+        self.run_command(request, client_id, responder).await
+    }
+
+    /// Evicts keys if memory is over maxmemory. Commands that may add to memory are refused
+    /// if that isn't enough. Replicas leave eviction to their master, which sends the DELs
+    async fn check_memory(
+        &mut self,
+        request: &RedisCommand,
+    ) -> Result<Option<Vec<Value>>, RedisError> {
+        if self.role.replica_address().is_some() {
+            return Ok(None);
+        }
+
+        let eviction = self.store.evict()?;
+        let mut events = Vec::with_capacity(eviction.evicted.len() + eviction.expired.len());
+        for (db, key) in eviction.expired {
+            events.push(KeyspaceEvent::new(db, EventClass::Expired, "expired", &key));
+            self.send_to_replicas(db, vec![del_command(key)]).await;
+        }
+        for (db, key) in eviction.evicted {
+            events.push(KeyspaceEvent::new(db, EventClass::Evicted, "evicted", &key));
+            self.send_to_replicas(db, vec![del_command(key)]).await;
+        }
+        publish_events(&self.store, events).await?;
+
+        if eviction.over_limit && request.cmd.spec().has_flag(CommandFlag::DenyOom) {
+            return Ok(Some(vec![RedisError::OutOfMemory.into()]));
+        }
+
+        Ok(None)
+    }
+
+    /// Executes a command without the per connection checks and propagates it to replicas
+    /// if it's a write. Commands a replica receives from its master are applied with this
+    async fn run_command(
+        &mut self,
+        request: &RedisCommand,
+        client_id: Bytes,
+        responder: AsyncSender<Vec<Value>>,
+    ) -> Result<Vec<Value>, RedisError> {
+        self.db = self.store.client_db(&client_id)?;
+        let spec = request.cmd.spec();
+        let keys = spec.keys(&request.args);
+        for key in keys.iter() {
+            if self.store.prepare_key(self.db, key)? {
+                self.notify(EventClass::Expired, "expired", key);
+                self.send_to_replicas(self.db, vec![del_command((*key).clone())])
+                    .await;
+            }
+        }
+
+        // Keys missing before the command are new if a write creates them, and misses if
+        // a read looks for them
+        let config = self.store.keyspace_events()?;
+        let write = spec.has_flag(CommandFlag::Write);
+        let wanted = if write {
+            config.wants(EventClass::New)
+        } else {
+            config.wants(EventClass::KeyMiss)
+                && !matches!(request.cmd, CommandType::Exists | CommandType::Type)
+        };
+        let mut missing = Vec::new();
+        if wanted {
+            for key in keys.iter() {
+                if !self.store.exists(self.db, key)? {
+                    missing.push(*key);
+                }
+            }
+        }
+        if !write {
+            for key in missing.drain(..) {
+                self.notify(EventClass::KeyMiss, "keymiss", key);
+            }
+        }
+        let created_at = self.events.len();
+
+        let response = self.dispatch_command(request, client_id, responder).await;
+        let mut created = Vec::new();
+        for key in missing {
+            if self.store.exists(self.db, key)? {
+                created.push(KeyspaceEvent::new(self.db, EventClass::New, "new", key));
+            }
+        }
+        self.events.splice(created_at..created_at, created);
+        publish_events(&self.store, std::mem::take(&mut self.events)).await?;
+        let response = response?;
+
+        if write {
+            self.propagate(request, &response).await?;
+        }
+
+        Ok(response)
+    }
+
+    /// One section of INFO with its header, None for sections that aren't known
+    fn info_section(&self, section: &str) -> Result<Option<String>, RedisError> {
+        let fields = match section {
+            "replication" => format!(
+                "# Replication\r\nrole:{}\r\nmaster_replid:8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb\r\nmaster_repl_offset:0\r\n",
+                self.role
+            ),
+            "memory" => {
+                let max_memory = self.store.max_memory()?;
+                format!(
+                    "# Memory\r\nused_memory:{}\r\nused_memory_peak:{}\r\nmaxmemory:{}\r\nmaxmemory_policy:{}\r\n",
+                    alloc::allocated(),
+                    alloc::peak_allocated(),
+                    max_memory.limit,
+                    max_memory.policy
+                )
+            }
+            "stats" => format!("# Stats\r\nevicted_keys:{}\r\n", self.store.evicted_keys()),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(fields))
+    }
+
+    /// Queues a keyspace event on a key of the selected database
+    fn notify(&mut self, class: EventClass, event: &'static str, key: &Bytes) {
+        self.events
+            .push(KeyspaceEvent::new(self.db, class, event, key));
+    }
+
+    async fn dispatch_command(
+        &mut self,
+        request: &RedisCommand,
+        client_id: Bytes,
+        responder: AsyncSender<Vec<Value>>,
+    ) -> Result<Vec<Value>, RedisError> {
         let mut response = Vec::new();
 
         match request.cmd {
             CommandType::Ping => response.push(Value::SimpleString("PONG".into())),
             CommandType::Echo => {
-                validate_args_len(&request, 1)?;
+                validate_args_len(request, 1)?;
 
                 let msg = &request.args[0];
                 response.push(Value::String(msg.clone()));
             }
             CommandType::Get => {
-                validate_args_len(&request, 1)?;
+                validate_args_len(request, 1)?;
 
                 let key = &request.args[0];
-                let store = self.store.map_reader()?;
+                let keyspace = self.store.keyspace_reader(self.db)?;
 
-                // TODO: Combine Memstore with RDB
-                match store.get(key) {
+                match keyspace.get::<Bytes>(key)? {
                     Some(value) => response.push(Value::String(value.clone())),
-                    None => {
-                        let rdb = self.store.rdb_reader()?;
-                        match rdb.get(key) {
-                            Some(value) => response.push(Value::String(value)),
-                            None => response.push(Value::NullString),
-                        }
-                        // response.push(Value::NullString)
-                    }
+                    None => response.push(Value::NullString),
                 }
             }
             CommandType::Set => {
-                validate_args_len(&request, 2)?;
+                validate_args_len(request, 2)?;
 
                 let key = &request.args[0];
                 let value = &request.args[1];
-                let ttl = if request.args.len() == 4 {
-                    Some(request.args[3].clone())
+                let options = SetOptions::parse(&request.args[2..])?;
+
+                let result = self
+                    .store
+                    .keyspace_writer(self.db)?
+                    .set(key, value, &options)?;
+                if result.written {
+                    self.notify(EventClass::String, "set", key);
+                    if matches!(options.expiry, SetExpiry::In(_) | SetExpiry::At(_)) {
+                        self.notify(EventClass::Generic, "expire", key);
+                    }
+                }
+                if options.get {
+                    response.push(result.previous.map_or(Value::NullString, Value::String));
+                } else if result.written {
+                    response.push(Value::ok());
                 } else {
-                    None
+                    response.push(Value::NullString);
+                }
+            }
+            CommandType::MGet => {
+                validate_args_len(request, 1)?;
+
+                // Keys holding other types read as nil rather than failing the command
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                let values = request
+                    .args
+                    .iter()
+                    .map(|key| match keyspace.data(key) {
+                        Some(Data::String(value)) => Value::String(value.clone()),
+                        _ => Value::NullString,
+                    })
+                    .collect();
+                response.push(Value::Array(values));
+            }
+
+            CommandType::MSet | CommandType::MSetNx => {
+                if request.args.is_empty() || !request.args.len().is_multiple_of(2) {
+                    return Err(RedisError::InsufficientArugments(request.cmd));
+                }
+
+                let pairs: Vec<(Bytes, Bytes)> = request
+                    .args
+                    .chunks_exact(2)
+                    .map(|pair| (pair[0].clone(), pair[1].clone()))
+                    .collect();
+
+                let only_new = request.cmd == CommandType::MSetNx;
+                let written = self.store.keyspace_writer(self.db)?.mset(&pairs, only_new);
+                if written {
+                    for (key, _) in pairs.iter() {
+                        self.notify(EventClass::String, "set", key);
+                    }
+                }
+                match request.cmd {
+                    CommandType::MSet => response.push(Value::ok()),
+                    _ => response.push(Value::Integer(written as i64)),
+                }
+            }
+
+            CommandType::GetSet => {
+                validate_args_len(request, 2)?;
+
+                let key = &request.args[0];
+                let value = &request.args[1];
+                let options = SetOptions {
+                    get: true,
+                    ..Default::default()
                 };
 
-                let mut store = self.store.map_writer()?;
-                match store.set(key, value, ttl) {
-                    Ok(_) => response.push(Value::ok()),
-                    Err(e) => response.push(Value::Error(e.to_string().into())),
+                let result = self
+                    .store
+                    .keyspace_writer(self.db)?
+                    .set(key, value, &options)?;
+                self.notify(EventClass::String, "set", key);
+                response.push(result.previous.map_or(Value::NullString, Value::String));
+            }
+
+            CommandType::GetDel => {
+                validate_args_len(request, 1)?;
+
+                let value = self
+                    .store
+                    .keyspace_writer(self.db)?
+                    .get_del(&request.args[0])?;
+                if value.is_some() {
+                    self.notify(EventClass::Generic, "del", &request.args[0]);
                 }
+                response.push(value.map_or(Value::NullString, Value::String));
             }
-            CommandType::RPush => {
-                validate_args_len(&request, 2)?;
 
-                let mut size = 0;
+            CommandType::GetEx => {
+                validate_args_len(request, 1)?;
+
                 let key = &request.args[0];
-                {
-                    let mut store = self.store.list_writer()?;
+                let expiry = SetExpiry::parse_getex(&request.args[1..])?;
+                let value = self.store.keyspace_writer(self.db)?.get_ex(key, expiry)?;
+                if value.is_some() {
+                    match expiry {
+                        SetExpiry::KeepTtl => {}
+                        SetExpiry::None => self.notify(EventClass::Generic, "persist", key),
+                        // A deadline already passed deletes the key
+                        _ if !self.store.exists(self.db, key)? => {
+                            self.notify(EventClass::Generic, "del", key)
+                        }
+                        _ => self.notify(EventClass::Generic, "expire", key),
+                    }
+                }
+                response.push(value.map_or(Value::NullString, Value::String));
+            }
+
+            CommandType::Append => {
+                validate_args_len(request, 2)?;
+
+                let max_len = self.store.max_string_len()?;
+                let mut keyspace = self.store.keyspace_writer(self.db)?;
+                let len = keyspace.append(&request.args[0], &request.args[1], max_len)?;
+                drop(keyspace);
+                self.notify(EventClass::String, "append", &request.args[0]);
+                response.push(Value::Integer(len as i64));
+            }
+
+            CommandType::StrLen => {
+                validate_args_len(request, 1)?;
+
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                response.push(Value::Integer(keyspace.strlen(&request.args[0])? as i64));
+            }
+
+            CommandType::GetRange => {
+                validate_args_len(request, 3)?;
+
+                let start = bytes_to_number::<i64>(&request.args[1])?;
+                let end = bytes_to_number::<i64>(&request.args[2])?;
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                response.push(Value::String(keyspace.getrange(
+                    &request.args[0],
+                    start,
+                    end,
+                )?));
+            }
+
+            CommandType::SetRange => {
+                validate_args_len(request, 3)?;
+
+                let offset = bytes_to_number::<i64>(&request.args[1])?;
+                let offset = usize::try_from(offset)
+                    .map_err(|_| RedisError::Custom("offset is out of range".into()))?;
+
+                let max_len = self.store.max_string_len()?;
+                let mut keyspace = self.store.keyspace_writer(self.db)?;
+                let len = keyspace.setrange(&request.args[0], offset, &request.args[2], max_len)?;
+                drop(keyspace);
+                if !request.args[2].is_empty() {
+                    self.notify(EventClass::String, "setrange", &request.args[0]);
+                }
+                response.push(Value::Integer(len as i64));
+            }
+
+            CommandType::SetBit => {
+                validate_args_len(request, 3)?;
+
+                let max_len = self.store.max_string_len()?;
+                let offset = parse_offset(&request.args[1], None, max_len)?;
+                let bit = parse_bit(&request.args[2])?;
+                let previous =
+                    self.store
+                        .keyspace_writer(self.db)?
+                        .setbit(&request.args[0], offset, bit)?;
+                self.notify(EventClass::String, "setbit", &request.args[0]);
+                response.push(Value::Integer(previous as i64));
+            }
+
+            CommandType::GetBit => {
+                validate_args_len(request, 2)?;
+
+                let max_len = self.store.max_string_len()?;
+                let offset = parse_offset(&request.args[1], None, max_len)?;
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                let value = keyspace
+                    .get::<Bytes>(&request.args[0])?
+                    .map_or(&[][..], |v| &v[..]);
+                response.push(Value::Integer(getbit(value, offset) as i64));
+            }
+
+            CommandType::BitCount => {
+                validate_args_len(request, 1)?;
+                if request.args.len() == 2 {
+                    return Err(RedisError::Syntax);
+                }
+
+                let range = BitRange::parse(&request.args[1..])?;
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                let value = keyspace
+                    .get::<Bytes>(&request.args[0])?
+                    .map_or(&[][..], |v| &v[..]);
+                response.push(Value::Integer(bitcount(value, &range) as i64));
+            }
+
+            CommandType::BitPos => {
+                validate_args_len(request, 2)?;
+
+                let bit = parse_bit(&request.args[1])
+                    .map_err(|_| RedisError::Custom("The bit argument must be 1 or 0.".into()))?;
+                let range = BitRange::parse(&request.args[2..])?;
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                let pos = match keyspace.get::<Bytes>(&request.args[0])? {
+                    Some(value) => bitpos(value, bit, &range),
+                    // A missing key is an empty string padded with zeros
+                    None if bit => -1,
+                    None => 0,
+                };
+                response.push(Value::Integer(pos));
+            }
+
+            CommandType::BitOp => {
+                validate_args_len(request, 3)?;
+
+                let op = BitOp::parse(&request.args[0], request.args.len() - 2)?;
+                let destination = &request.args[1];
+                let mut keyspace = self.store.keyspace_writer(self.db)?;
+                let existed = keyspace.contains(destination);
+                let len = keyspace.bitop(op, destination, &request.args[2..])?;
+                drop(keyspace);
+                // An empty result deletes the destination
+                if len > 0 {
+                    self.notify(EventClass::String, "set", destination);
+                } else if existed {
+                    self.notify(EventClass::Generic, "del", destination);
+                }
+                response.push(Value::Integer(len as i64));
+            }
+
+            CommandType::BitField | CommandType::BitFieldRo => {
+                validate_args_len(request, 1)?;
 
-                    for value in request.args[1..].iter() {
-                        size = store.append(key, value);
+                let max_len = self.store.max_string_len()?;
+                let read_only = request.cmd == CommandType::BitFieldRo;
+                let ops = BitfieldOp::parse_all(&request.args[1..], max_len, read_only)?;
+                let replies = match read_only {
+                    true => {
+                        let keyspace = self.store.keyspace_reader(self.db)?;
+                        let value = keyspace
+                            .get::<Bytes>(&request.args[0])?
+                            .map_or(&[][..], |v| &v[..]);
+                        ops.iter().map(|op| Some(op.read(value))).collect()
                     }
+                    false => self
+                        .store
+                        .keyspace_writer(self.db)?
+                        .bitfield(&request.args[0], &ops)?,
+                };
+                if !read_only && ops.iter().any(BitfieldOp::writes) {
+                    self.notify(EventClass::String, "setbit", &request.args[0]);
+                }
+                let replies = replies
+                    .into_iter()
+                    .map(|reply| reply.map_or(Value::NullString, Value::Integer))
+                    .collect();
+                response.push(Value::Array(replies));
+            }
+
+            CommandType::PfAdd => {
+                validate_args_len(request, 1)?;
+
+                let sparse_max = self.store.hll_sparse_max_bytes()?;
+                let mut keyspace = self.store.keyspace_writer(self.db)?;
+                let updated = keyspace.pfadd(&request.args[0], &request.args[1..], sparse_max)?;
+                drop(keyspace);
+                if updated {
+                    self.notify(EventClass::String, "pfadd", &request.args[0]);
                 }
+                response.push(Value::Integer(updated as i64));
+            }
+
+            CommandType::PfCount => {
+                validate_args_len(request, 1)?;
 
-                if let Some(sender) = self.store.client_sender(key)? {
+                let count = self
+                    .store
+                    .keyspace_writer(self.db)?
+                    .pfcount(&request.args)?;
+                response.push(Value::Integer(count as i64));
+            }
+
+            CommandType::PfMerge => {
+                validate_args_len(request, 1)?;
+
+                let sparse_max = self.store.hll_sparse_max_bytes()?;
+                let mut keyspace = self.store.keyspace_writer(self.db)?;
+                keyspace.pfmerge(&request.args[0], &request.args[1..], sparse_max)?;
+                drop(keyspace);
+                self.notify(EventClass::String, "pfadd", &request.args[0]);
+                response.push(Value::ok());
+            }
+
+            CommandType::RPush => {
+                validate_args_len(request, 2)?;
+
+                let key = &request.args[0];
+                let size = self
+                    .store
+                    .keyspace_writer(self.db)?
+                    .rpush(key, &request.args[1..])?;
+                self.notify(EventClass::List, "rpush", key);
+
+                if let Some(sender) = self.store.client_sender(self.db, key)? {
                     sender
                         .send(key.clone())
                         .await
                         .map_err(|_| RedisError::ChannelSendError)?;
                 } else {
                     let mut notifier = self.store.notifier_writer()?;
-                    notifier.add_to_backlog(key.clone());
+                    notifier.add_to_backlog(self.db, key.clone());
                 }
 
                 response.push(Value::Integer(size as i64));
             }
             CommandType::LPush => {
-                validate_args_len(&request, 2)?;
+                validate_args_len(request, 2)?;
 
-                let mut size = 0;
                 let key = &request.args[0];
-                let mut store = self.store.list_writer()?;
-
-                for value in request.args[1..].iter() {
-                    size = store.prepend(key, value);
-                }
+                let mut keyspace = self.store.keyspace_writer(self.db)?;
+                let size = keyspace.lpush(key, &request.args[1..])?;
+                drop(keyspace);
+                self.notify(EventClass::List, "lpush", key);
 
                 response.push(Value::Integer(size as i64));
             }
             CommandType::LRange | CommandType::RRange => {
-                validate_args_len(&request, 3)?;
+                validate_args_len(request, 3)?;
 
                 let key = &request.args[0];
                 let start = bytes_to_number(&request.args[1])?;
                 let end = bytes_to_number(&request.args[2])?;
 
-                let store = self.store.list_reader()?;
-                match store.slice(key, start, end) {
-                    Some(slice) => {
-                        let values = slice
-                            .iter()
-                            .map(|v| Value::String(v.clone()))
-                            .collect::<Vec<Value>>();
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                let slice = keyspace.lrange(key, start, end)?;
+                if slice.is_empty() {
+                    response.push(Value::EmptyArray);
+                } else {
+                    let values = slice
+                        .iter()
+                        .map(|v| Value::String(v.clone()))
+                        .collect::<Vec<Value>>();
 
-                        response.push(Value::Array(values));
-                    }
-                    None => response.push(Value::EmptyArray),
+                    response.push(Value::Array(values));
                 }
             }
             CommandType::LLen => {
-                validate_args_len(&request, 1)?;
+                validate_args_len(request, 1)?;
 
                 let key = &request.args[0];
-                let store = self.store.list_reader()?;
+                let keyspace = self.store.keyspace_reader(self.db)?;
 
-                let size = store.len(key);
+                let size = keyspace.llen(key)?;
 
                 response.push(Value::Integer(size as i64));
             }
             CommandType::LPop => {
-                validate_args_len(&request, 1)?;
+                validate_args_len(request, 1)?;
 
                 let key = &request.args[0];
                 let to_remove = match request.args.get(1) {
@@ -499,9 +1266,18 @@ impl Worker {
                     Some(total) => bytes_to_number::<usize>(total)?,
                 };
 
-                let mut store = self.store.list_writer()?;
+                let mut keyspace = self.store.keyspace_writer(self.db)?;
+                let popped = keyspace.lpop(key, to_remove)?;
+                let emptied = !keyspace.contains(key);
+                drop(keyspace);
+                if popped.as_ref().is_some_and(|popped| !popped.is_empty()) {
+                    self.notify(EventClass::List, "lpop", key);
+                    if emptied {
+                        self.notify(EventClass::Generic, "del", key);
+                    }
+                }
 
-                match store.remove(key, to_remove) {
+                match popped {
                     Some(elements) => match elements.len() {
                         0 => response.push(Value::NullString),
                         1 => response.push(Value::String(elements[0].clone())),
@@ -517,87 +1293,494 @@ impl Worker {
                     None => response.push(Value::NullString),
                 }
             }
-            CommandType::BLPop => {
-                validate_args_len(&request, 2)?;
-                let keys = &request.args[..&request.args.len() - 1];
-                let timeout = &request.args.last().unwrap();
-                // FIXME: Issue here is that the RPUSH is happening before we register
-                let rx = self
-                    .store
-                    .register_interest(client_id.clone(), keys)
-                    .await?;
+            CommandType::BLPop => {
+                validate_args_len(request, 2)?;
+                let keys = &request.args[..&request.args.len() - 1];
+                let timeout = &request.args.last().unwrap();
+                // FIXME: Issue here is that the RPUSH is happening before we register
+                let rx = self
+                    .store
+                    .register_interest(client_id.clone(), self.db, keys)
+                    .await?;
+
+                let timeout = bytes_to_number::<f64>(timeout)?;
+                if timeout == 0.0 {
+                    let key = rx.recv().await.map_err(|_| RedisError::ChannelSendError)?;
+                    let mut writer = self.store.keyspace_writer(self.db)?;
+                    match writer.lpop_one(&key)? {
+                        Some(value) => response.push(Value::Array(vec![
+                            Value::String(key.clone()),
+                            Value::String(value),
+                        ])),
+                        None => response.push(Value::NullString),
+                    }
+                } else {
+                    match tokio::time::timeout(
+                        Duration::from_millis((timeout * 1000.0) as u64),
+                        rx.recv(),
+                    )
+                    .await
+                    {
+                        Ok(Ok(key)) => {
+                            let mut writer = self.store.keyspace_writer(self.db)?;
+
+                            match writer.lpop_one(&key)? {
+                                Some(value) => response.push(Value::Array(vec![
+                                    Value::String(key.clone()),
+                                    Value::String(value),
+                                ])),
+                                None => response.push(Value::NullArray),
+                            }
+                        }
+                        _ => {
+                            let mut notifier = self.store.notifier_writer()?;
+                            let mut writer = self.store.keyspace_writer(self.db)?;
+                            let db = self.db;
+                            let (backlog, others) = notifier
+                                .backlog
+                                .drain(..)
+                                .partition::<Vec<_>, _>(|(pushed, _)| *pushed == db);
+                            notifier.backlog = others;
+                            if !backlog.is_empty() {
+                                for (_, key) in backlog {
+                                    match writer.lpop_one(&key)? {
+                                        Some(value) => response.push(Value::Array(vec![
+                                            Value::String(key.clone()),
+                                            Value::String(value),
+                                        ])),
+                                        None => response.push(Value::NullArray),
+                                    }
+                                }
+                            } else {
+                                response.push(Value::NullArray);
+                            }
+                        }
+                    }
+                }
+
+                self.store.unregister_interest(&client_id)?;
+
+                let popped: Vec<Bytes> = response
+                    .iter()
+                    .filter_map(|value| match value {
+                        Value::Array(pair) => match pair.first() {
+                            Some(Value::String(key)) => Some(key.clone()),
+                            _ => None,
+                        },
+                        _ => None,
+                    })
+                    .collect();
+                for key in popped.iter() {
+                    self.notify(EventClass::List, "lpop", key);
+                    if !self.store.exists(self.db, key)? {
+                        self.notify(EventClass::Generic, "del", key);
+                    }
+                }
+            }
+
+            CommandType::Type => {
+                validate_args_len(request, 1)?;
+                let key = &request.args[0];
+                let key_type = self.store.key_type(self.db, key)?;
+                response.push(Value::SimpleString(key_type));
+            }
+
+            CommandType::Del | CommandType::Unlink => {
+                validate_args_len(request, 1)?;
+
+                let mut keyspace = self.store.keyspace_writer(self.db)?;
+                let removed: Vec<(&Bytes, Data)> = request
+                    .args
+                    .iter()
+                    .filter_map(|key| Some((key, keyspace.remove(key)?)))
+                    .collect();
+                drop(keyspace);
+                response.push(Value::Integer(removed.len() as i64));
+                for (key, _) in removed.iter() {
+                    self.notify(EventClass::Generic, "del", key);
+                }
+
+                if request.cmd == CommandType::Unlink {
+                    let large: Vec<Data> = removed
+                        .into_iter()
+                        .map(|(_, data)| data)
+                        .filter(|data| data.free_effort() > LAZYFREE_THRESHOLD)
+                        .collect();
+                    if !large.is_empty() {
+                        tokio::task::spawn_blocking(move || drop(large));
+                    }
+                }
+            }
+
+            CommandType::Exists | CommandType::Touch => {
+                validate_args_len(request, 1)?;
+
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                // TOUCH counts as an access of the keys, EXISTS doesn't
+                let touch = request.cmd == CommandType::Touch;
+                let count = request
+                    .args
+                    .iter()
+                    .filter(|key| {
+                        if touch {
+                            keyspace.touch(key)
+                        } else {
+                            keyspace.contains(key)
+                        }
+                    })
+                    .count();
+                response.push(Value::Integer(count as i64));
+            }
+
+            CommandType::Rename | CommandType::RenameNx => {
+                validate_args_len(request, 2)?;
+
+                let only_new = request.cmd == CommandType::RenameNx;
+                let mut keyspace = self.store.keyspace_writer(self.db)?;
+                let (src, dst) = (&request.args[0], &request.args[1]);
+                let renamed = keyspace.rename(src, dst, only_new)?;
+                drop(keyspace);
+                if renamed && src != dst {
+                    self.notify(EventClass::Generic, "rename_from", src);
+                    self.notify(EventClass::Generic, "rename_to", dst);
+                }
+                match request.cmd {
+                    CommandType::Rename => response.push(Value::ok()),
+                    _ => response.push(Value::Integer(renamed as i64)),
+                }
+            }
+
+            CommandType::Copy => {
+                validate_args_len(request, 2)?;
+
+                let mut replace = false;
+                let mut db = self.db;
+                let mut options = request.args[2..].iter();
+                while let Some(option) = options.next() {
+                    match &option.to_ascii_uppercase()[..] {
+                        b"REPLACE" => replace = true,
+                        b"DB" => {
+                            let index = options.next().ok_or(RedisError::Syntax)?;
+                            db = database_index(&self.store, index)?;
+                        }
+                        _ => return Err(RedisError::Syntax),
+                    }
+                }
+
+                let (src, dst) = (&request.args[0], &request.args[1]);
+                let copied = if db == self.db {
+                    if src == dst {
+                        return Err(RedisError::SameObject);
+                    }
+                    self.store.keyspace_writer(db)?.copy(src, dst, replace)
+                } else {
+                    let (source, mut target) = self.store.keyspace_pair(self.db, db)?;
+                    source.copy_to(src, &mut target, dst, replace)
+                };
+                if copied {
+                    self.events
+                        .push(KeyspaceEvent::new(db, EventClass::Generic, "copy_to", dst));
+                }
+                response.push(Value::Integer(copied as i64));
+            }
+
+            CommandType::Select => {
+                validate_args_len(request, 1)?;
+
+                let db = database_index(&self.store, &request.args[0])?;
+                self.store.select(&client_id, db)?;
+                self.db = db;
+                response.push(Value::ok());
+            }
+
+            CommandType::SwapDb => {
+                validate_args_len(request, 2)?;
+
+                let first = database_index(&self.store, &request.args[0])?;
+                let second = database_index(&self.store, &request.args[1])?;
+                if first != second {
+                    let (mut first, mut second) = self.store.keyspace_pair(first, second)?;
+                    std::mem::swap(&mut *first, &mut *second);
+                }
+                response.push(Value::ok());
+            }
+
+            CommandType::Move => {
+                validate_args_len(request, 2)?;
+
+                let db = database_index(&self.store, &request.args[1])?;
+                if db == self.db {
+                    return Err(RedisError::SameObject);
+                }
+
+                let (mut source, mut target) = self.store.keyspace_pair(self.db, db)?;
+                let key = &request.args[0];
+                let moved = source.move_to(key, &mut target);
+                drop((source, target));
+                if moved {
+                    self.notify(EventClass::Generic, "move_from", key);
+                    self.events
+                        .push(KeyspaceEvent::new(db, EventClass::Generic, "move_to", key));
+                }
+                response.push(Value::Integer(moved as i64));
+            }
+
+            CommandType::FlushDb | CommandType::FlushAll => {
+                let lazy = match request.args.first() {
+                    None => false,
+                    Some(mode) if mode.eq_ignore_ascii_case(b"ASYNC") => true,
+                    Some(mode) if mode.eq_ignore_ascii_case(b"SYNC") => false,
+                    Some(_) => return Err(RedisError::Syntax),
+                };
+                if request.args.len() > 1 {
+                    return Err(RedisError::Syntax);
+                }
+
+                let db = (request.cmd == CommandType::FlushDb).then_some(self.db);
+                let flushed = self.store.flush(db)?;
+                if lazy {
+                    tokio::task::spawn_blocking(move || drop(flushed));
+                }
+                response.push(Value::ok());
+            }
 
-                let timeout = bytes_to_number::<f64>(timeout)?;
-                if timeout == 0.0 {
-                    let key = rx.recv().await.map_err(|_| RedisError::ChannelSendError)?;
-                    let mut writer = self.store.list_writer()?;
-                    match writer.remove_single(&key) {
-                        Some(value) => response.push(Value::Array(vec![
-                            Value::String(key.clone()),
-                            Value::String(value),
-                        ])),
-                        None => response.push(Value::NullString),
+            CommandType::DbSize => {
+                let count = self.store.keyspace_reader(self.db)?.key_count();
+                response.push(Value::Integer(count as i64));
+            }
+
+            CommandType::Object => {
+                validate_args_len(request, 1)?;
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                let reply = match (&request.args[0].to_ascii_uppercase()[..], &request.args[1..]) {
+                    (b"ENCODING", [key]) => keyspace
+                        .peek(key)
+                        .map(|data| Value::String(data.encoding().into())),
+                    (b"REFCOUNT", [key]) => keyspace.peek(key).map(|data| Value::Integer(data.refcount())),
+                    (b"IDLETIME", [key]) => keyspace
+                        .idle_ms(key)
+                        .map(|idle| Value::Integer((idle / 1000) as i64)),
+                    (b"FREQ", [key]) => keyspace
+                        .frequency(key)
+                        .map(|frequency| Value::Integer(frequency as i64)),
+                    (b"HELP", []) => Some(help_reply(&[
+                        "OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                        "ENCODING <key>",
+                        "    Return the kind of internal representation used in order to store the value",
+                        "    associated with a <key>.",
+                        "FREQ <key>",
+                        "    Return the access frequency index of the <key>. The returned integer is",
+                        "    proportional to the logarithm of the recent access frequency of the key.",
+                        "IDLETIME <key>",
+                        "    Return the idle time of the <key>, that is the approximated number of",
+                        "    seconds elapsed since the last access to the key.",
+                        "REFCOUNT <key>",
+                        "    Return the number of references of the value associated with the specified",
+                        "    <key>.",
+                        "HELP",
+                        "    Print this help.",
+                    ])),
+                    _ => {
+                        return Err(RedisError::Custom(format!(
+                            "unknown subcommand or wrong number of arguments for '{}'. Try OBJECT HELP.",
+                            String::from_utf8_lossy(&request.args[0])
+                        )))
                     }
-                } else {
-                    match tokio::time::timeout(
-                        Duration::from_millis((timeout * 1000.0) as u64),
-                        rx.recv(),
-                    )
-                    .await
-                    {
-                        Ok(Ok(key)) => {
-                            let mut writer = self.store.list_writer()?;
+                };
+                response.push(reply.unwrap_or(Value::NullString));
+            }
 
-                            match writer.remove_single(&key) {
-                                Some(value) => response.push(Value::Array(vec![
-                                    Value::String(key.clone()),
-                                    Value::String(value),
-                                ])),
-                                None => response.push(Value::NullArray),
+            CommandType::Memory => {
+                validate_args_len(request, 1)?;
+                match &request.args[0].to_ascii_uppercase()[..] {
+                    b"USAGE" => {
+                        validate_args_len(request, 2)?;
+                        let samples = match &request.args[2..] {
+                            [] => DEFAULT_SAMPLES,
+                            [option, samples] if option.eq_ignore_ascii_case(b"SAMPLES") => {
+                                bytes_to_number::<usize>(samples)?
                             }
+                            _ => return Err(RedisError::Syntax),
+                        };
+
+                        let keyspace = self.store.keyspace_reader(self.db)?;
+                        let usage = keyspace.memory_usage(&request.args[1], samples);
+                        response.push(
+                            usage.map_or(Value::NullString, |bytes| Value::Integer(bytes as i64)),
+                        );
+                    }
+                    b"STATS" => {
+                        let stats = self.store.memory_stats()?;
+                        let field = |name: &str, value: Value| {
+                            (Value::String(name.to_string().into()), value)
+                        };
+                        let mut reply = vec![
+                            field(
+                                "peak.allocated",
+                                Value::Integer(stats.peak_allocated as i64),
+                            ),
+                            field(
+                                "total.allocated",
+                                Value::Integer(stats.total_allocated as i64),
+                            ),
+                            field(
+                                "startup.allocated",
+                                Value::Integer(stats.startup_allocated as i64),
+                            ),
+                            field("overhead.total", Value::Integer(stats.overhead() as i64)),
+                            field("keys.count", Value::Integer(stats.keys as i64)),
+                            field(
+                                "keys.bytes-per-key",
+                                Value::Integer(stats.bytes_per_key() as i64),
+                            ),
+                            field("dataset.bytes", Value::Integer(stats.dataset() as i64)),
+                            field(
+                                "dataset.percentage",
+                                Value::Double(stats.dataset_percentage()),
+                            ),
+                            field("peak.percentage", Value::Double(stats.peak_percentage())),
+                        ];
+                        for (db, main, expires) in &stats.databases {
+                            let tables = Value::Map(vec![
+                                field("overhead.hashtable.main", Value::Integer(*main as i64)),
+                                field(
+                                    "overhead.hashtable.expires",
+                                    Value::Integer(*expires as i64),
+                                ),
+                            ]);
+                            reply.push(field(&format!("db.{db}"), tables));
                         }
-                        _ => {
-                            let mut notifier = self.store.notifier_writer()?;
-                            let mut writer = self.store.list_writer()?;
-                            if !notifier.backlog.is_empty() {
-                                for key in notifier.backlog.drain(..) {
-                                    match writer.remove_single(&key) {
-                                        Some(value) => response.push(Value::Array(vec![
-                                            Value::String(key.clone()),
-                                            Value::String(value),
-                                        ])),
-                                        None => response.push(Value::NullArray),
-                                    }
-                                }
-                            } else {
-                                response.push(Value::NullArray);
+                        response.push(Value::Map(reply));
+                    }
+                    b"DOCTOR" => {
+                        let report = self.store.memory_stats()?.doctor();
+                        response.push(Value::Verbatim("txt".into(), report.into()));
+                    }
+                    b"HELP" => {
+                        response.push(help_reply(&[
+                            "MEMORY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                            "DOCTOR",
+                            "    Return memory problems reports.",
+                            "STATS",
+                            "    Return information about the memory usage of the server.",
+                            "USAGE <key> [SAMPLES <count>]",
+                            "    Return memory in bytes used by <key> and its value. Nested values are",
+                            "    sampled up to <count> times (default: 5, 0 means sample all).",
+                            "HELP",
+                            "    Print this help.",
+                        ]));
+                    }
+                    _ => {
+                        return Err(RedisError::Custom(format!(
+                            "unknown subcommand '{}'. Try MEMORY HELP.",
+                            String::from_utf8_lossy(&request.args[0])
+                        )))
+                    }
+                }
+            }
+
+            CommandType::Sort | CommandType::SortRo => {
+                validate_args_len(request, 1)?;
+                let key = &request.args[0];
+                let options = SortOptions::parse(request.cmd, &request.args[1..])?;
+
+                match &options.store {
+                    Some(destination) => {
+                        let mut keyspace = self.store.keyspace_writer(self.db)?;
+                        let sorted = keyspace.sort(key, &options)?;
+                        let count = sorted.len();
+                        if sorted.is_empty() {
+                            let deleted = keyspace.remove(destination).is_some();
+                            drop(keyspace);
+                            if deleted {
+                                self.notify(EventClass::Generic, "del", destination);
                             }
+                        } else {
+                            // Nothing found by a GET pattern is stored as an empty string
+                            let list = sorted.into_iter().map(Option::unwrap_or_default);
+                            keyspace.insert(destination, Data::List(list.collect()));
+                            drop(keyspace);
+                            self.notify(EventClass::List, "sortstore", destination);
                         }
+                        response.push(Value::Integer(count as i64));
+                    }
+                    None => {
+                        let sorted = self.store.keyspace_reader(self.db)?.sort(key, &options)?;
+                        let sorted = sorted
+                            .into_iter()
+                            .map(|value| value.map_or(Value::NullString, Value::String));
+                        response.push(Value::Array(sorted.collect()));
                     }
                 }
+            }
 
-                self.store.unregister_interest(&client_id)?;
+            CommandType::Dump => {
+                let payload = self.store.keyspace_reader(self.db)?.dump(&request.args[0]);
+                response.push(payload.map_or(Value::NullString, Value::String));
             }
 
-            CommandType::Type => {
-                validate_args_len(&request, 1)?;
+            CommandType::Restore => {
+                let options = RestoreOptions::parse(&request.args[3..])?;
+                let (key, ttl, payload) = (&request.args[0], &request.args[1], &request.args[2]);
+                self.store
+                    .keyspace_writer(self.db)?
+                    .restore(key, ttl, payload, &options)?;
+                // Nothing is created when the TTL has already passed
+                if self.store.exists(self.db, key)? {
+                    self.notify(EventClass::Generic, "restore", key);
+                }
+                response.push(Value::ok());
+            }
+
+            CommandType::RandomKey => {
+                let key = self.store.keyspace_reader(self.db)?.random_key();
+                response.push(key.map_or(Value::NullString, Value::String));
+            }
+
+            CommandType::Scan => {
+                let options = ScanOptions::parse(request.cmd, &request.args)?;
+                let (cursor, keys) = self.store.keyspace_reader(self.db)?.scan(&options);
+                response.push(scan_reply(cursor, keys.into_iter().map(Value::String)));
+            }
+
+            CommandType::SScan | CommandType::HScan | CommandType::ZScan => {
+                validate_args_len(request, 2)?;
+
                 let key = &request.args[0];
-                let key_type = self.store.key_type(key)?;
-                response.push(Value::SimpleString(key_type));
+                let options = ScanOptions::parse(request.cmd, &request.args[1..])?;
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                let reply = match request.cmd {
+                    CommandType::SScan => {
+                        let (cursor, members) = keyspace.sscan(key, &options)?;
+                        scan_reply(cursor, members.into_iter().map(Value::String))
+                    }
+                    CommandType::HScan => {
+                        let (cursor, fields) = keyspace.hscan(key, &options)?;
+                        scan_reply(cursor, fields.into_iter().map(Value::String))
+                    }
+                    _ => {
+                        let (cursor, members) = keyspace.zscan(key, &options)?;
+                        let members = members.into_iter().flat_map(|(member, score)| {
+                            [
+                                Value::String(member),
+                                Value::String(format_double(score).into()),
+                            ]
+                        });
+                        scan_reply(cursor, members)
+                    }
+                };
+                response.push(reply);
             }
+
             CommandType::XAdd => {
-                validate_args_len(&request, 2)?;
+                validate_args_len(request, 2)?;
 
                 let stream_key = &request.args[0];
                 let entry_id = &request.args[1];
 
                 let values = if request.args.len() > 2 {
-                    if &request.args[2..].len() % 2 != 0 {
-                        response.push(Value::error(
-                            "need even number of keys and values for stream".into(),
-                        ));
-
-                        return Ok(response);
+                    if !request.args[2..].len().is_multiple_of(2) {
+                        return Err(RedisError::InsufficientArugments(request.cmd));
                     }
 
                     let pairs: Vec<(Bytes, Bytes)> = request.args[2..]
@@ -611,17 +1794,13 @@ impl Worker {
                 };
 
                 {
-                    let mut store = self.store.stream_writer()?;
-                    match store.add_entry(stream_key, entry_id, values.as_deref()) {
-                        Ok(entry_key) => response.push(entry_key),
-                        Err(e) => match e {
-                            RedisError::StreamIdError(se) => response.push(Value::Error(se.into())),
-                            _ => return Err(e),
-                        },
-                    }
+                    let mut keyspace = self.store.keyspace_writer(self.db)?;
+                    let entry_key = keyspace.add_entry(stream_key, entry_id, values.as_deref())?;
+                    response.push(entry_key);
                 }
+                self.notify(EventClass::Stream, "xadd", stream_key);
 
-                if let Some(sender) = self.store.client_sender(stream_key)? {
+                if let Some(sender) = self.store.client_sender(self.db, stream_key)? {
                     sender
                         .send(stream_key.clone())
                         .await
@@ -630,18 +1809,18 @@ impl Worker {
             }
 
             CommandType::XRange => {
-                validate_args_len(&request, 3)?;
+                validate_args_len(request, 3)?;
 
                 let key = &request.args[0];
                 let start = &request.args[1];
                 let end = &request.args[2];
-                let store = self.store.stream_reader()?;
-                let values = store.xrange(key, start, end)?;
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                let values = keyspace.xrange(key, start, end)?;
                 response.push(values);
             }
 
             CommandType::XRead => {
-                validate_args_len(&request, 3)?;
+                validate_args_len(request, 3)?;
                 let (timeout, keys) = if request.args.contains(&"block".into()) {
                     let timeout = &request.args[1];
                     let keys = &request.args[3..];
@@ -658,13 +1837,13 @@ impl Worker {
                     Some(to) => {
                         let receiver = self
                             .store
-                            .register_interest(client_id.clone(), keys)
+                            .register_interest(client_id.clone(), self.db, keys)
                             .await?;
 
                         if to == 0 {
                             if let Ok(v) = receiver.recv().await {
-                                let store = self.store.stream_reader()?;
-                                let result = store.xread(&[v], entry_ids);
+                                let keyspace = self.store.keyspace_reader(self.db)?;
+                                let result = keyspace.xread(&[v], entry_ids)?;
                                 response.push(result);
                             }
                         } else {
@@ -675,8 +1854,8 @@ impl Worker {
                             .await
                             {
                                 Ok(Ok(item)) => {
-                                    let store = self.store.stream_reader()?;
-                                    let result = store.xread(&[item], entry_ids);
+                                    let keyspace = self.store.keyspace_reader(self.db)?;
+                                    let result = keyspace.xread(&[item], entry_ids)?;
                                     response.push(result);
                                 }
                                 _ => response.push(Value::NullArray),
@@ -686,24 +1865,127 @@ impl Worker {
                         self.store.unregister_interest(&client_id)?;
                     }
                     None => {
-                        let store = self.store.stream_reader()?;
-                        let results = store.xread(stream_keys, entry_ids);
+                        let keyspace = self.store.keyspace_reader(self.db)?;
+                        let results = keyspace.xread(stream_keys, entry_ids)?;
                         response.push(results);
                     }
                 }
             }
 
-            CommandType::Incr => {
-                validate_args_len(&request, 1)?;
+            CommandType::Expire
+            | CommandType::PExpire
+            | CommandType::ExpireAt
+            | CommandType::PExpireAt => {
+                validate_args_len(request, 2)?;
+
+                let key = &request.args[0];
+                let time = bytes_to_number::<i64>(&request.args[1])?;
+                let options = ExpireOptions::parse(&request.args[2..])?;
+
+                let invalid = || {
+                    RedisError::Custom(format!("invalid expire time in '{}' command", request.cmd))
+                };
+                let ms = match request.cmd {
+                    CommandType::Expire | CommandType::ExpireAt => {
+                        time.checked_mul(1000).ok_or_else(invalid)?
+                    }
+                    _ => time,
+                };
+                let at = match request.cmd {
+                    CommandType::Expire | CommandType::PExpire => ms
+                        .checked_add(self.store.now_ms() as i64)
+                        .ok_or_else(invalid)?,
+                    _ => ms,
+                };
+
+                let updated = self.store.expire(self.db, key, at, &options)?;
+                if updated {
+                    // A deadline already passed deletes the key
+                    if self.store.exists(self.db, key)? {
+                        self.notify(EventClass::Generic, "expire", key);
+                    } else {
+                        self.notify(EventClass::Generic, "del", key);
+                    }
+                }
+                response.push(Value::Integer(updated as i64));
+            }
+
+            CommandType::Ttl
+            | CommandType::PTtl
+            | CommandType::ExpireTime
+            | CommandType::PExpireTime => {
+                validate_args_len(request, 1)?;
+
+                let key = &request.args[0];
+                let expiry = match self.store.exists(self.db, key)? {
+                    true => self.store.expiry(self.db, key)?,
+                    false => {
+                        response.push(Value::Integer(-2));
+                        return Ok(response);
+                    }
+                };
+
+                let reply = match expiry {
+                    None => -1,
+                    Some(at) => expiry_reply(request.cmd, at, self.store.now_ms()),
+                };
+
+                response.push(Value::Integer(reply));
+            }
+
+            CommandType::Persist => {
+                validate_args_len(request, 1)?;
 
                 let key = &request.args[0];
-                let mut map = self.store.map_writer()?;
-                match map.incr(key) {
-                    Ok(value) => response.push(Value::Integer(value)),
-                    Err(_) => response.push(Value::error(
-                        "ERR value is not an integer or out of range".into(),
-                    )),
+                let persisted = self.store.persist(self.db, key)?;
+                if persisted {
+                    self.notify(EventClass::Generic, "persist", key);
                 }
+                response.push(Value::Integer(persisted as i64));
+            }
+
+            CommandType::Incr | CommandType::Decr => {
+                validate_args_len(request, 1)?;
+
+                let key = &request.args[0];
+                let delta = if request.cmd == CommandType::Incr {
+                    1
+                } else {
+                    -1
+                };
+                let value = self.store.keyspace_writer(self.db)?.incr_by(key, delta)?;
+                self.notify(EventClass::String, "incrby", key);
+                response.push(Value::Integer(value));
+            }
+
+            CommandType::IncrBy | CommandType::DecrBy => {
+                validate_args_len(request, 2)?;
+
+                let key = &request.args[0];
+                let delta = bytes_to_number::<i64>(&request.args[1])?;
+                let delta = match request.cmd {
+                    CommandType::IncrBy => delta,
+                    _ => delta
+                        .checked_neg()
+                        .ok_or(RedisError::Custom("decrement would overflow".into()))?,
+                };
+
+                let value = self.store.keyspace_writer(self.db)?.incr_by(key, delta)?;
+                self.notify(EventClass::String, "incrby", key);
+                response.push(Value::Integer(value));
+            }
+
+            CommandType::IncrByFloat => {
+                validate_args_len(request, 2)?;
+
+                let key = &request.args[0];
+                let delta = parse_float(&request.args[1])?;
+                let value = self
+                    .store
+                    .keyspace_writer(self.db)?
+                    .incr_by_float(key, delta)?;
+                self.notify(EventClass::String, "incrbyfloat", key);
+                response.push(Value::String(value));
             }
 
             CommandType::Multi => {
@@ -726,10 +2008,13 @@ impl Worker {
 
                 let mut results = Vec::new();
                 for cmd in txn.commands() {
-                    results.push(
+                    // Errors are reported per command rather than aborting the transaction
+                    let result =
                         Box::pin(self.execute_command(&cmd, client_id.clone(), responder.clone()))
-                            .await?,
-                    );
+                            .await
+                            .unwrap_or_else(|e| vec![e.into()]);
+
+                    results.push(result);
                 }
 
                 response = results.into_iter().flatten().collect();
@@ -749,21 +2034,31 @@ impl Worker {
             }
 
             CommandType::Info => {
-                validate_args_len(&request, 1)?;
+                let mut requested: Vec<String> = Vec::new();
+                for arg in &request.args {
+                    let section = bytes_to_str(arg)?.to_lowercase();
+                    if ["default", "all", "everything"].contains(&&section[..]) {
+                        requested.extend(INFO_SECTIONS.map(String::from));
+                    } else {
+                        requested.push(section);
+                    }
+                }
+                if requested.is_empty() {
+                    requested.extend(INFO_SECTIONS.map(String::from));
+                }
 
-                match str::from_utf8(&request.args[0]).map_err(|_| RedisError::StringConversion)? {
-                    "replication" => {
-                        let info_string = format!(
-                            "role:{}\nmaster_replid:8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb\nmaster_repl_offset:0",
-                            self.role
-                        );
-                        response.push(Value::String(info_string.into()));
+                // Sections are separated by a blank line, unknown ones are left out
+                let mut sections = Vec::new();
+                for (i, section) in requested.iter().enumerate() {
+                    if !requested[..i].contains(section) {
+                        sections.extend(self.info_section(section)?);
                     }
-                    _ => {}
                 }
+                response.push(Value::String(sections.join("\r\n").into()));
             }
 
             CommandType::ReplConf => {
+                validate_args_len(request, 2)?;
                 if *request.args[0] == *b"ACK" {
                     let value = bytes_to_number::<usize>(&request.args[1])?;
                     let _ = self.acknowledger.0.send(value).await;
@@ -784,7 +2079,7 @@ impl Worker {
             }
 
             CommandType::Wait => {
-                validate_args_len(&request, 2)?;
+                validate_args_len(request, 2)?;
                 let num_replicas = bytes_to_number::<usize>(&request.args[0])?;
                 let wait_time = bytes_to_number::<usize>(&request.args[1])?;
                 if num_replicas == 0 {
@@ -796,7 +2091,7 @@ impl Worker {
                         Value::String("GETACK".into()),
                         Value::String("*".into()),
                     ]);
-                    for thing in reader.values() {
+                    for thing in reader.senders.values() {
                         let _ = thing.send(vec![replconf.clone()]).await;
                     }
 
@@ -823,43 +2118,67 @@ impl Worker {
             }
 
             CommandType::Config => {
-                validate_args_len(&request, 2)?;
-                let cmd = &request.args[0];
+                validate_args_len(request, 1)?;
+                let cmd = request.args[0].to_ascii_uppercase();
                 let rest = &request.args[1..];
 
                 let mut values = vec![];
                 if &cmd[..] == b"GET" {
+                    validate_args_len(request, 2)?;
                     let cfg = self.store.config_reader()?;
                     for key in rest.iter() {
-                        match cfg.get(key) {
-                            Some(value) => {
-                                values.push(Value::String(key.clone()));
-                                values.push(Value::String(value.clone()));
+                        if let Some(value) = cfg.get(key) {
+                            values.push((Value::String(key.clone()), Value::String(value.clone())));
+                        }
+                    }
+                    response.push(Value::Map(values));
+                } else if &cmd[..] == b"SET" {
+                    if rest.is_empty() || !rest.len().is_multiple_of(2) {
+                        return Err(RedisError::InsufficientArugments(request.cmd));
+                    }
+
+                    // Every parameter is checked before any is applied
+                    let mut max_memory = self.store.max_memory()?;
+                    let mut events = None;
+                    for pair in rest.chunks(2) {
+                        let (name, value) = (bytes_to_str(&pair[0])?, bytes_to_str(&pair[1])?);
+                        match &name.to_ascii_lowercase()[..] {
+                            "maxmemory" => max_memory.limit = parse_memory_size(value)?,
+                            "maxmemory-policy" => max_memory.policy = EvictionPolicy::parse(value)?,
+                            "notify-keyspace-events" => {
+                                events = Some(KeyspaceEvents::parse(value)?)
+                            }
+                            _ => {
+                                return Err(RedisError::Custom(format!(
+                                "Unknown option or number of arguments for CONFIG SET - '{name}'"
+                            )))
                             }
-                            None => {}
                         }
                     }
-                }
 
-                response.push(Value::Array(values));
+                    set_eviction(&self.store, max_memory)?;
+                    if let Some(events) = events {
+                        set_notifications(&self.store, events)?;
+                    }
+                    response.push(Value::ok());
+                } else {
+                    return Err(RedisError::Custom(format!(
+                        "unknown subcommand '{}'. Try CONFIG HELP.",
+                        String::from_utf8_lossy(&request.args[0])
+                    )));
+                }
             }
             CommandType::Keys => {
-                validate_args_len(&request, 1)?;
-                let key = &request.args[0];
-                if &key[..] == b"*" {
-                    let map = self.store.map_reader()?;
-                    let mem_keys = map.list();
-                    let rdb = self.store.rdb_reader()?;
-                    let rdb_keys = rdb.list();
-
-                    let combined = vec![mem_keys, rdb_keys]
-                        .into_iter()
-                        .flatten()
-                        .map(|k| Value::String(k.clone()))
-                        .collect::<Vec<Value>>();
+                validate_args_len(request, 1)?;
+                let pattern = &request.args[0];
+                let keys = self
+                    .store
+                    .keys(self.db, pattern)?
+                    .into_iter()
+                    .map(Value::String)
+                    .collect::<Vec<Value>>();
 
-                    response.push(Value::Array(combined));
-                }
+                response.push(Value::Array(keys));
             }
 
             CommandType::Publish => {
@@ -869,10 +2188,7 @@ impl Worker {
 
                 let topic = {
                     let ps_reader = self.store.pubsub_reader()?;
-                    match ps_reader.get_topic(channel_name) {
-                        Some(t) => Some(t.clone()),
-                        None => None,
-                    }
+                    ps_reader.get_topic(channel_name).cloned()
                 };
 
                 if let Some(topic) = topic {
@@ -884,15 +2200,17 @@ impl Worker {
             CommandType::Subscribe => {
                 validate_args_len(request, 1)?;
                 let channel_name = &request.args[0];
+                let protocol = self.store.client_protocol(&client_id)?;
                 let mut ps_writer = self.store.pubsub_writer()?;
-                let subs = ps_writer.subscribe(channel_name.clone(), &client_id, responder);
+                let subs =
+                    ps_writer.subscribe(channel_name.clone(), &client_id, responder, protocol);
                 let msg = vec![
                     Value::String("subscribe".into()),
                     Value::String(channel_name.clone()),
                     Value::Integer(subs as i64),
                 ];
 
-                response.push(Value::Array(msg));
+                response.push(Value::Push(msg));
             }
 
             CommandType::Unsubscribe => {
@@ -906,7 +2224,7 @@ impl Worker {
                     Value::Integer(subs as i64),
                 ];
 
-                response.push(Value::Array(msg));
+                response.push(Value::Push(msg));
             }
 
             CommandType::ZAdd => {
@@ -915,8 +2233,10 @@ impl Worker {
                 let score = bytes_to_number::<f64>(&request.args[1])?;
                 let name = &request.args[2];
 
-                let mut set_writer = self.store.sorted_set_writer()?;
-                let added = set_writer.zadd(set_name, name, score);
+                let mut keyspace = self.store.keyspace_writer(self.db)?;
+                let added = keyspace.zadd(set_name, name, score)?;
+                drop(keyspace);
+                self.notify(EventClass::SortedSet, "zadd", set_name);
                 response.push(Value::Integer(added as i64));
             }
 
@@ -925,8 +2245,8 @@ impl Worker {
                 let set_name = &request.args[0];
                 let name = &request.args[1];
 
-                let set_reader = self.store.sorted_set_reader()?;
-                match set_reader.zrank(set_name, name) {
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                match keyspace.zrank(set_name, name)? {
                     Some(total) => response.push(Value::Integer(total as i64)),
                     None => response.push(Value::NullString),
                 }
@@ -938,15 +2258,12 @@ impl Worker {
                 let start = bytes_to_number::<i32>(&request.args[1])?;
                 let end = bytes_to_number::<i32>(&request.args[2])?;
 
-                let set_reader = self.store.sorted_set_reader()?;
-                let members = set_reader.zrange(set_name, start, end);
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                let members = keyspace.zrange(set_name, start, end)?;
                 if members.is_empty() {
                     response.push(Value::EmptyArray);
                 } else {
-                    let members: Vec<Value> = members
-                        .into_iter()
-                        .map(|member| Value::String(member))
-                        .collect();
+                    let members: Vec<Value> = members.into_iter().map(Value::String).collect();
                     response.push(Value::Array(members));
                 }
             }
@@ -954,8 +2271,8 @@ impl Worker {
             CommandType::ZCard => {
                 validate_args_len(request, 1)?;
                 let set_name = &request.args[0];
-                let set_reader = self.store.sorted_set_reader()?;
-                let size = set_reader.zcard(set_name);
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                let size = keyspace.zcard(set_name)?;
                 response.push(Value::Integer(size as i64));
             }
 
@@ -963,10 +2280,10 @@ impl Worker {
                 validate_args_len(request, 2)?;
                 let set_name = &request.args[0];
                 let name = &request.args[1];
-                let set_reader = self.store.sorted_set_reader()?;
+                let keyspace = self.store.keyspace_reader(self.db)?;
 
-                match set_reader.zscore(set_name, name) {
-                    Some(score) => response.push(Value::String(format!("{score}").into())),
+                match keyspace.zscore(set_name, name)? {
+                    Some(score) => response.push(Value::double(score)),
                     None => response.push(Value::NullString),
                 }
             }
@@ -975,8 +2292,16 @@ impl Worker {
                 validate_args_len(request, 2)?;
                 let set_name = &request.args[0];
                 let name = &request.args[1];
-                let mut set_reader = self.store.sorted_set_writer()?;
-                let removed = set_reader.zrem(set_name, name);
+                let mut keyspace = self.store.keyspace_writer(self.db)?;
+                let removed = keyspace.zrem(set_name, name)?;
+                let emptied = !keyspace.contains(set_name);
+                drop(keyspace);
+                if removed > 0 {
+                    self.notify(EventClass::SortedSet, "zrem", set_name);
+                    if emptied {
+                        self.notify(EventClass::Generic, "del", set_name);
+                    }
+                }
                 response.push(Value::Integer(removed as i64));
             }
 
@@ -995,8 +2320,10 @@ impl Worker {
                 }
 
                 let score = encode_latlon(lat, lon);
-                let mut set_writer = self.store.sorted_set_writer()?;
-                let added = set_writer.zadd(key, place, score as f64);
+                let mut keyspace = self.store.keyspace_writer(self.db)?;
+                let added = keyspace.zadd(key, place, score as f64)?;
+                drop(keyspace);
+                self.notify(EventClass::SortedSet, "zadd", key);
                 response.push(Value::Integer(added as i64));
             }
 
@@ -1005,11 +2332,11 @@ impl Worker {
                 let key = &request.args[0];
                 let places = &request.args[1..];
 
-                let set_reader = self.store.sorted_set_reader()?;
+                let keyspace = self.store.keyspace_reader(self.db)?;
                 let mut values = Vec::new();
 
                 for place in places {
-                    match set_reader.zscore(key, place) {
+                    match keyspace.zscore(key, place)? {
                         Some(score) => {
                             let (lat, lon) = decode_latlon(score as u64);
                             let resp = vec![
@@ -1031,43 +2358,47 @@ impl Worker {
                 let origin = &request.args[1];
                 let dest = &request.args[2];
 
-                let set_reader = self.store.sorted_set_reader()?;
-                let Some(origin_score) = set_reader.zscore(key, origin) else {
-                    todo!();
+                let unit = match request.args.get(3) {
+                    Some(unit) => distance_unit(unit)?,
+                    None => 1.0,
                 };
 
-                let Some(dest_score) = set_reader.zscore(key, dest) else {
-                    todo!();
-                };
-
-                let origin = decode_latlon(origin_score as u64);
-                let dest = decode_latlon(dest_score as u64);
-                let dist = latlon_dist(origin, dest);
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                match (keyspace.zscore(key, origin)?, keyspace.zscore(key, dest)?) {
+                    (Some(origin_score), Some(dest_score)) => {
+                        let origin = decode_latlon(origin_score as u64);
+                        let dest = decode_latlon(dest_score as u64);
+                        let dist = latlon_dist(origin, dest) / unit;
 
-                response.push(Value::String(dist.to_string().into()));
+                        response.push(Value::String(dist.to_string().into()));
+                    }
+                    _ => response.push(Value::NullString),
+                }
             }
 
             CommandType::GeoSearch => {
                 validate_args_len(request, 7)?;
                 let key = &request.args[0];
                 let mode = &request.args[1];
+                let search_option = &request.args[4];
+                if !mode.eq_ignore_ascii_case(b"FROMLONLAT")
+                    || !search_option.eq_ignore_ascii_case(b"BYRADIUS")
+                {
+                    return Err(RedisError::Syntax);
+                }
+
                 let src_lon = bytes_to_number::<f64>(&request.args[2])?;
                 let src_lat = bytes_to_number::<f64>(&request.args[3])?;
-                let search_option = &request.args[4];
                 let dist_value = bytes_to_number::<f64>(&request.args[5])?;
-                let unit = &request.args[6];
-                assert_eq!(&mode[..], b"FROMLONLAT");
-                assert_eq!(&search_option[..], b"BYRADIUS");
-                assert_eq!(&unit[..], b"m");
+                let unit = distance_unit(&request.args[6])?;
 
-                let set_reader = self.store.sorted_set_reader()?;
-                let entries = set_reader.zrange(key, 0, -1);
+                let dist_value = dist_value * unit;
+                let keyspace = self.store.keyspace_reader(self.db)?;
+                let entries = keyspace.zrange(key, 0, -1)?;
                 let valid_entries = entries
                     .into_iter()
                     .filter_map(|entry| {
-                        let score = set_reader
-                            .zscore(key, &entry)
-                            .expect("already present in set");
+                        let score = keyspace.zscore(key, &entry).ok().flatten()?;
                         let dest = decode_latlon(score as u64);
                         let dist = latlon_dist((src_lat, src_lon), dest);
                         if dist < dist_value {
@@ -1091,25 +2422,35 @@ impl Worker {
                         validate_args_len(request, 2)?;
                         let username = &request.args[1];
                         let user_reader = self.store.user_reader()?;
-                        let Some(user) = user_reader.get(username) else {
-                            todo!();
-                        };
-
-                        response.push(user);
+                        match user_reader.get(username) {
+                            Some(user) => response.push(user),
+                            None => response.push(Value::Null),
+                        }
                     }
                     b"SETUSER" => {
                         validate_args_len(request, 3)?;
                         let username = &request.args[1];
                         let pass = &request.args[2];
 
-                        assert_eq!(pass[0], '>' as u8);
-                        let pass = Bytes::copy_from_slice(&pass[1..]);
+                        if pass.first() != Some(&b'>') {
+                            return Err(RedisError::Custom(format!(
+                                "Error in ACL SETUSER modifier '{}': Syntax error",
+                                String::from_utf8_lossy(pass)
+                            )));
+                        }
+
+                        let pass = pass.slice(1..);
 
                         let mut user_writer = self.store.user_writer()?;
                         user_writer.set_password(username, &pass, &client_id);
                         response.push(Value::ok());
                     }
-                    _ => {}
+                    _ => {
+                        return Err(RedisError::Custom(format!(
+                            "unknown subcommand '{}'. Try ACL HELP.",
+                            String::from_utf8_lossy(subcmd)
+                        )))
+                    }
                 }
             }
 
@@ -1120,15 +2461,331 @@ impl Worker {
 
                 let mut user_writer = self.store.user_writer()?;
                 if !user_writer.authenticate(username, password, &client_id) {
-                    response.push(Value::Error(
-                        "WRONGPASS invalid username-password pair or user is disabled".into(),
-                    ));
+                    response.push(RedisError::WrongPass.into());
                 } else {
                     response.push(Value::ok());
                 }
             }
+
+            CommandType::Command => match request.args.first() {
+                None => {
+                    let all = COMMANDS.iter().map(CommandSpec::info).collect();
+                    response.push(Value::Array(all));
+                }
+                Some(subcmd) => match &subcmd.to_ascii_uppercase()[..] {
+                    b"COUNT" => response.push(Value::Integer(COMMANDS.len() as i64)),
+                    b"LIST" => {
+                        let names = COMMANDS
+                            .iter()
+                            .map(|spec| Value::String(spec.name.into()))
+                            .collect();
+                        response.push(Value::Array(names));
+                    }
+                    b"INFO" => {
+                        let specs: Vec<Option<&CommandSpec>> = if request.args.len() == 1 {
+                            COMMANDS.iter().map(Some).collect()
+                        } else {
+                            request.args[1..]
+                                .iter()
+                                .map(|name| CommandSpec::lookup(bytes_to_str(name).ok()?))
+                                .collect()
+                        };
+
+                        let infos = specs
+                            .into_iter()
+                            .map(|spec| spec.map(CommandSpec::info).unwrap_or(Value::NullArray))
+                            .collect();
+                        response.push(Value::Array(infos));
+                    }
+                    b"DOCS" => {
+                        let specs: Vec<&CommandSpec> = if request.args.len() == 1 {
+                            COMMANDS.iter().collect()
+                        } else {
+                            request.args[1..]
+                                .iter()
+                                .filter_map(|name| CommandSpec::lookup(bytes_to_str(name).ok()?))
+                                .collect()
+                        };
+
+                        let docs = specs
+                            .into_iter()
+                            .map(|spec| (Value::String(spec.name.into()), spec.docs()))
+                            .collect();
+                        response.push(Value::Map(docs));
+                    }
+                    b"GETKEYS" => {
+                        validate_args_len(request, 2)?;
+                        let spec = bytes_to_str(&request.args[1])
+                            .ok()
+                            .and_then(CommandSpec::lookup)
+                            .ok_or_else(|| {
+                                RedisError::Custom("Invalid command specified".into())
+                            })?;
+
+                        let args = &request.args[2..];
+                        if !spec.arity_matches(args.len()) {
+                            return Err(RedisError::Custom(
+                                "Invalid number of arguments specified for command".into(),
+                            ));
+                        }
+
+                        let keys = spec.keys(args);
+                        if keys.is_empty() {
+                            return Err(RedisError::Custom(
+                                "The command has no key arguments".into(),
+                            ));
+                        }
+
+                        let keys = keys.into_iter().map(|k| Value::String(k.clone())).collect();
+                        response.push(Value::Array(keys));
+                    }
+                    _ => {
+                        return Err(RedisError::Custom(format!(
+                            "unknown subcommand '{}'. Try COMMAND HELP.",
+                            String::from_utf8_lossy(subcmd)
+                        )))
+                    }
+                },
+            },
+
+            CommandType::Hello => {
+                let mut args = request.args.iter();
+                let protocol = match args.next() {
+                    Some(protover) => Some(ProtocolVersion::from_bytes(protover)?),
+                    None => None,
+                };
+
+                let mut name = None;
+                while let Some(option) = args.next() {
+                    match &option.to_ascii_uppercase()[..] {
+                        b"AUTH" => {
+                            let (Some(username), Some(password)) = (args.next(), args.next())
+                            else {
+                                return Err(RedisError::Syntax);
+                            };
+
+                            let mut user_writer = self.store.user_writer()?;
+                            if !user_writer.authenticate(username, password, &client_id) {
+                                return Err(RedisError::WrongPass);
+                            }
+                        }
+                        b"SETNAME" => {
+                            let Some(client_name) = args.next() else {
+                                return Err(RedisError::Syntax);
+                            };
+
+                            name = Some(client_name.clone());
+                        }
+                        _ => return Err(RedisError::Syntax),
+                    }
+                }
+
+                {
+                    let user_reader = self.store.user_reader()?;
+                    if user_reader.requires_authentication(&"default".into(), &client_id) {
+                        response.push(Value::error(
+                            "NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time".into(),
+                        ));
+                        return Ok(response);
+                    }
+                }
+
+                let mut clients = self.store.client_writer()?;
+                let client = clients.get_or_register(&client_id);
+                if let Some(protocol) = protocol {
+                    client.protocol = protocol;
+                }
+
+                if name.is_some() {
+                    client.name = name;
+                }
+
+                let role = match *self.role {
+                    ServerRole::Master => "master",
+                    ServerRole::Replica(_) => "replica",
+                };
+
+                response.push(Value::Map(vec![
+                    (
+                        Value::String("server".into()),
+                        Value::String("redis".into()),
+                    ),
+                    (
+                        Value::String("version".into()),
+                        Value::String(REDIS_VERSION.into()),
+                    ),
+                    (
+                        Value::String("proto".into()),
+                        Value::Integer(client.protocol.version()),
+                    ),
+                    (Value::String("id".into()), Value::Integer(client.id as i64)),
+                    (
+                        Value::String("mode".into()),
+                        Value::String("standalone".into()),
+                    ),
+                    (Value::String("role".into()), Value::String(role.into())),
+                    (Value::String("modules".into()), Value::Array(vec![])),
+                ]));
+            }
         }
 
         Ok(response)
     }
 }
+
+#[cfg(test)]
+mod server_tests {
+    use super::*;
+    use crate::redis::utils::clock::ManualClock;
+
+    fn request(args: &[&str]) -> RedisCommand {
+        let args = args.iter().map(|arg| Bytes::from(arg.to_string()));
+        RedisCommand::new(&command(args)).unwrap()
+    }
+
+    fn strings(args: &[&str]) -> Value {
+        command(args.iter().map(|arg| Bytes::from(arg.to_string())))
+    }
+
+    #[tokio::test]
+    async fn config_set_enables_notifications() -> Result<(), RedisError> {
+        let store = Arc::new(GlobalStore::with_clock(
+            Arc::new(ManualClock::new(1_000_000)),
+            DEFAULT_DATABASES,
+        ));
+        let (sender, receiver) = kanal::unbounded_async();
+        store.pubsub_writer()?.subscribe(
+            "__keyevent@0__:set".into(),
+            &"subscriber".into(),
+            sender,
+            ProtocolVersion::Resp2,
+        );
+        let mut worker = Worker::detached(store.clone(), Arc::new(ServerRole::Master));
+
+        worker.apply(&request(&["SET", "before", "1"])).await?;
+        assert!(receiver.is_empty());
+
+        let set = request(&["config", "set", "notify-keyspace-events", "E$"]);
+        assert_eq!(worker.apply(&set).await?, vec![Value::ok()]);
+        let get = request(&["Config", "Get", "notify-keyspace-events"]);
+        assert_eq!(
+            worker.apply(&get).await?,
+            vec![Value::Map(vec![(
+                Value::String("notify-keyspace-events".into()),
+                Value::String("$E".into())
+            )])]
+        );
+
+        worker.apply(&request(&["SET", "after", "1"])).await?;
+        let message = receiver
+            .try_recv()
+            .map_err(|_| RedisError::ChannelSendError)?;
+        assert_eq!(
+            message,
+            Some(vec![Value::Push(vec![
+                Value::String("message".into()),
+                Value::String("__keyevent@0__:set".into()),
+                Value::String("after".into()),
+            ])
+            .into_protocol(ProtocolVersion::Resp2)])
+        );
+        assert!(receiver.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn config_unknown_subcommand() {
+        let store = Arc::new(GlobalStore::with_clock(
+            Arc::new(ManualClock::new(1_000_000)),
+            DEFAULT_DATABASES,
+        ));
+        let mut worker = Worker::detached(store, Arc::new(ServerRole::Master));
+
+        for subcommand in [&["RESETSTAT"][..], &["gett", "maxmemory"]] {
+            let mut args = vec!["CONFIG"];
+            args.extend(subcommand);
+            let error = worker.apply(&request(&args)).await.unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("unknown subcommand '{}'. Try CONFIG HELP.", subcommand[0])
+            );
+        }
+    }
+
+    #[test]
+    fn relative_ttls_replicate_as_deadlines() -> Result<(), RedisError> {
+        let store = GlobalStore::with_clock(Arc::new(ManualClock::new(1_000_000)), 1);
+        let key: Bytes = "key".into();
+
+        let set = request(&["SET", "key", "v", "nx", "ex", "10"]);
+        let options = SetOptions::parse(&set.args[2..])?;
+        store.keyspace_writer(0)?.set(&key, &"v".into(), &options)?;
+        assert_eq!(
+            replicated_commands(&store, 0, &set, &[Value::ok()])?,
+            vec![strings(&["SET", "key", "v", "nx", "PXAT", "1010000"])]
+        );
+
+        let expire = request(&["PEXPIRE", "key", "500"]);
+        store.expire(0, &key, 1_000_500, &ExpireOptions::default())?;
+        assert_eq!(
+            replicated_commands(&store, 0, &expire, &[Value::Integer(1)])?,
+            vec![strings(&["PEXPIREAT", "key", "1000500"])]
+        );
+        assert!(replicated_commands(&store, 0, &expire, &[Value::Integer(0)])?.is_empty());
+
+        let getex = request(&["GETEX", "key", "PX", "100"]);
+        let expiry = SetExpiry::parse_getex(&getex.args[1..])?;
+        let value = store.keyspace_writer(0)?.get_ex(&key, expiry)?;
+        assert_eq!(
+            replicated_commands(&store, 0, &getex, &[Value::String(value.unwrap())])?,
+            vec![strings(&["PEXPIREAT", "key", "1000100"])]
+        );
+
+        let payload = store.keyspace_reader(0)?.dump(&key).unwrap();
+        let restore = RedisCommand::new(&command([
+            "RESTORE".into(),
+            key.clone(),
+            "2000".into(),
+            payload.clone(),
+            "REPLACE".into(),
+        ]))?;
+        let options = RestoreOptions::parse(&restore.args[3..])?;
+        store
+            .keyspace_writer(0)?
+            .restore(&key, &"2000".into(), &payload, &options)?;
+        assert_eq!(
+            replicated_commands(&store, 0, &restore, &[Value::ok()])?,
+            vec![command([
+                "RESTORE".into(),
+                key.clone(),
+                "1002000".into(),
+                payload,
+                "REPLACE".into(),
+                "ABSTTL".into(),
+            ])]
+        );
+
+        // A deadline that has already passed deletes the key
+        let expire = request(&["EXPIRE", "key", "-1"]);
+        store.expire(0, &key, 999_000, &ExpireOptions::default())?;
+        assert_eq!(
+            replicated_commands(&store, 0, &expire, &[Value::Integer(1)])?,
+            vec![strings(&["DEL", "key"])]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn expiry_replies() {
+        let now = 1_000;
+        assert_eq!(expiry_reply(CommandType::Ttl, 2_500, now), 2);
+        assert_eq!(expiry_reply(CommandType::Ttl, 2_400, now), 1);
+        assert_eq!(expiry_reply(CommandType::PTtl, 2_500, now), 1_500);
+        assert_eq!(expiry_reply(CommandType::PTtl, 500, now), 0);
+        assert_eq!(expiry_reply(CommandType::ExpireTime, 1_500, now), 1);
+        assert_eq!(expiry_reply(CommandType::ExpireTime, 1_999, now), 1);
+        assert_eq!(expiry_reply(CommandType::PExpireTime, 1_500, now), 1_500);
+    }
+}
@@ -1,13 +1,11 @@
-use std::sync::Arc;
-
 use anyhow::Result;
 use futures_util::{SinkExt, StreamExt};
 use tokio::net::TcpStream;
 use tokio_util::codec::Framed;
 
+use super::Worker;
 use crate::redis::{
     protocol::{CommandType, RedisCommand, RedisError, RespProtocol, Value},
-    stores::GlobalStore,
     utils::validate_args_len,
 };
 
@@ -15,7 +13,7 @@ pub struct ReplicaMasterConnection {
     offset: usize,
     repl_port: u16,
     stream: Framed<TcpStream, RespProtocol>,
-    store: Arc<GlobalStore>,
+    worker: Worker,
 }
 
 impl ReplicaMasterConnection {
@@ -23,15 +21,15 @@ impl ReplicaMasterConnection {
         addr: String,
         port: u16,
         repl_port: u16,
-        store: Arc<GlobalStore>,
+        worker: Worker,
     ) -> Result<Self, RedisError> {
         let stream = TcpStream::connect(format!("{addr}:{port}")).await?;
 
         Ok(Self {
-            store,
+            worker,
             offset: 0,
             repl_port,
-            stream: Framed::new(stream, RespProtocol),
+            stream: Framed::new(stream, RespProtocol::default()),
         })
     }
 
@@ -65,54 +63,28 @@ impl ReplicaMasterConnection {
         ];
         self.stream.send(Value::Array(psync)).await?;
         let _ = self.stream.next().await;
-        let _rdb = self.stream.next().await;
-
-        Ok(())
-    }
 
-    // HAX: This is rotten but meh
-    async fn catch_trailing(&mut self) -> Result<(), RedisError> {
-        let mut fragments = Vec::new();
-        while let Some(frame) = self.stream.next().await {
-            match frame {
-                Ok(value) => match &value {
-                    Value::String(_) => {
-                        fragments.push(value);
-                        if fragments.len() < 3 {
-                            continue;
-                        }
-
-                        match RedisCommand::new(&Value::Array(fragments.clone())) {
-                            Ok(cmd) => {
-                                self.process_command(cmd).await?;
-                                break;
-                            }
-                            Err(_) => {}
-                        }
-                    }
-                    _ => {
-                        eprintln!("SOMETHING WRONG WITH TRAILING COMMAND");
-                    }
-                },
-                Err(e) => {
-                    eprintln!("REPL ERROR: {e:?}");
-                    return Err(RedisError::UnexpectedValue);
-                }
-            }
-        }
+        self.stream.codec_mut().expect_rdb();
+        let _rdb = self.stream.next().await;
 
         Ok(())
     }
 
     pub async fn replicate(&mut self) -> Result<(), RedisError> {
         self.handshake().await?;
-        self.catch_trailing().await?;
 
         while let Some(frame) = self.stream.next().await {
             match frame {
                 Ok(value) => {
-                    let cmd = RedisCommand::new(&value)?;
-                    self.process_command(cmd).await?;
+                    // A bad command from the master is skipped rather than ending replication
+                    let result = match RedisCommand::new(&value) {
+                        Ok(cmd) => self.process_command(cmd).await,
+                        Err(e) => Err(e),
+                    };
+
+                    if let Err(e) = result {
+                        eprintln!("REPL COMMAND ERROR {e:#?}");
+                    }
                 }
                 Err(e) => {
                     eprintln!("REPL PARSE ERROR {e:#?}");
@@ -125,40 +97,30 @@ impl ReplicaMasterConnection {
     }
 
     pub async fn process_command(&mut self, request: RedisCommand) -> Result<(), RedisError> {
-        match request.cmd {
-            CommandType::Set => {
-                validate_args_len(&request, 2)?;
-
-                let key = &request.args[0];
-                let value = &request.args[1];
-                let ttl = if request.args.len() == 4 {
-                    Some(request.args[3].clone())
-                } else {
-                    None
-                };
-
-                let mut store = self.store.map_writer()?;
-                store.set(key, value, ttl)?;
-            }
+        let result = match request.cmd {
+            CommandType::ReplConf => self.replconf(&request).await,
+            // The master doesn't expect replies to anything else
+            _ => self.worker.apply(&request).await.map(|_| ()),
+        };
 
-            CommandType::ReplConf => {
-                validate_args_len(&request, 2)?;
-                let arg = &request.args[0];
-                let _value = &request.args[1];
-                if **arg == *b"GETACK" {
-                    let value = format!("{}", self.offset);
-                    let response = Value::Array(vec![
-                        Value::String("REPLCONF".into()),
-                        Value::String("ACK".into()),
-                        Value::String(value.into()),
-                    ]);
-                    self.stream.send(response).await?;
-                }
-            }
-            _ => {}
+        // The offset covers every command received, including ones that failed to apply
+        self.offset += request.size();
+        result
+    }
+
+    async fn replconf(&mut self, request: &RedisCommand) -> Result<(), RedisError> {
+        validate_args_len(request, 2)?;
+        let arg = &request.args[0];
+        if **arg == *b"GETACK" {
+            let value = format!("{}", self.offset);
+            let response = Value::Array(vec![
+                Value::String("REPLCONF".into()),
+                Value::String("ACK".into()),
+                Value::String(value.into()),
+            ]);
+            self.stream.send(response).await?;
         }
 
-        self.offset += request.size();
         Ok(())
     }
 }
@@ -0,0 +1,103 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::redis::utils::random_u64;
+
+// Usage of each key as Redis tracks it for OBJECT IDLETIME, OBJECT FREQ and eviction. The
+// frequency is a logarithmic counter: an access bumps it with a probability that falls as
+// the counter grows, and it decays by one for every minute the key goes unused
+
+/// Counter of a new key, so it isn't the first to go before it had a chance to be used
+const LFU_INIT_VAL: u8 = 5;
+const LFU_LOG_FACTOR: f64 = 10.0;
+const LFU_DECAY_MS: u64 = 60_000;
+
+/// Atomics so reads holding a shared reference to the keyspace still record the access
+#[derive(Debug)]
+pub struct Access {
+    /// Unix time in milliseconds
+    last_ms: AtomicU64,
+    counter: AtomicU8,
+}
+
+impl Access {
+    pub fn new(now: u64) -> Self {
+        Self {
+            last_ms: AtomicU64::new(now),
+            counter: AtomicU8::new(LFU_INIT_VAL),
+        }
+    }
+
+    pub fn touch(&self, now: u64) {
+        let counter = log_incr(self.frequency(now));
+        self.counter.store(counter, Ordering::Relaxed);
+        self.last_ms.store(now, Ordering::Relaxed);
+    }
+
+    /// Backdates the last access by `idle_ms`, as RESTORE IDLETIME does
+    pub fn set_idle_ms(&self, now: u64, idle_ms: u64) {
+        self.last_ms
+            .store(now.saturating_sub(idle_ms), Ordering::Relaxed);
+    }
+
+    /// Sets the counter as of `now`, as RESTORE FREQ does
+    pub fn set_frequency(&self, now: u64, counter: u8) {
+        self.counter.store(counter, Ordering::Relaxed);
+        self.last_ms.store(now, Ordering::Relaxed);
+    }
+
+    /// Milliseconds since the last access
+    pub fn idle_ms(&self, now: u64) -> u64 {
+        now.saturating_sub(self.last_ms.load(Ordering::Relaxed))
+    }
+
+    /// The access counter once decayed for the time the key went unused
+    pub fn frequency(&self, now: u64) -> u8 {
+        let periods = (self.idle_ms(now) / LFU_DECAY_MS).min(u8::MAX as u64) as u8;
+        self.counter.load(Ordering::Relaxed).saturating_sub(periods)
+    }
+}
+
+impl Clone for Access {
+    fn clone(&self) -> Self {
+        Self {
+            last_ms: AtomicU64::new(self.last_ms.load(Ordering::Relaxed)),
+            counter: AtomicU8::new(self.counter.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+fn log_incr(counter: u8) -> u8 {
+    if counter == u8::MAX {
+        return counter;
+    }
+
+    let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+    let p = 1.0 / (base * LFU_LOG_FACTOR + 1.0);
+    let r = random_u64() as f64 / u64::MAX as f64;
+    if r < p {
+        counter + 1
+    } else {
+        counter
+    }
+}
+
+#[cfg(test)]
+mod access_tests {
+    use super::*;
+
+    #[test]
+    fn frequency_grows_slowly_and_decays() {
+        let access = Access::new(0);
+        assert_eq!(access.frequency(0), LFU_INIT_VAL);
+
+        for _ in 0..100 {
+            access.touch(1_000);
+        }
+        let frequency = access.frequency(1_000);
+        assert!((LFU_INIT_VAL + 1..LFU_INIT_VAL + 20).contains(&frequency));
+        assert_eq!(access.idle_ms(4_000), 3_000);
+
+        // Three minutes unused takes three off
+        assert_eq!(access.frequency(1_000 + 3 * LFU_DECAY_MS), frequency - 3);
+    }
+}
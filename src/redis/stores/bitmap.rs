@@ -0,0 +1,524 @@
+use bytes::{Bytes, BytesMut};
+
+use crate::redis::protocol::RedisError;
+use crate::redis::utils::bytes_to_number;
+
+// Bits are numbered from the most significant bit of the first byte, as in Redis
+
+fn offset_error() -> RedisError {
+    RedisError::Custom("bit offset is not an integer or out of range".into())
+}
+
+/// Parses a bit offset, which must land inside a string of at most `max_len` bytes. BITFIELD
+/// also accepts `#N`, meaning the N-th field of `width` bits
+pub fn parse_offset(arg: &Bytes, width: Option<u32>, max_len: usize) -> Result<u64, RedisError> {
+    let offset = match (arg.strip_prefix(b"#"), width) {
+        (Some(index), Some(width)) => bytes_to_number::<u64>(&Bytes::copy_from_slice(index))
+            .ok()
+            .and_then(|index| index.checked_mul(width as u64)),
+        _ => bytes_to_number::<u64>(arg).ok(),
+    };
+
+    match offset {
+        Some(offset) if offset >> 3 < max_len as u64 => Ok(offset),
+        _ => Err(offset_error()),
+    }
+}
+
+pub fn parse_bit(arg: &Bytes) -> Result<bool, RedisError> {
+    match &arg[..] {
+        b"0" => Ok(false),
+        b"1" => Ok(true),
+        _ => Err(RedisError::Custom(
+            "bit is not an integer or out of range".into(),
+        )),
+    }
+}
+
+pub fn getbit(value: &[u8], offset: u64) -> bool {
+    let byte = (offset / 8) as usize;
+    value
+        .get(byte)
+        .is_some_and(|b| b & (0x80 >> (offset % 8)) != 0)
+}
+
+/// Sets a bit, growing the value with zero bytes as needed, and returns the previous bit
+pub fn setbit(value: &mut BytesMut, offset: u64, bit: bool) -> bool {
+    let byte = (offset / 8) as usize;
+    if value.len() <= byte {
+        value.resize(byte + 1, 0);
+    }
+
+    let mask = 0x80 >> (offset % 8);
+    let previous = value[byte] & mask != 0;
+    if bit {
+        value[byte] |= mask;
+    } else {
+        value[byte] &= !mask;
+    }
+
+    previous
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum BitUnit {
+    #[default]
+    Byte,
+    Bit,
+}
+
+/// The optional `start end [BYTE|BIT]` range of BITCOUNT and BITPOS
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BitRange {
+    start: i64,
+    end: Option<i64>,
+    unit: BitUnit,
+}
+
+impl BitRange {
+    pub fn parse(args: &[Bytes]) -> Result<Self, RedisError> {
+        if args.len() > 3 {
+            return Err(RedisError::Syntax);
+        }
+
+        let mut range = Self::default();
+        if let Some(start) = args.first() {
+            range.start = bytes_to_number(start)?;
+        }
+        if let Some(end) = args.get(1) {
+            range.end = Some(bytes_to_number(end)?);
+        }
+        if let Some(unit) = args.get(2) {
+            range.unit = match &unit.to_ascii_uppercase()[..] {
+                b"BYTE" => BitUnit::Byte,
+                b"BIT" => BitUnit::Bit,
+                _ => return Err(RedisError::Syntax),
+            };
+        }
+
+        Ok(range)
+    }
+
+    /// First and last bit covered by the range in a value of `len` bytes, negative indexes
+    /// counting from the end. None when the range is empty
+    fn resolve(&self, len: usize) -> Option<(u64, u64)> {
+        let total = match self.unit {
+            BitUnit::Byte => len as i64,
+            BitUnit::Bit => len as i64 * 8,
+        };
+
+        let mut start = self.start;
+        let mut end = self.end.unwrap_or(total - 1);
+        if start < 0 {
+            start = (start + total).max(0);
+        }
+        if end < 0 {
+            end = (end + total).max(0);
+        }
+        end = end.min(total - 1);
+
+        if start > end {
+            return None;
+        }
+
+        let (start, end) = (start as u64, end as u64);
+        match self.unit {
+            BitUnit::Byte => Some((start * 8, end * 8 + 7)),
+            BitUnit::Bit => Some((start, end)),
+        }
+    }
+}
+
+pub fn bitcount(value: &[u8], range: &BitRange) -> u64 {
+    let Some((start, end)) = range.resolve(value.len()) else {
+        return 0;
+    };
+
+    let (first, last) = ((start / 8) as usize, (end / 8) as usize);
+    let mut count: u64 = value[first..=last]
+        .iter()
+        .map(|b| b.count_ones() as u64)
+        .sum();
+
+    // Drop the bits of the edge bytes that fall outside the range
+    let head = start % 8;
+    if head > 0 {
+        count -= (value[first] >> (8 - head)).count_ones() as u64;
+    }
+    let tail = 7 - end % 8;
+    count -= (value[last] & ((1u8 << tail) - 1)).count_ones() as u64;
+
+    count
+}
+
+/// Position of the first bit set to `bit` in the range. Without an explicit end the value
+/// is treated as padded with zeros, so looking for a clear bit never fails
+pub fn bitpos(value: &[u8], bit: bool, range: &BitRange) -> i64 {
+    let Some((start, end)) = range.resolve(value.len()) else {
+        return -1;
+    };
+
+    let skip = if bit { 0x00 } else { 0xff };
+    let mut pos = start;
+    while pos <= end {
+        if pos % 8 == 0 && pos + 7 <= end && value[(pos / 8) as usize] == skip {
+            pos += 8;
+            continue;
+        }
+
+        if getbit(value, pos) == bit {
+            return pos as i64;
+        }
+        pos += 1;
+    }
+
+    match (bit, range.end) {
+        (false, None) => end as i64 + 1,
+        _ => -1,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOp {
+    And,
+    Or,
+    Xor,
+    Not,
+    Diff,
+}
+
+impl BitOp {
+    pub fn parse(op: &Bytes, sources: usize) -> Result<Self, RedisError> {
+        let op = match &op.to_ascii_uppercase()[..] {
+            b"AND" => Self::And,
+            b"OR" => Self::Or,
+            b"XOR" => Self::Xor,
+            b"NOT" => Self::Not,
+            b"DIFF" => Self::Diff,
+            _ => return Err(RedisError::Syntax),
+        };
+
+        match op {
+            Self::Not if sources != 1 => Err(RedisError::Custom(
+                "BITOP NOT must be called with a single source key.".into(),
+            )),
+            Self::Diff if sources < 2 => Err(RedisError::Custom(
+                "BITOP DIFF must be called with at least two source keys.".into(),
+            )),
+            _ => Ok(op),
+        }
+    }
+
+    /// Combines the sources byte by byte, shorter ones padded with zeros. DIFF keeps the bits
+    /// of the first source that are set in none of the others
+    pub fn apply(&self, sources: &[&[u8]]) -> Vec<u8> {
+        let len = sources.iter().map(|s| s.len()).max().unwrap_or(0);
+        let byte = |source: &[u8], i: usize| source.get(i).copied().unwrap_or(0);
+
+        (0..len)
+            .map(|i| {
+                let mut bytes = sources.iter().map(|s| byte(s, i));
+                let first = bytes.next().unwrap_or(0);
+                match self {
+                    Self::And => bytes.fold(first, |acc, b| acc & b),
+                    Self::Or => bytes.fold(first, |acc, b| acc | b),
+                    Self::Xor => bytes.fold(first, |acc, b| acc ^ b),
+                    Self::Not => !first,
+                    Self::Diff => first & !bytes.fold(0, |acc, b| acc | b),
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Overflow {
+    #[default]
+    Wrap,
+    Sat,
+    Fail,
+}
+
+/// A BITFIELD integer encoding such as `i16` or `u8`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitfieldType {
+    signed: bool,
+    bits: u32,
+}
+
+impl BitfieldType {
+    fn parse(arg: &Bytes) -> Result<Self, RedisError> {
+        let bits = bytes_to_number::<u32>(&arg.slice(1.min(arg.len())..)).ok();
+        let parsed = match (arg.first(), bits) {
+            (Some(b'i' | b'I'), Some(bits @ 1..=64)) => Some(Self { signed: true, bits }),
+            (Some(b'u' | b'U'), Some(bits @ 1..=63)) => Some(Self {
+                signed: false,
+                bits,
+            }),
+            _ => None,
+        };
+
+        parsed.ok_or_else(|| {
+            RedisError::Custom(
+                "Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is."
+                    .into(),
+            )
+        })
+    }
+
+    fn range(&self) -> (i128, i128) {
+        match self.signed {
+            true => (-(1 << (self.bits - 1)), (1 << (self.bits - 1)) - 1),
+            false => (0, (1 << self.bits) - 1),
+        }
+    }
+
+    fn read(&self, value: &[u8], offset: u64) -> i64 {
+        let raw =
+            (0..self.bits as u64).fold(0u64, |acc, i| acc << 1 | getbit(value, offset + i) as u64);
+        match self.signed && self.bits < 64 && raw >> (self.bits - 1) == 1 {
+            true => (raw | !((1u64 << self.bits) - 1)) as i64,
+            false => raw as i64,
+        }
+    }
+
+    fn write(&self, value: &mut BytesMut, offset: u64, field: i64) {
+        for i in 0..self.bits {
+            let bit = (field as u64 >> (self.bits - 1 - i)) & 1 == 1;
+            setbit(value, offset + i as u64, bit);
+        }
+    }
+
+    /// Brings a result into the range of the type, or None when it overflows and the
+    /// overflow mode is FAIL
+    fn fit(&self, result: i128, overflow: Overflow) -> Option<i64> {
+        let (min, max) = self.range();
+        if (min..=max).contains(&result) {
+            return Some(result as i64);
+        }
+
+        match overflow {
+            Overflow::Wrap => {
+                let modulus = 1i128 << self.bits;
+                let wrapped = result.rem_euclid(modulus);
+                Some(if wrapped > max {
+                    wrapped - modulus
+                } else {
+                    wrapped
+                } as i64)
+            }
+            Overflow::Sat => Some(if result > max { max } else { min } as i64),
+            Overflow::Fail => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitfieldKind {
+    Get,
+    Set(i64),
+    IncrBy(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitfieldOp {
+    kind: BitfieldKind,
+    ty: BitfieldType,
+    offset: u64,
+    overflow: Overflow,
+}
+
+impl BitfieldOp {
+    /// Parses the subcommands of BITFIELD, or of BITFIELD_RO when `read_only` is set. OVERFLOW
+    /// applies to the SET and INCRBY operations that follow it
+    pub fn parse_all(
+        args: &[Bytes],
+        max_len: usize,
+        read_only: bool,
+    ) -> Result<Vec<Self>, RedisError> {
+        let mut ops = Vec::new();
+        let mut overflow = Overflow::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let sub = arg.to_ascii_uppercase();
+            if &sub[..] == b"OVERFLOW" {
+                let mode = args.next().ok_or(RedisError::Syntax)?;
+                overflow = match &mode.to_ascii_uppercase()[..] {
+                    b"WRAP" => Overflow::Wrap,
+                    b"SAT" => Overflow::Sat,
+                    b"FAIL" => Overflow::Fail,
+                    _ => return Err(RedisError::Custom("Invalid OVERFLOW type specified".into())),
+                };
+                continue;
+            }
+
+            let (Some(ty), Some(offset)) = (args.next(), args.next()) else {
+                return Err(RedisError::Syntax);
+            };
+            let ty = BitfieldType::parse(ty)?;
+            let offset = parse_offset(offset, Some(ty.bits), max_len)?;
+
+            let kind = match &sub[..] {
+                b"GET" => BitfieldKind::Get,
+                b"SET" | b"INCRBY" if read_only => {
+                    return Err(RedisError::Custom(
+                        "BITFIELD_RO only supports the GET subcommand".into(),
+                    ))
+                }
+                b"SET" => {
+                    BitfieldKind::Set(bytes_to_number(args.next().ok_or(RedisError::Syntax)?)?)
+                }
+                b"INCRBY" => {
+                    BitfieldKind::IncrBy(bytes_to_number(args.next().ok_or(RedisError::Syntax)?)?)
+                }
+                _ => return Err(RedisError::Syntax),
+            };
+
+            ops.push(Self {
+                kind,
+                ty,
+                offset,
+                overflow,
+            });
+        }
+
+        Ok(ops)
+    }
+
+    pub fn is_write(&self) -> bool {
+        self.kind != BitfieldKind::Get
+    }
+
+    /// Runs the operation, returning what BITFIELD replies for it: the value read, the
+    /// previous value for SET and the new value for INCRBY
+    pub fn apply(&self, value: &mut BytesMut) -> Option<i64> {
+        let current = self.ty.read(value, self.offset);
+        let (result, reply) = match self.kind {
+            BitfieldKind::Get => return Some(current),
+            BitfieldKind::Set(field) => {
+                // Unsigned fields take the two's complement of a negative value, as Redis does
+                let field = match self.ty.signed {
+                    true => field as i128,
+                    false => field as u64 as i128,
+                };
+                let result = self.ty.fit(field, self.overflow)?;
+                (result, current)
+            }
+            BitfieldKind::IncrBy(delta) => {
+                let result = self
+                    .ty
+                    .fit(current as i128 + delta as i128, self.overflow)?;
+                (result, result)
+            }
+        };
+
+        self.ty.write(value, self.offset, result);
+        Some(reply)
+    }
+
+    pub fn read(&self, value: &[u8]) -> i64 {
+        self.ty.read(value, self.offset)
+    }
+
+    /// Whether this is a SET or INCRBY rather than a GET
+    pub fn writes(&self) -> bool {
+        self.kind != BitfieldKind::Get
+    }
+
+    /// Length in bytes a value needs to hold this field
+    pub fn required_len(&self) -> usize {
+        ((self.offset + self.ty.bits as u64).div_ceil(8)) as usize
+    }
+}
+
+#[cfg(test)]
+mod bitmap_tests {
+    use super::*;
+
+    fn args(args: &[&'static str]) -> Vec<Bytes> {
+        args.iter().map(|a| Bytes::from(*a)).collect()
+    }
+
+    #[test]
+    fn count_and_pos() -> Result<(), RedisError> {
+        let value = b"foobar";
+        assert_eq!(bitcount(value, &BitRange::default()), 26);
+        assert_eq!(bitcount(value, &BitRange::parse(&args(&["1", "1"]))?), 6);
+        assert_eq!(
+            bitcount(value, &BitRange::parse(&args(&["5", "30", "BIT"]))?),
+            17
+        );
+        assert_eq!(bitcount(value, &BitRange::parse(&args(&["-2", "-3"]))?), 0);
+
+        let value = [0xff, 0xf0, 0x00];
+        assert_eq!(bitpos(&value, false, &BitRange::default()), 12);
+        assert_eq!(
+            bitpos(&value, true, &BitRange::parse(&args(&["2", "-1", "BYTE"]))?),
+            -1
+        );
+        assert_eq!(
+            bitpos(&value, true, &BitRange::parse(&args(&["7", "15", "BIT"]))?),
+            7
+        );
+
+        // Clear bits past the end only count when no end was given
+        let value = [0xff];
+        assert_eq!(bitpos(&value, false, &BitRange::default()), 8);
+        assert_eq!(
+            bitpos(&value, false, &BitRange::parse(&args(&["0", "-1"]))?),
+            -1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn bitop() -> Result<(), RedisError> {
+        let (a, b): (&[u8], &[u8]) = (&[0b1100, 0xff], &[0b1010]);
+        assert_eq!(
+            BitOp::parse(&"and".into(), 2)?.apply(&[a, b]),
+            vec![0b1000, 0]
+        );
+        assert_eq!(
+            BitOp::parse(&"OR".into(), 2)?.apply(&[a, b]),
+            vec![0b1110, 0xff]
+        );
+        assert_eq!(
+            BitOp::parse(&"XOR".into(), 2)?.apply(&[a, b]),
+            vec![0b0110, 0xff]
+        );
+        assert_eq!(
+            BitOp::parse(&"DIFF".into(), 2)?.apply(&[a, b]),
+            vec![0b0100, 0xff]
+        );
+        assert_eq!(BitOp::parse(&"NOT".into(), 1)?.apply(&[b]), vec![!0b1010]);
+        assert!(BitOp::parse(&"NOT".into(), 2).is_err());
+        assert!(BitOp::parse(&"DIFF".into(), 1).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn bitfield() -> Result<(), RedisError> {
+        let ops = BitfieldOp::parse_all(
+            &args(&[
+                "SET", "i8", "#0", "100", "INCRBY", "i8", "0", "100", "OVERFLOW", "SAT", "INCRBY",
+                "u4", "8", "20", "OVERFLOW", "FAIL", "INCRBY", "u4", "8", "1", "GET", "i8", "0",
+            ]),
+            1024,
+            false,
+        )?;
+
+        let mut value = BytesMut::new();
+        let replies: Vec<_> = ops.iter().map(|op| op.apply(&mut value)).collect();
+        assert_eq!(replies, vec![Some(0), Some(-56), Some(15), None, Some(-56)]);
+        assert_eq!(&value[..], [0xc8, 0xf0]);
+
+        assert!(BitfieldOp::parse_all(&args(&["GET", "u64", "0"]), 1024, false).is_err());
+        assert!(BitfieldOp::parse_all(&args(&["SET", "u8", "0", "1"]), 1024, true).is_err());
+        assert!(BitfieldOp::parse_all(&args(&["GET", "u8", "8192"]), 1024, true).is_err());
+
+        Ok(())
+    }
+}
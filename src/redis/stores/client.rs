@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use crate::redis::protocol::ProtocolVersion;
+
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub protocol: ProtocolVersion,
+    pub name: Option<Bytes>,
+    /// Database selected with SELECT
+    pub db: usize,
+}
+
+#[derive(Debug)]
+pub struct ClientStore {
+    next_id: u64,
+    clients: HashMap<Bytes, ClientInfo>,
+}
+
+impl ClientStore {
+    pub fn new() -> Self {
+        Self {
+            next_id: 1,
+            clients: HashMap::new(),
+        }
+    }
+
+    pub fn protocol(&self, client_id: &Bytes) -> ProtocolVersion {
+        match self.clients.get(client_id) {
+            Some(client) => client.protocol,
+            None => ProtocolVersion::default(),
+        }
+    }
+
+    pub fn db(&self, client_id: &Bytes) -> usize {
+        self.clients.get(client_id).map_or(0, |client| client.db)
+    }
+
+    pub fn unregister(&mut self, client_id: &Bytes) {
+        self.clients.remove(client_id);
+    }
+
+    pub fn get_or_register(&mut self, client_id: &Bytes) -> &mut ClientInfo {
+        self.clients.entry(client_id.clone()).or_insert_with(|| {
+            let id = self.next_id;
+            self.next_id += 1;
+
+            ClientInfo {
+                id,
+                protocol: ProtocolVersion::default(),
+                name: None,
+                db: 0,
+            }
+        })
+    }
+}
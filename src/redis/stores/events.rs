@@ -0,0 +1,240 @@
+use bytes::Bytes;
+
+use super::{read_lock, write_lock, GlobalStore};
+use crate::redis::protocol::RedisError;
+
+// Keyspace notifications go out as ordinary pub/sub messages. An event on a key is sent
+// to __keyspace@<db>__:<key> with the event as the message when K is set, and to
+// __keyevent@<db>__:<event> with the key as the message when E is set
+
+/// Kinds of event, each enabled by one character of the notify-keyspace-events config
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventClass {
+    /// Commands that work on any type, like DEL, EXPIRE and RENAME
+    Generic,
+    String,
+    List,
+    Set,
+    Hash,
+    SortedSet,
+    Stream,
+    /// A key deleted once its TTL passed
+    Expired,
+    /// A key deleted to get under maxmemory
+    Evicted,
+    /// A key a command read but didn't find
+    KeyMiss,
+    /// A key added to a database
+    New,
+}
+
+const KEYSPACE: u16 = 1 << 0;
+const KEYEVENT: u16 = 1 << 1;
+const MODULE: u16 = 1 << 2;
+
+// The order Redis lists the classes in when giving the config back
+const CLASSES: [(char, EventClass); 9] = [
+    ('g', EventClass::Generic),
+    ('$', EventClass::String),
+    ('l', EventClass::List),
+    ('s', EventClass::Set),
+    ('h', EventClass::Hash),
+    ('z', EventClass::SortedSet),
+    ('x', EventClass::Expired),
+    ('e', EventClass::Evicted),
+    ('t', EventClass::Stream),
+];
+
+impl EventClass {
+    const fn flag(self) -> u16 {
+        let bit = match self {
+            Self::Generic => 3,
+            Self::String => 4,
+            Self::List => 5,
+            Self::Set => 6,
+            Self::Hash => 7,
+            Self::SortedSet => 8,
+            Self::Expired => 9,
+            Self::Evicted => 10,
+            Self::Stream => 11,
+            Self::KeyMiss => 12,
+            Self::New => 13,
+        };
+        1 << bit
+    }
+}
+
+/// Every class 'A' stands for. Key misses and new keys have to be asked for by name
+const ALL: u16 = {
+    let mut all = MODULE;
+    let mut i = 0;
+    while i < CLASSES.len() {
+        all |= CLASSES[i].1.flag();
+        i += 1;
+    }
+    all
+};
+
+/// The notify-keyspace-events config. Nothing is sent unless K or E is set along with at
+/// least one class
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct KeyspaceEvents(u16);
+
+impl KeyspaceEvents {
+    pub fn parse(flags: &str) -> Result<Self, RedisError> {
+        let mut events = 0;
+        for c in flags.chars() {
+            events |= match c {
+                'A' => ALL,
+                'K' => KEYSPACE,
+                'E' => KEYEVENT,
+                'd' => MODULE,
+                'm' => EventClass::KeyMiss.flag(),
+                'n' => EventClass::New.flag(),
+                _ => match CLASSES.iter().find(|(name, _)| *name == c) {
+                    Some((_, class)) => class.flag(),
+                    None => {
+                        return Err(RedisError::Custom(
+                            "Invalid event class character. Use 'Ag$lshzxeKEtmdn'.".into(),
+                        ))
+                    }
+                },
+            };
+        }
+
+        Ok(Self(events))
+    }
+
+    pub fn wants(&self, class: EventClass) -> bool {
+        self.0 & (KEYSPACE | KEYEVENT) != 0 && self.0 & class.flag() != 0
+    }
+
+    /// Channels an event is published on, each with its message
+    pub fn messages(&self, event: &KeyspaceEvent) -> Vec<(Bytes, Bytes)> {
+        let mut messages = Vec::new();
+        if !self.wants(event.class) {
+            return messages;
+        }
+
+        if self.0 & KEYSPACE != 0 {
+            let mut channel = format!("__keyspace@{}__:", event.db).into_bytes();
+            channel.extend_from_slice(&event.key);
+            messages.push((channel.into(), Bytes::from_static(event.event.as_bytes())));
+        }
+        if self.0 & KEYEVENT != 0 {
+            let channel = format!("__keyevent@{}__:{}", event.db, event.event);
+            messages.push((channel.into(), event.key.clone()));
+        }
+
+        messages
+    }
+}
+
+impl std::fmt::Display for KeyspaceEvents {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut flags = String::new();
+        if self.0 & ALL == ALL {
+            flags.push('A');
+        } else {
+            for (name, class) in CLASSES {
+                if self.0 & class.flag() != 0 {
+                    flags.push(name);
+                }
+            }
+            if self.0 & MODULE != 0 {
+                flags.push('d');
+            }
+        }
+
+        let rest = [
+            ('K', KEYSPACE),
+            ('E', KEYEVENT),
+            ('m', EventClass::KeyMiss.flag()),
+            ('n', EventClass::New.flag()),
+        ];
+        for (name, flag) in rest {
+            if self.0 & flag != 0 {
+                flags.push(name);
+            }
+        }
+
+        write!(f, "{flags}")
+    }
+}
+
+/// Something that happened to a key, published if the config asks for its class
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyspaceEvent {
+    pub db: usize,
+    pub class: EventClass,
+    pub event: &'static str,
+    pub key: Bytes,
+}
+
+impl KeyspaceEvent {
+    pub fn new(db: usize, class: EventClass, event: &'static str, key: &Bytes) -> Self {
+        Self {
+            db,
+            class,
+            event,
+            key: key.clone(),
+        }
+    }
+}
+
+impl GlobalStore {
+    pub fn keyspace_events(&self) -> Result<KeyspaceEvents, RedisError> {
+        Ok(*read_lock(&self.keyspace_events)?)
+    }
+
+    pub fn set_keyspace_events(&self, events: KeyspaceEvents) -> Result<(), RedisError> {
+        *write_lock(&self.keyspace_events)? = events;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod events_tests {
+    use super::*;
+
+    #[test]
+    fn flags() -> Result<(), RedisError> {
+        assert_eq!(KeyspaceEvents::parse("")?.to_string(), "");
+        assert_eq!(KeyspaceEvents::parse("xE")?.to_string(), "xE");
+        assert_eq!(KeyspaceEvents::parse("KEA")?.to_string(), "AKE");
+        assert_eq!(KeyspaceEvents::parse("g$lshzxetdKE")?.to_string(), "AKE");
+        assert_eq!(KeyspaceEvents::parse("nmzK")?.to_string(), "zKmn");
+        assert!(KeyspaceEvents::parse("Kq").is_err());
+
+        // A class alone, or K and E alone, send nothing
+        assert!(!KeyspaceEvents::parse("A")?.wants(EventClass::Generic));
+        assert!(!KeyspaceEvents::parse("KE")?.wants(EventClass::Generic));
+        assert!(!KeyspaceEvents::parse("AKE")?.wants(EventClass::KeyMiss));
+        assert!(KeyspaceEvents::parse("Em")?.wants(EventClass::KeyMiss));
+
+        Ok(())
+    }
+
+    #[test]
+    fn messages() -> Result<(), RedisError> {
+        let expired = KeyspaceEvent::new(3, EventClass::Expired, "expired", &"key".into());
+        let channels = |flags: &str| -> Result<Vec<(Bytes, Bytes)>, RedisError> {
+            Ok(KeyspaceEvents::parse(flags)?.messages(&expired))
+        };
+
+        assert_eq!(channels("gKE")?, vec![]);
+        assert_eq!(
+            channels("Ex")?,
+            vec![("__keyevent@3__:expired".into(), "key".into())]
+        );
+        assert_eq!(
+            channels("AKE")?,
+            vec![
+                ("__keyspace@3__:key".into(), "expired".into()),
+                ("__keyevent@3__:expired".into(), "key".into()),
+            ]
+        );
+
+        Ok(())
+    }
+}
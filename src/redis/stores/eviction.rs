@@ -0,0 +1,276 @@
+use std::sync::atomic::Ordering;
+
+use bytes::Bytes;
+
+use super::keyspace::Keyspace;
+use super::{read_lock, write_lock, GlobalStore};
+use crate::redis::protocol::RedisError;
+use crate::redis::utils::{alloc, random_u64};
+
+// Like Redis, eviction doesn't look for the best key overall. It samples a few keys from
+// each database and evicts the best of those, repeating until memory is under the limit
+
+/// What happens once memory goes over maxmemory, the maxmemory-policy config
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum EvictionPolicy {
+    #[default]
+    NoEviction,
+    AllKeysLru,
+    AllKeysLfu,
+    AllKeysRandom,
+    VolatileLru,
+    VolatileLfu,
+    VolatileRandom,
+    VolatileTtl,
+}
+
+impl EvictionPolicy {
+    pub fn parse(name: &str) -> Result<Self, RedisError> {
+        let policy = match &name.to_ascii_lowercase()[..] {
+            "noeviction" => Self::NoEviction,
+            "allkeys-lru" => Self::AllKeysLru,
+            "allkeys-lfu" => Self::AllKeysLfu,
+            "allkeys-random" => Self::AllKeysRandom,
+            "volatile-lru" => Self::VolatileLru,
+            "volatile-lfu" => Self::VolatileLfu,
+            "volatile-random" => Self::VolatileRandom,
+            "volatile-ttl" => Self::VolatileTtl,
+            _ => {
+                return Err(RedisError::Custom(format!(
+                    "invalid maxmemory-policy '{name}'"
+                )))
+            }
+        };
+
+        Ok(policy)
+    }
+
+    /// Whether only keys with a TTL may be evicted
+    fn volatile(&self) -> bool {
+        matches!(
+            self,
+            Self::VolatileLru | Self::VolatileLfu | Self::VolatileRandom | Self::VolatileTtl
+        )
+    }
+}
+
+impl std::fmt::Display for EvictionPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::NoEviction => "noeviction",
+            Self::AllKeysLru => "allkeys-lru",
+            Self::AllKeysLfu => "allkeys-lfu",
+            Self::AllKeysRandom => "allkeys-random",
+            Self::VolatileLru => "volatile-lru",
+            Self::VolatileLfu => "volatile-lfu",
+            Self::VolatileRandom => "volatile-random",
+            Self::VolatileTtl => "volatile-ttl",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The maxmemory, maxmemory-policy and maxmemory-samples configs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaxMemory {
+    /// Bytes, no limit when 0
+    pub limit: usize,
+    pub policy: EvictionPolicy,
+    /// Keys sampled from each database to pick one to evict
+    pub samples: usize,
+}
+
+impl Default for MaxMemory {
+    fn default() -> Self {
+        Self {
+            limit: 0,
+            policy: EvictionPolicy::NoEviction,
+            samples: 5,
+        }
+    }
+}
+
+/// Keys evicted by `GlobalStore::evict` by database, and whether memory is still over
+/// the limit after it
+#[derive(Debug, Default)]
+pub struct Eviction {
+    pub evicted: Vec<(usize, Bytes)>,
+    /// Keys picked for being past their TTL, deleted as expired rather than evicted
+    pub expired: Vec<(usize, Bytes)>,
+    pub over_limit: bool,
+}
+
+impl Keyspace {
+    /// The best key to evict out of a sample, along with how good a pick it is. Keys past
+    /// their TTL are always the best pick
+    fn eviction_candidate(&self, policy: EvictionPolicy, samples: usize) -> Option<(u64, Bytes)> {
+        self.random_keys(samples, policy.volatile())
+            .into_iter()
+            .filter_map(|key| {
+                let score = match policy {
+                    _ if self.is_expired(&key) => u64::MAX,
+                    EvictionPolicy::AllKeysLru | EvictionPolicy::VolatileLru => {
+                        self.idle_ms(&key)?
+                    }
+                    EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu => {
+                        u8::MAX as u64 - self.frequency(&key)? as u64
+                    }
+                    EvictionPolicy::VolatileTtl => u64::MAX - 1 - self.expiry(&key)?,
+                    _ => random_u64(),
+                };
+                Some((score, key))
+            })
+            .max_by_key(|(score, _)| *score)
+    }
+}
+
+impl GlobalStore {
+    pub fn max_memory(&self) -> Result<MaxMemory, RedisError> {
+        Ok(*read_lock(&self.max_memory)?)
+    }
+
+    pub fn set_max_memory(&self, max_memory: MaxMemory) -> Result<(), RedisError> {
+        *write_lock(&self.max_memory)? = max_memory;
+        Ok(())
+    }
+
+    /// Number of keys evicted since startup
+    pub fn evicted_keys(&self) -> u64 {
+        self.evicted_keys.load(Ordering::Relaxed)
+    }
+
+    /// Evicts keys as the policy says until memory in use is back under maxmemory
+    pub fn evict(&self) -> Result<Eviction, RedisError> {
+        let max_memory = self.max_memory()?;
+        let mut eviction = Eviction::default();
+        if max_memory.limit == 0 {
+            return Ok(eviction);
+        }
+
+        while alloc::allocated() > max_memory.limit {
+            if max_memory.policy == EvictionPolicy::NoEviction {
+                eviction.over_limit = true;
+                break;
+            }
+
+            let mut best: Option<(u64, usize, Bytes)> = None;
+            for db in 0..self.databases.len() {
+                let keyspace = self.keyspace_reader(db)?;
+                if keyspace.key_count() == 0 {
+                    continue;
+                }
+
+                let candidate = keyspace.eviction_candidate(max_memory.policy, max_memory.samples);
+                if let Some((score, key)) = candidate {
+                    if best.as_ref().is_none_or(|(best, _, _)| score > *best) {
+                        best = Some((score, db, key));
+                    }
+                }
+            }
+
+            let Some((_, db, key)) = best else {
+                eviction.over_limit = true;
+                break;
+            };
+
+            let mut keyspace = self.keyspace_writer(db)?;
+            if keyspace.expire_if_needed(&key) {
+                eviction.expired.push((db, key));
+                continue;
+            }
+
+            // Taken out under the lock but freed once it's released
+            let evicted = keyspace.remove(&key);
+            drop(keyspace);
+            if evicted.is_some() {
+                drop(evicted);
+                self.evicted_keys.fetch_add(1, Ordering::Relaxed);
+                eviction.evicted.push((db, key));
+            }
+        }
+
+        Ok(eviction)
+    }
+}
+
+#[cfg(test)]
+mod eviction_tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::redis::stores::keyspace::Data;
+    use crate::redis::utils::clock::ManualClock;
+
+    #[test]
+    fn policies() -> Result<(), RedisError> {
+        assert_eq!(
+            EvictionPolicy::parse("AllKeys-LRU")?,
+            EvictionPolicy::AllKeysLru
+        );
+        assert_eq!(EvictionPolicy::VolatileTtl.to_string(), "volatile-ttl");
+        assert!(EvictionPolicy::parse("lru").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn candidates_follow_the_policy() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut keyspace = Keyspace::new(clock.clone());
+        let (old, new, volatile): (Bytes, Bytes, Bytes) = ("old".into(), "new".into(), "v".into());
+
+        keyspace.insert(&old, Data::String("1".into()));
+        clock.advance(10_000);
+        keyspace.insert(&new, Data::String("2".into()));
+        keyspace.insert(&volatile, Data::String("3".into()));
+        keyspace.set_expiry(&volatile, Some(60_000));
+
+        // Enough samples to see every key
+        let pick = |keyspace: &Keyspace, policy| {
+            keyspace
+                .eviction_candidate(policy, 10_000)
+                .map(|(_, key)| key)
+        };
+        assert_eq!(pick(&keyspace, EvictionPolicy::AllKeysLru), Some(old));
+        assert_eq!(
+            pick(&keyspace, EvictionPolicy::VolatileLru),
+            Some(volatile.clone())
+        );
+        assert_eq!(
+            pick(&keyspace, EvictionPolicy::VolatileTtl),
+            Some(volatile.clone())
+        );
+
+        keyspace.set_expiry(&volatile, None);
+        assert_eq!(pick(&keyspace, EvictionPolicy::VolatileRandom), None);
+    }
+
+    #[test]
+    fn expired_keys_are_expired_not_evicted() -> Result<(), RedisError> {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let store = GlobalStore::with_clock(clock.clone(), 2);
+        let (live, gone): (Bytes, Bytes) = ("live".into(), "gone".into());
+
+        store
+            .keyspace_writer(0)?
+            .insert(&live, Data::String("1".into()));
+        let mut keyspace = store.keyspace_writer(1)?;
+        keyspace.insert(&gone, Data::String("2".into()));
+        keyspace.set_expiry(&gone, Some(2_000));
+        drop(keyspace);
+        clock.advance(5_000);
+
+        // A limit nothing fits under, so every key goes and the expired one goes first
+        store.set_max_memory(MaxMemory {
+            limit: 1,
+            policy: EvictionPolicy::AllKeysRandom,
+            samples: 10,
+        })?;
+        let eviction = store.evict()?;
+        assert_eq!(eviction.expired, vec![(1, gone)]);
+        assert_eq!(eviction.evicted, vec![(0, live)]);
+        assert!(eviction.over_limit);
+        assert_eq!(store.evicted_keys(), 1);
+
+        Ok(())
+    }
+}
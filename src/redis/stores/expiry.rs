@@ -0,0 +1,172 @@
+use std::collections::BTreeSet;
+use std::ops::Bound;
+
+use bytes::Bytes;
+
+use super::scan;
+use crate::redis::protocol::RedisError;
+
+/// Options accepted by EXPIRE and friends after the key and time
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExpireOptions {
+    pub nx: bool,
+    pub xx: bool,
+    pub gt: bool,
+    pub lt: bool,
+}
+
+impl ExpireOptions {
+    pub fn parse(args: &[Bytes]) -> Result<Self, RedisError> {
+        let mut options = Self::default();
+
+        for arg in args {
+            match &arg.to_ascii_uppercase()[..] {
+                b"NX" => options.nx = true,
+                b"XX" => options.xx = true,
+                b"GT" => options.gt = true,
+                b"LT" => options.lt = true,
+                _ => {
+                    return Err(RedisError::Custom(format!(
+                        "Unsupported option {}",
+                        String::from_utf8_lossy(arg)
+                    )))
+                }
+            }
+        }
+
+        if options.nx && (options.xx || options.gt || options.lt) {
+            return Err(RedisError::Custom(
+                "NX and XX, GT or LT options at the same time are not compatible".into(),
+            ));
+        }
+
+        if options.gt && options.lt {
+            return Err(RedisError::Custom(
+                "GT and LT options at the same time are not compatible".into(),
+            ));
+        }
+
+        Ok(options)
+    }
+
+    /// Whether a key with the `current` deadline may be given the `new` one.
+    /// A key without a TTL counts as never expiring for GT and LT
+    pub fn allows(&self, current: Option<u64>, new: i64) -> bool {
+        match current {
+            None => !(self.xx || self.gt),
+            Some(current) => {
+                let current = current as i64;
+                !(self.nx || (self.gt && new <= current) || (self.lt && new >= current))
+            }
+        }
+    }
+}
+
+/// Keys that have a TTL, the deadlines themselves are kept with the values
+#[derive(Debug, Default)]
+pub struct ExpiryStore {
+    keys: BTreeSet<Bytes>,
+    /// The same keys by their position in scan order, to pick them at random
+    by_position: BTreeSet<(u64, Bytes)>,
+    // The active expire cycle resumes sampling after this key
+    cursor: Option<Bytes>,
+}
+
+impl ExpiryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, key: &Bytes) {
+        self.keys.insert(key.clone());
+        self.by_position.insert((scan::position(key), key.clone()));
+    }
+
+    pub fn remove(&mut self, key: &Bytes) {
+        self.keys.remove(key);
+        self.by_position.remove(&(scan::position(key), key.clone()));
+    }
+
+    /// Up to `count` keys with a TTL picked at random
+    pub fn random(&self, count: usize) -> Vec<Bytes> {
+        scan::random_sample(&self.by_position, count)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns up to `count` keys with a TTL, continuing from where the last call stopped
+    /// and wrapping around without repeating a key
+    pub fn sample(&mut self, count: usize) -> Vec<Bytes> {
+        let mut sampled = Vec::with_capacity(count);
+        if let Some(cursor) = &self.cursor {
+            let after = (Bound::Excluded(cursor), Bound::Unbounded);
+            let keys = self.keys.range::<Bytes, _>(after);
+            sampled.extend(keys.take(count).cloned());
+        }
+
+        if sampled.len() < count {
+            let remaining = count - sampled.len();
+            let wrapped = self
+                .keys
+                .iter()
+                .take(remaining)
+                .take_while(|key| sampled.first() != Some(*key))
+                .cloned()
+                .collect::<Vec<_>>();
+            sampled.extend(wrapped);
+        }
+
+        self.cursor = sampled.last().cloned();
+        sampled
+    }
+}
+
+#[cfg(test)]
+mod expiry_store_tests {
+    use super::*;
+
+    #[test]
+    fn expire_options() {
+        let parse = |args: &[&'static str]| {
+            let args: Vec<Bytes> = args.iter().map(|a| Bytes::from(*a)).collect();
+            ExpireOptions::parse(&args)
+        };
+
+        assert!(parse(&["nx", "xx"]).is_err());
+        assert!(parse(&["GT", "LT"]).is_err());
+        assert!(parse(&["EX"]).is_err());
+
+        let gt = parse(&["XX", "GT"]).unwrap();
+        assert!(!gt.allows(None, 100));
+        assert!(!gt.allows(Some(100), 100));
+        assert!(gt.allows(Some(100), 101));
+
+        let lt = parse(&["LT"]).unwrap();
+        assert!(lt.allows(None, 100));
+        assert!(!lt.allows(Some(100), 200));
+
+        let nx = parse(&["NX"]).unwrap();
+        assert!(nx.allows(None, 100));
+        assert!(!nx.allows(Some(100), 50));
+    }
+
+    #[test]
+    fn sampling() {
+        let mut expires = ExpiryStore::new();
+        for i in 0..10 {
+            expires.insert(&format!("key:{i}").into());
+        }
+
+        let first = expires.sample(4);
+        assert_eq!(first.first(), Some(&"key:0".into()));
+        assert_eq!(first.len(), 4);
+
+        // Resumes after the last sampled key then wraps around to the start
+        let second = expires.sample(20);
+        assert_eq!(second.len(), 10);
+        assert_eq!(second.first(), Some(&"key:4".into()));
+        assert_eq!(second.last(), Some(&"key:3".into()));
+    }
+}
@@ -0,0 +1,494 @@
+use bytes::Bytes;
+
+use crate::redis::protocol::RedisError;
+
+// HyperLogLogs are stored as strings in the same layout Redis uses, so they survive GET, SET
+// and replication byte for byte. A 16 byte header (magic, encoding, three unused bytes and
+// a little endian cached cardinality whose top bit marks it stale) is followed by either
+// 16384 packed 6 bit registers or the sparse run-length encoding of them
+
+const MAGIC: &[u8] = b"HYLL";
+const HEADER_LEN: usize = 16;
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+
+const P: u32 = 14;
+const Q: usize = 64 - P as usize;
+const REGISTERS: usize = 1 << P;
+const REGISTER_MAX: u8 = 63;
+const DENSE_LEN: usize = HEADER_LEN + REGISTERS * 6 / 8;
+
+/// Default for the hll-sparse-max-bytes config
+pub const SPARSE_MAX_BYTES: usize = 3000;
+
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+const HASH_SEED: u64 = 0xadc8_3b19;
+
+// Sparse opcodes: ZERO `00xxxxxx` and XZERO `01xxxxxx yyyyyyyy` are runs of empty
+// registers, VAL `1vvvvvxx` is a run of up to 4 registers holding the same value up to 32
+const ZERO_MAX_LEN: usize = 64;
+const VAL_MAX_VALUE: u8 = 32;
+const VAL_MAX_LEN: usize = 4;
+
+fn is_zero(op: u8) -> bool {
+    op & 0xc0 == 0
+}
+
+fn is_xzero(op: u8) -> bool {
+    op & 0xc0 == 0x40
+}
+
+fn is_val(op: u8) -> bool {
+    op & 0x80 != 0
+}
+
+fn val_value(op: u8) -> u8 {
+    ((op >> 2) & 0x1f) + 1
+}
+
+fn val_len(op: u8) -> usize {
+    (op & 0x3) as usize + 1
+}
+
+fn val(value: u8, len: usize) -> u8 {
+    0x80 | (value - 1) << 2 | (len - 1) as u8
+}
+
+fn zero_run(len: usize) -> Vec<u8> {
+    let len = len - 1;
+    match len < ZERO_MAX_LEN {
+        true => vec![len as u8],
+        false => vec![0x40 | (len >> 8) as u8, len as u8],
+    }
+}
+
+/// Number of registers covered by the opcode at `p` and its length in bytes
+fn opcode(sparse: &[u8], p: usize) -> Option<(usize, usize)> {
+    let op = sparse[p];
+    if is_zero(op) {
+        Some(((op & 0x3f) as usize + 1, 1))
+    } else if is_xzero(op) {
+        let next = *sparse.get(p + 1)?;
+        Some(((((op & 0x3f) as usize) << 8 | next as usize) + 1, 2))
+    } else {
+        Some((val_len(op), 1))
+    }
+}
+
+fn dense_get(registers: &[u8], index: usize) -> u8 {
+    let (byte, shift) = (index * 6 / 8, index * 6 % 8);
+    let low = registers[byte] as u16 >> shift;
+    let high = (registers.get(byte + 1).copied().unwrap_or(0) as u16) << (8 - shift);
+    (low | high) as u8 & REGISTER_MAX
+}
+
+fn dense_set(registers: &mut [u8], index: usize, value: u8) {
+    let (byte, shift) = (index * 6 / 8, index * 6 % 8);
+    registers[byte] &= !(REGISTER_MAX << shift);
+    registers[byte] |= value << shift;
+    // Registers starting in the low bits of a byte don't reach into the next one
+    if let Some(next) = registers.get_mut(byte + 1).filter(|_| shift > 2) {
+        *next &= !(REGISTER_MAX >> (8 - shift));
+        *next |= value >> (8 - shift);
+    }
+}
+
+/// MurmurHash64A, the hash Redis feeds elements through
+fn murmur64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap_or_default());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, b) in tail.iter().enumerate() {
+            h ^= (*b as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+/// Register an element lands in and the value it gives it: one more than the number of
+/// trailing zeros in the rest of the hash
+fn pattern(element: &[u8]) -> (usize, u8) {
+    let hash = murmur64a(element, HASH_SEED);
+    let index = (hash & (REGISTERS as u64 - 1)) as usize;
+    let hash = hash >> P | 1 << Q;
+    (index, hash.trailing_zeros() as u8 + 1)
+}
+
+/// Cardinality estimate from the histogram of register values, using the improved
+/// estimator from Otmar Ertl's paper as Redis does
+fn estimate(histogram: &[u32; 64]) -> u64 {
+    fn sigma(mut x: f64) -> f64 {
+        if x == 1.0 {
+            return f64::INFINITY;
+        }
+        let (mut y, mut z) = (1.0, x);
+        loop {
+            x *= x;
+            let previous = z;
+            z += x * y;
+            y += y;
+            if previous == z {
+                return z;
+            }
+        }
+    }
+
+    fn tau(mut x: f64) -> f64 {
+        if x == 0.0 || x == 1.0 {
+            return 0.0;
+        }
+        let (mut y, mut z) = (1.0, 1.0 - x);
+        loop {
+            x = x.sqrt();
+            let previous = z;
+            y *= 0.5;
+            z -= (1.0 - x).powi(2) * y;
+            if previous == z {
+                return z / 3.0;
+            }
+        }
+    }
+
+    let m = REGISTERS as f64;
+    let mut z = m * tau((m - histogram[Q + 1] as f64) / m);
+    for count in histogram[1..=Q].iter().rev() {
+        z += *count as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+/// Estimated number of distinct elements across a set of merged registers
+pub fn count_registers(registers: &[u8]) -> u64 {
+    let mut histogram = [0; 64];
+    for register in registers {
+        histogram[*register as usize] += 1;
+    }
+    estimate(&histogram)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyperLogLog {
+    data: Vec<u8>,
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        let mut data = vec![0; HEADER_LEN];
+        data[..MAGIC.len()].copy_from_slice(MAGIC);
+        data[4] = SPARSE;
+        data.extend(zero_run(REGISTERS));
+        Self { data }
+    }
+
+    /// Checks that a string value holds a HyperLogLog
+    pub fn parse(value: &[u8]) -> Result<Self, RedisError> {
+        let valid = value.len() >= HEADER_LEN
+            && value.starts_with(MAGIC)
+            && match value[4] {
+                DENSE => value.len() == DENSE_LEN,
+                SPARSE => true,
+                _ => false,
+            };
+
+        match valid {
+            true => Ok(Self {
+                data: value.to_vec(),
+            }),
+            false => Err(RedisError::InvalidHll),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.data.into()
+    }
+
+    fn is_sparse(&self) -> bool {
+        self.data[4] == SPARSE
+    }
+
+    pub fn invalidate_cache(&mut self) {
+        self.data[15] |= 0x80;
+    }
+
+    /// Adds an element, returning whether a register changed. Sparse encodings are promoted
+    /// to dense once they outgrow `sparse_max` bytes
+    pub fn add(&mut self, element: &[u8], sparse_max: usize) -> Result<bool, RedisError> {
+        let (index, value) = pattern(element);
+        self.set(index, value, sparse_max)
+    }
+
+    /// Raises the register at `index` to `value` if it is lower
+    fn set(&mut self, index: usize, value: u8, sparse_max: usize) -> Result<bool, RedisError> {
+        if self.is_sparse() {
+            return self.sparse_set(index, value, sparse_max);
+        }
+
+        let registers = &mut self.data[HEADER_LEN..];
+        if dense_get(registers, index) >= value {
+            return Ok(false);
+        }
+        dense_set(registers, index, value);
+        Ok(true)
+    }
+
+    // Mirrors hllSparseSet so the sparse bytes come out exactly as Redis would write them:
+    // the opcode covering the register is split in place and equal neighbouring VAL
+    // opcodes are merged afterwards
+    fn sparse_set(
+        &mut self,
+        index: usize,
+        value: u8,
+        sparse_max: usize,
+    ) -> Result<bool, RedisError> {
+        if value > VAL_MAX_VALUE {
+            return self.promote(index, value);
+        }
+
+        let (mut p, mut first, mut prev) = (HEADER_LEN, 0, None);
+        let (span, oplen) = loop {
+            if p >= self.data.len() {
+                return Err(RedisError::CorruptHll);
+            }
+            let (span, oplen) = opcode(&self.data, p).ok_or(RedisError::CorruptHll)?;
+            if index < first + span {
+                break (span, oplen);
+            }
+            prev = Some(p);
+            p += oplen;
+            first += span;
+        };
+
+        let op = self.data[p];
+        let last = first + span - 1;
+        let mut seq = Vec::with_capacity(5);
+        if is_val(op) {
+            let current = val_value(op);
+            if current >= value {
+                return Ok(false);
+            }
+            if index != first {
+                seq.push(val(current, index - first));
+            }
+            seq.push(val(value, 1));
+            if index != last {
+                seq.push(val(current, last - index));
+            }
+        } else {
+            if index != first {
+                seq.extend(zero_run(index - first));
+            }
+            seq.push(val(value, 1));
+            if index != last {
+                seq.extend(zero_run(last - index));
+            }
+        }
+
+        if seq.len() > oplen && self.data.len() + seq.len() - oplen > sparse_max {
+            return self.promote(index, value);
+        }
+        self.data.splice(p..p + oplen, seq);
+
+        // Merge equal VAL opcodes in the few opcodes around the change
+        let mut p = prev.unwrap_or(HEADER_LEN);
+        let mut scan = 5;
+        while p < self.data.len() && scan > 0 {
+            scan -= 1;
+            let op = self.data[p];
+            if is_xzero(op) {
+                p += 2;
+                continue;
+            }
+            if is_zero(op) {
+                p += 1;
+                continue;
+            }
+
+            if let Some(&next) = self.data.get(p + 1).filter(|next| is_val(**next)) {
+                let len = val_len(op) + val_len(next);
+                if val_value(op) == val_value(next) && len <= VAL_MAX_LEN {
+                    self.data[p + 1] = val(val_value(op), len);
+                    self.data.remove(p);
+                    continue;
+                }
+            }
+            p += 1;
+        }
+
+        Ok(true)
+    }
+
+    fn promote(&mut self, index: usize, value: u8) -> Result<bool, RedisError> {
+        self.make_dense()?;
+        self.set(index, value, 0)
+    }
+
+    /// Switches to the dense encoding, keeping the header and cached cardinality
+    fn make_dense(&mut self) -> Result<(), RedisError> {
+        let mut registers = empty_registers();
+        self.merge_into(&mut registers)?;
+
+        self.data[4] = DENSE;
+        self.data.truncate(HEADER_LEN);
+        self.data.resize(DENSE_LEN, 0);
+        for (i, register) in registers.iter().enumerate().filter(|(_, r)| **r > 0) {
+            dense_set(&mut self.data[HEADER_LEN..], i, *register);
+        }
+
+        Ok(())
+    }
+
+    /// Raises each of `registers` to the matching register of this HyperLogLog
+    pub fn merge_into(&self, registers: &mut [u8]) -> Result<(), RedisError> {
+        let data = &self.data[HEADER_LEN..];
+        if !self.is_sparse() {
+            for (i, register) in registers.iter_mut().enumerate() {
+                *register = (*register).max(dense_get(data, i));
+            }
+            return Ok(());
+        }
+
+        let (mut p, mut i) = (0, 0);
+        while p < data.len() {
+            let (span, oplen) = opcode(data, p).ok_or(RedisError::CorruptHll)?;
+            if i + span > REGISTERS {
+                return Err(RedisError::CorruptHll);
+            }
+            if is_val(data[p]) {
+                let value = val_value(data[p]);
+                for register in &mut registers[i..i + span] {
+                    *register = (*register).max(value);
+                }
+            }
+            p += oplen;
+            i += span;
+        }
+
+        match i == REGISTERS {
+            true => Ok(()),
+            false => Err(RedisError::CorruptHll),
+        }
+    }
+
+    /// Merges registers into this HyperLogLog, converting it to dense first when asked to
+    pub fn merge_from(
+        &mut self,
+        registers: &[u8],
+        dense: bool,
+        sparse_max: usize,
+    ) -> Result<(), RedisError> {
+        if dense && self.is_sparse() {
+            self.make_dense()?;
+        }
+
+        for (i, register) in registers.iter().enumerate().filter(|(_, r)| **r > 0) {
+            self.set(i, *register, sparse_max)?;
+        }
+        self.invalidate_cache();
+        Ok(())
+    }
+
+    pub fn is_dense(&self) -> bool {
+        !self.is_sparse()
+    }
+
+    /// The estimated cardinality, served from the cache in the header when it is fresh and
+    /// written back to it otherwise
+    pub fn count(&mut self) -> Result<u64, RedisError> {
+        if self.data[15] & 0x80 == 0 {
+            let cached = self.data[8..16].try_into().unwrap_or_default();
+            return Ok(u64::from_le_bytes(cached));
+        }
+
+        let mut registers = empty_registers();
+        self.merge_into(&mut registers)?;
+        let count = count_registers(&registers);
+        self.data[8..16].copy_from_slice(&count.to_le_bytes());
+        Ok(count)
+    }
+}
+
+/// Registers of an empty HyperLogLog, for merging several into one
+pub fn empty_registers() -> Vec<u8> {
+    vec![0; REGISTERS]
+}
+
+#[cfg(test)]
+mod hyperloglog_tests {
+    use super::*;
+
+    #[test]
+    fn sparse_encoding() -> Result<(), RedisError> {
+        let mut hll = HyperLogLog::new();
+        assert_eq!(&hll.data[HEADER_LEN..], &[0x7f, 0xff]);
+        assert_eq!(hll.count()?, 0);
+
+        // Splitting the XZERO run around a register, then merging its equal neighbour
+        assert!(hll.set(100, 3, 3000)?);
+        assert_eq!(&hll.data[HEADER_LEN..], &[0x40, 99, 0x88, 0x7f, 0x9a]);
+        assert!(hll.set(101, 3, 3000)?);
+        assert_eq!(&hll.data[HEADER_LEN..], &[0x40, 99, 0x89, 0x7f, 0x99]);
+        assert!(!hll.set(101, 2, 3000)?);
+
+        // Values past 32 don't fit a VAL opcode
+        assert!(hll.set(5, 40, 3000)?);
+        assert!(hll.is_dense());
+        assert_eq!(hll.data.len(), DENSE_LEN);
+        let mut registers = empty_registers();
+        hll.merge_into(&mut registers)?;
+        assert_eq!((registers[5], registers[100], registers[101]), (40, 3, 3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cardinality() -> Result<(), RedisError> {
+        let mut hll = HyperLogLog::new();
+        for i in 0..20_000 {
+            hll.add(format!("element:{i}").as_bytes(), 3000)?;
+        }
+        hll.invalidate_cache();
+
+        assert!(hll.is_dense());
+        let count = hll.count()? as f64;
+        assert!((count - 20_000.0).abs() < 20_000.0 * 0.02, "{count}");
+
+        let mut small = HyperLogLog::new();
+        for element in ["a", "b", "c", "d", "e", "f", "g"] {
+            small.add(element.as_bytes(), 3000)?;
+        }
+        small.invalidate_cache();
+        assert!(!small.is_dense());
+        assert_eq!(small.count()?, 7);
+
+        assert!(HyperLogLog::parse(b"HYLL").is_err());
+        assert!(HyperLogLog::parse(&hll.data[..100]).is_err());
+        assert!(HyperLogLog::parse(&small.data).is_ok());
+
+        Ok(())
+    }
+}
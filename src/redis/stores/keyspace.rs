@@ -0,0 +1,560 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use super::access::Access;
+use super::expiry::ExpiryStore;
+use super::scan::{self, ScanOptions};
+use super::sorted_set::SortedSet;
+use super::stream::Stream;
+use crate::redis::protocol::RedisError;
+use crate::redis::utils::{clock::Clock, glob::glob_match, random_u64};
+
+/// The value held by a key, one variant per Redis type
+#[derive(Debug, Clone)]
+pub enum Data {
+    String(Bytes),
+    List(Vec<Bytes>),
+    Set(HashSet<Bytes>),
+    Hash(HashMap<Bytes, Bytes>),
+    SortedSet(Box<SortedSet>),
+    Stream(Stream),
+}
+
+impl Data {
+    /// Name reported by TYPE
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::String(_) => "string",
+            Self::List(_) => "list",
+            Self::Set(_) => "set",
+            Self::Hash(_) => "hash",
+            Self::SortedSet(_) => "zset",
+            Self::Stream(_) => "stream",
+        }
+    }
+
+    /// Roughly how many allocations dropping the value frees, UNLINK frees values above a
+    /// threshold in the background
+    pub fn free_effort(&self) -> usize {
+        match self {
+            Self::String(_) => 1,
+            Self::List(list) => list.len(),
+            Self::Set(set) => set.len(),
+            Self::Hash(hash) => hash.len(),
+            Self::SortedSet(set) => set.len(),
+            Self::Stream(stream) => stream.len(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Self::List(list) => list.is_empty(),
+            Self::Set(set) => set.is_empty(),
+            Self::Hash(hash) => hash.is_empty(),
+            Self::SortedSet(set) => set.len() == 0,
+            Self::String(_) | Self::Stream(_) => false,
+        }
+    }
+}
+
+/// Implemented by the value of each type so commands get a typed reference to it, and
+/// WRONGTYPE when the key holds something else
+pub trait Typed: Default + Sized {
+    fn get(data: &Data) -> Option<&Self>;
+    fn get_mut(data: &mut Data) -> Option<&mut Self>;
+    fn into_data(self) -> Data;
+}
+
+// The coercions let a variant hold its value boxed, as sorted sets are to keep `Data` small
+macro_rules! typed {
+    ($type:ty, $variant:ident) => {
+        impl Typed for $type {
+            fn get(data: &Data) -> Option<&Self> {
+                match data {
+                    Data::$variant(value) => {
+                        let value: &Self = value;
+                        Some(value)
+                    }
+                    _ => None,
+                }
+            }
+
+            fn get_mut(data: &mut Data) -> Option<&mut Self> {
+                match data {
+                    Data::$variant(value) => {
+                        let value: &mut Self = value;
+                        Some(value)
+                    }
+                    _ => None,
+                }
+            }
+
+            fn into_data(self) -> Data {
+                Data::$variant(self.into())
+            }
+        }
+    };
+}
+
+typed!(Bytes, String);
+typed!(Vec<Bytes>, List);
+typed!(HashSet<Bytes>, Set);
+typed!(HashMap<Bytes, Bytes>, Hash);
+typed!(SortedSet, SortedSet);
+typed!(Stream, Stream);
+
+#[derive(Debug, Clone)]
+struct Entry {
+    data: Data,
+    /// Unix time in milliseconds
+    expires_at: Option<u64>,
+    access: Access,
+}
+
+impl Entry {
+    fn new(data: Data, now: u64) -> Self {
+        Self {
+            data,
+            expires_at: None,
+            access: Access::new(now),
+        }
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|at| at < now)
+    }
+}
+
+// Keys RANDOMKEY samples before walking the keyspace for one that hasn't expired
+const RANDOM_KEY_TRIES: usize = 100;
+
+/// Every key along with its value and TTL. Keys past their TTL are never handed out, and
+/// are deleted the next time they're written to or by the active expire cycle
+pub struct Keyspace {
+    entries: BTreeMap<Bytes, Entry>,
+    volatile: ExpiryStore,
+    /// Every key by its position in the order SCAN visits them
+    scan_order: BTreeSet<(u64, Bytes)>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Keyspace {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            volatile: ExpiryStore::new(),
+            scan_order: BTreeSet::new(),
+            clock,
+        }
+    }
+
+    /// Current unix time in milliseconds
+    pub fn now_ms(&self) -> u64 {
+        self.clock.now_ms()
+    }
+
+    fn live(&self, key: &Bytes) -> Option<&Entry> {
+        let now = self.now_ms();
+        self.entries.get(key).filter(|entry| !entry.is_expired(now))
+    }
+
+    // Like `live` but counts as an access of the key
+    fn touched(&self, key: &Bytes) -> Option<&Entry> {
+        let entry = self.live(key)?;
+        entry.access.touch(self.now_ms());
+        Some(entry)
+    }
+
+    fn touched_mut(&mut self, key: &Bytes) -> Option<&mut Entry> {
+        let now = self.now_ms();
+        let entry = self.entries.get_mut(key)?;
+        entry.access.touch(now);
+        Some(entry)
+    }
+
+    /// Whether the key is still stored but its TTL has passed
+    pub fn is_expired(&self, key: &Bytes) -> bool {
+        let now = self.now_ms();
+        self.entries
+            .get(key)
+            .is_some_and(|entry| entry.is_expired(now))
+    }
+
+    /// Deletes the key if its TTL has passed, returning whether it did
+    pub fn expire_if_needed(&mut self, key: &Bytes) -> bool {
+        if !self.is_expired(key) {
+            return false;
+        }
+
+        self.remove_entry(key);
+        true
+    }
+
+    pub fn contains(&self, key: &Bytes) -> bool {
+        self.live(key).is_some()
+    }
+
+    pub fn data(&self, key: &Bytes) -> Option<&Data> {
+        self.touched(key).map(|entry| &entry.data)
+    }
+
+    /// The value without counting as an access, for introspection
+    pub fn peek(&self, key: &Bytes) -> Option<&Data> {
+        self.live(key).map(|entry| &entry.data)
+    }
+
+    /// Records an access of the key, returning whether it exists
+    pub fn touch(&self, key: &Bytes) -> bool {
+        self.touched(key).is_some()
+    }
+
+    /// Milliseconds since the key was last accessed
+    pub fn idle_ms(&self, key: &Bytes) -> Option<u64> {
+        let now = self.now_ms();
+        self.live(key).map(|entry| entry.access.idle_ms(now))
+    }
+
+    /// Logarithmic access counter of the key, decayed while it goes unused
+    pub fn frequency(&self, key: &Bytes) -> Option<u8> {
+        let now = self.now_ms();
+        self.live(key).map(|entry| entry.access.frequency(now))
+    }
+
+    /// Sets how long the key has gone unused or how often it's used, returning whether
+    /// it exists
+    pub fn set_access(&self, key: &Bytes, idle_ms: Option<u64>, frequency: Option<u8>) -> bool {
+        let now = self.now_ms();
+        let Some(entry) = self.live(key) else {
+            return false;
+        };
+
+        if let Some(idle_ms) = idle_ms {
+            entry.access.set_idle_ms(now, idle_ms);
+        }
+        if let Some(frequency) = frequency {
+            entry.access.set_frequency(now, frequency);
+        }
+        true
+    }
+
+    pub fn key_type(&self, key: &Bytes) -> &'static str {
+        self.peek(key).map_or("none", Data::type_name)
+    }
+
+    pub fn get<T: Typed>(&self, key: &Bytes) -> Result<Option<&T>, RedisError> {
+        match self.data(key) {
+            Some(data) => T::get(data).map(Some).ok_or(RedisError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_mut<T: Typed>(&mut self, key: &Bytes) -> Result<Option<&mut T>, RedisError> {
+        self.expire_if_needed(key);
+        match self.touched_mut(key) {
+            Some(entry) => T::get_mut(&mut entry.data)
+                .map(Some)
+                .ok_or(RedisError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// The value of the key, created empty if the key doesn't exist. Callers that may leave
+    /// it empty should follow up with `remove_if_empty`
+    pub fn get_or_insert<T: Typed>(&mut self, key: &Bytes) -> Result<&mut T, RedisError> {
+        self.expire_if_needed(key);
+        if !self.entries.contains_key(key) {
+            let entry = Entry::new(T::default().into_data(), self.now_ms());
+            self.insert_entry(key, entry);
+        }
+
+        let entry = self.touched_mut(key).expect("inserted above if missing");
+        T::get_mut(&mut entry.data).ok_or(RedisError::WrongType)
+    }
+
+    /// Stores a value under the key, replacing whatever it held along with its TTL
+    pub fn insert(&mut self, key: &Bytes, data: Data) {
+        self.remove_entry(key);
+        let entry = Entry::new(data, self.now_ms());
+        self.insert_entry(key, entry);
+    }
+
+    pub fn remove(&mut self, key: &Bytes) -> Option<Data> {
+        self.expire_if_needed(key);
+        self.remove_entry(key).map(|entry| entry.data)
+    }
+
+    /// Deletes the key if its value is an empty collection, which Redis never keeps around
+    pub fn remove_if_empty(&mut self, key: &Bytes) {
+        if self.peek(key).is_some_and(Data::is_empty) {
+            self.remove(key);
+        }
+    }
+
+    /// Moves the value and TTL of `src` to `dst`. With `only_new` nothing happens and false
+    /// is returned if `dst` already exists
+    pub fn rename(&mut self, src: &Bytes, dst: &Bytes, only_new: bool) -> Result<bool, RedisError> {
+        self.expire_if_needed(src);
+        self.expire_if_needed(dst);
+        if !self.entries.contains_key(src) {
+            return Err(RedisError::NoSuchKey);
+        }
+
+        if self.entries.contains_key(dst) && (only_new || src == dst) {
+            return Ok(!only_new);
+        }
+
+        let entry = self.remove_entry(src).expect("checked the key exists");
+        self.remove(dst);
+        self.insert_entry(dst, entry);
+        Ok(true)
+    }
+
+    /// Copies the value and TTL of `src` to `dst`, returning false if `src` doesn't exist or
+    /// `dst` does and `replace` isn't set
+    pub fn copy(&mut self, src: &Bytes, dst: &Bytes, replace: bool) -> bool {
+        match self.live(src).cloned() {
+            Some(entry) => self.insert_copy(dst, entry, replace),
+            None => false,
+        }
+    }
+
+    /// Like `copy` with `dst` in another database
+    pub fn copy_to(&self, src: &Bytes, target: &mut Keyspace, dst: &Bytes, replace: bool) -> bool {
+        match self.live(src).cloned() {
+            Some(entry) => target.insert_copy(dst, entry, replace),
+            None => false,
+        }
+    }
+
+    fn insert_copy(&mut self, key: &Bytes, mut entry: Entry, replace: bool) -> bool {
+        if self.contains(key) && !replace {
+            return false;
+        }
+
+        // A copy is a new object as far as access tracking goes
+        entry.access = Access::new(self.now_ms());
+        self.remove(key);
+        self.insert_entry(key, entry);
+        true
+    }
+
+    /// Moves a key and its TTL to another database, returning false if the key doesn't
+    /// exist or the other database already has it
+    pub fn move_to(&mut self, key: &Bytes, target: &mut Keyspace) -> bool {
+        if !self.contains(key) || target.contains(key) {
+            return false;
+        }
+
+        let entry = self.remove_entry(key).expect("checked the key exists");
+        target.remove(key);
+        target.insert_entry(key, entry);
+        true
+    }
+
+    /// Number of keys, including expired ones that haven't been deleted yet
+    pub fn key_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Number of keys with a TTL, including expired ones that haven't been deleted yet
+    pub fn volatile_count(&self) -> usize {
+        self.volatile.len()
+    }
+
+    // Every key is added and removed through these two so the indexes stay in step
+    fn remove_entry(&mut self, key: &Bytes) -> Option<Entry> {
+        let entry = self.entries.remove(key)?;
+        self.volatile.remove(key);
+        self.scan_order.remove(&(scan::position(key), key.clone()));
+        Some(entry)
+    }
+
+    fn insert_entry(&mut self, key: &Bytes, entry: Entry) {
+        if entry.expires_at.is_some() {
+            self.volatile.insert(key);
+        }
+        self.scan_order.insert((scan::position(key), key.clone()));
+        self.entries.insert(key.clone(), entry);
+    }
+
+    /// Expiry deadline of the key as unix time in milliseconds
+    pub fn expiry(&self, key: &Bytes) -> Option<u64> {
+        self.live(key)?.expires_at
+    }
+
+    /// Sets or clears the deadline of an existing key, returning false if it doesn't exist
+    pub fn set_expiry(&mut self, key: &Bytes, at: Option<u64>) -> bool {
+        self.expire_if_needed(key);
+        let Some(entry) = self.entries.get_mut(key) else {
+            return false;
+        };
+
+        entry.expires_at = at;
+        match at {
+            Some(_) => self.volatile.insert(key),
+            None => self.volatile.remove(key),
+        }
+        true
+    }
+
+    /// Keys that haven't expired and match the glob-style pattern
+    pub fn keys(&self, pattern: &[u8]) -> Vec<Bytes> {
+        let now = self.now_ms();
+        let all_keys = pattern == b"*";
+        self.entries
+            .iter()
+            .filter(|(key, entry)| !entry.is_expired(now) && (all_keys || glob_match(pattern, key)))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// A random key that hasn't expired. Keys are sampled one at a time, and only when
+    /// `RANDOM_KEY_TRIES` samples are all expired does it walk on to the next live key
+    pub fn random_key(&self) -> Option<Bytes> {
+        let now = self.now_ms();
+        let live = |key: &Bytes| {
+            self.entries
+                .get(key)
+                .is_some_and(|entry| !entry.is_expired(now))
+        };
+
+        let sampled = (0..RANDOM_KEY_TRIES)
+            .map_while(|_| scan::random_sample(&self.scan_order, 1).pop())
+            .find(|key| live(key));
+        sampled.or_else(|| {
+            let start = (random_u64(), Bytes::new());
+            let after = self.scan_order.range(start.clone()..);
+            let before = self.scan_order.range(..start);
+            after
+                .chain(before)
+                .map(|(_, key)| key)
+                .find(|key| live(key))
+                .cloned()
+        })
+    }
+
+    /// Visits keys from the cursor on, returning the cursor to continue from and the keys
+    /// that pass the filters. Expired keys are left out but still count towards COUNT
+    pub fn scan(&self, options: &ScanOptions) -> (u64, Vec<Bytes>) {
+        let start = (scan::position_of_cursor(options.cursor), Bytes::new());
+        let visited = self
+            .scan_order
+            .range(start..)
+            .map(|(position, key)| (*position, key));
+
+        let (cursor, keys) = scan::batch(visited, options.count);
+        let keys = keys
+            .into_iter()
+            .filter(|key| {
+                self.peek(key)
+                    .is_some_and(|data| options.matches(key, Some(data.type_name())))
+            })
+            .cloned()
+            .collect();
+
+        (cursor, keys)
+    }
+
+    /// Up to `count` keys picked at random, only keys with a TTL if `volatile` is set
+    pub fn random_keys(&self, count: usize, volatile: bool) -> Vec<Bytes> {
+        if volatile {
+            self.volatile.random(count)
+        } else {
+            scan::random_sample(&self.scan_order, count)
+        }
+    }
+
+    /// Up to `count` keys with a TTL, for the active expire cycle
+    pub fn sample_volatile(&mut self, count: usize) -> Vec<Bytes> {
+        self.volatile.sample(count)
+    }
+}
+
+#[cfg(test)]
+mod keyspace_tests {
+    use super::*;
+    use crate::redis::utils::clock::ManualClock;
+
+    #[test]
+    fn typed_values() -> Result<(), RedisError> {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut keyspace = Keyspace::new(clock.clone());
+        let (key, list): (Bytes, Bytes) = ("key".into(), "list".into());
+
+        keyspace.insert(&key, Data::String("value".into()));
+        keyspace
+            .get_or_insert::<Vec<Bytes>>(&list)?
+            .push("a".into());
+        assert_eq!(keyspace.get::<Bytes>(&key)?, Some(&"value".into()));
+        assert_eq!(keyspace.key_type(&list), "list");
+        assert!(matches!(
+            keyspace.get::<Vec<Bytes>>(&key),
+            Err(RedisError::WrongType)
+        ));
+        assert!(keyspace.get_or_insert::<Bytes>(&list).is_err());
+
+        if let Some(list) = keyspace.get_mut::<Vec<Bytes>>(&list)? {
+            list.clear();
+        }
+        keyspace.remove_if_empty(&list);
+        assert_eq!(keyspace.key_type(&list), "none");
+
+        // Writing a key replaces its TTL, an expired key reads as missing
+        assert!(keyspace.set_expiry(&key, Some(1_000)));
+        assert_eq!(keyspace.expiry(&key), Some(1_000));
+        clock.advance(1);
+        assert_eq!(keyspace.get::<Bytes>(&key)?, None);
+        assert!(keyspace.is_expired(&key));
+        assert!(keyspace.get_or_insert::<Vec<Bytes>>(&key)?.is_empty());
+        assert_eq!(keyspace.expiry(&key), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rename_and_copy() -> Result<(), RedisError> {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut keyspace = Keyspace::new(clock);
+        let (a, b, c): (Bytes, Bytes, Bytes) = ("a".into(), "b".into(), "c".into());
+
+        assert!(matches!(
+            keyspace.rename(&a, &b, false),
+            Err(RedisError::NoSuchKey)
+        ));
+
+        keyspace.insert(&a, Data::String("1".into()));
+        keyspace.set_expiry(&a, Some(5_000));
+        keyspace.insert(&b, Data::String("2".into()));
+        assert!(!keyspace.rename(&a, &b, true)?);
+        assert!(keyspace.rename(&a, &b, false)?);
+        assert!(!keyspace.contains(&a));
+        assert_eq!(keyspace.get::<Bytes>(&b)?, Some(&"1".into()));
+        assert_eq!(keyspace.expiry(&b), Some(5_000));
+
+        assert!(keyspace.copy(&b, &c, false));
+        assert!(!keyspace.copy(&b, &c, false));
+        assert!(!keyspace.copy(&a, &c, true));
+        assert_eq!(keyspace.expiry(&c), Some(5_000));
+        assert_eq!(keyspace.sample_volatile(10), vec![b, c]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn random_key() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut keyspace = Keyspace::new(clock.clone());
+        assert_eq!(keyspace.random_key(), None);
+
+        let (live, expired): (Bytes, Bytes) = ("live".into(), "expired".into());
+        keyspace.insert(&live, Data::String("1".into()));
+        keyspace.insert(&expired, Data::String("2".into()));
+        keyspace.set_expiry(&expired, Some(1_000));
+        clock.advance(1);
+        for _ in 0..20 {
+            assert_eq!(keyspace.random_key(), Some(live.clone()));
+        }
+    }
+}
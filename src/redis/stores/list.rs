@@ -1,46 +1,37 @@
-use std::collections::BTreeMap;
-
 use bytes::Bytes;
 
-pub struct ListStore {
-    map: BTreeMap<Bytes, Vec<Bytes>>,
-}
+use super::keyspace::Keyspace;
+use crate::redis::protocol::RedisError;
 
-impl ListStore {
-    pub fn new() -> Self {
-        Self {
-            map: BTreeMap::new(),
-        }
+// List commands. Popping the last element deletes the key
+impl Keyspace {
+    pub fn llen(&self, key: &Bytes) -> Result<usize, RedisError> {
+        Ok(self.get::<Vec<Bytes>>(key)?.map_or(0, Vec::len))
     }
 
-    pub fn len(&self, key: &Bytes) -> usize {
-        match self.map.get(key) {
-            Some(entry) => entry.len(),
-            None => 0,
-        }
+    pub fn rpush(&mut self, key: &Bytes, elements: &[Bytes]) -> Result<usize, RedisError> {
+        let list = self.get_or_insert::<Vec<Bytes>>(key)?;
+        list.extend_from_slice(elements);
+        Ok(list.len())
     }
 
-    pub fn contains(&self, key: &Bytes) -> bool {
-        self.map.contains_key(key)
+    /// Inserts the elements at the head one at a time, so they end up in reverse order
+    pub fn lpush(&mut self, key: &Bytes, elements: &[Bytes]) -> Result<usize, RedisError> {
+        let list = self.get_or_insert::<Vec<Bytes>>(key)?;
+        list.splice(0..0, elements.iter().rev().cloned());
+        Ok(list.len())
     }
 
-    pub fn append(&mut self, key: &Bytes, element: &Bytes) -> usize {
-        let entry = self.map.entry(key.clone()).or_default();
-        entry.push(element.clone());
-
-        entry.len()
-    }
-
-    pub fn prepend(&mut self, key: &Bytes, element: &Bytes) -> usize {
-        let entry = self.map.entry(key.clone()).or_default();
-        entry.insert(0, element.clone());
-        entry.len()
-    }
-
-    pub fn slice(&self, key: &Bytes, start: i64, end: i64) -> Option<&[Bytes]> {
-        let list = self.map.get(key)?;
+    pub fn lrange(&self, key: &Bytes, start: i64, end: i64) -> Result<&[Bytes], RedisError> {
+        let Some(list) = self.get::<Vec<Bytes>>(key)? else {
+            return Ok(&[]);
+        };
 
         let list_size = list.len();
+        if list_size == 0 {
+            return Ok(&[]);
+        }
+
         let start = idx_calc(start, list_size);
         let mut end = idx_calc(end, list_size);
 
@@ -49,34 +40,35 @@ impl ListStore {
         }
 
         if start > end || start >= list.len() {
-            return None;
+            return Ok(&[]);
         }
 
-        Some(&list[start..=end])
+        Ok(&list[start..=end])
     }
 
-    pub fn remove(&mut self, key: &Bytes, to_remove: usize) -> Option<Vec<Bytes>> {
-        let list = self.map.get_mut(key)?;
-
-        if list.is_empty() {
-            return Some(Vec::new());
-        }
+    /// Removes up to `count` elements from the head, None if the key doesn't exist
+    pub fn lpop(&mut self, key: &Bytes, count: usize) -> Result<Option<Vec<Bytes>>, RedisError> {
+        let Some(list) = self.get_mut::<Vec<Bytes>>(key)? else {
+            return Ok(None);
+        };
 
-        let sub_list: Vec<Bytes> = list.drain(..to_remove).collect();
+        let count = count.min(list.len());
+        let popped = list.drain(..count).collect();
+        self.remove_if_empty(key);
 
-        Some(sub_list)
+        Ok(Some(popped))
     }
 
-    pub fn remove_single(&mut self, key: &Bytes) -> Option<Bytes> {
-        let list = self.map.get_mut(key)?;
-        Some(list.remove(0))
+    pub fn lpop_one(&mut self, key: &Bytes) -> Result<Option<Bytes>, RedisError> {
+        let popped = self.lpop(key, 1)?;
+        Ok(popped.and_then(|popped| popped.into_iter().next()))
     }
 }
 
 #[inline]
 fn idx_calc(int: i64, list_size: usize) -> usize {
     if int < 0 {
-        if i64::abs(int) > list_size as i64 {
+        if int.unsigned_abs() > list_size as u64 {
             0
         } else {
             (list_size as i64 + int) as usize
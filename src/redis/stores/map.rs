@@ -1,36 +1,122 @@
 use std::collections::BTreeMap;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 
+use super::bitmap::{self, BitOp, BitfieldOp};
+use super::hyperloglog::{self, HyperLogLog};
+use super::keyspace::{Data, Keyspace};
 use crate::redis::protocol::RedisError;
 use crate::redis::utils::bytes_to_number;
 
-struct MapStoreValue {
-    value: Bytes,
-    created: Instant,
-    ttl: Option<Duration>,
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SetCondition {
+    #[default]
+    Always,
+    NotExists,
+    Exists,
 }
 
-impl MapStoreValue {
-    pub fn new(value: Bytes, ttl: Option<Duration>) -> Self {
-        Self {
-            value,
-            created: Instant::now(),
-            ttl,
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SetExpiry {
+    #[default]
+    None,
+    KeepTtl,
+    In(Duration),
+    /// Unix time in milliseconds
+    At(u64),
+}
+
+/// Options accepted by SET after the key and value
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SetOptions {
+    pub condition: SetCondition,
+    pub expiry: SetExpiry,
+    pub get: bool,
+}
+
+impl SetOptions {
+    pub fn parse(args: &[Bytes]) -> Result<Self, RedisError> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let arg = arg.to_ascii_uppercase();
+            match &arg[..] {
+                b"NX" if options.condition != SetCondition::Exists => {
+                    options.condition = SetCondition::NotExists
+                }
+                b"XX" if options.condition != SetCondition::NotExists => {
+                    options.condition = SetCondition::Exists
+                }
+                b"GET" => options.get = true,
+                b"KEEPTTL" if options.expiry == SetExpiry::None => {
+                    options.expiry = SetExpiry::KeepTtl
+                }
+                b"EX" | b"PX" | b"EXAT" | b"PXAT" if options.expiry == SetExpiry::None => {
+                    options.expiry = SetExpiry::parse(&arg, args.next(), "set")?;
+                }
+                _ => return Err(RedisError::Syntax),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+impl SetExpiry {
+    /// Parses one of EX/PX/EXAT/PXAT and the time following it
+    fn parse(unit: &[u8], time: Option<&Bytes>, cmd: &str) -> Result<Self, RedisError> {
+        let invalid = || RedisError::Custom(format!("invalid expire time in '{cmd}' command"));
+
+        let time = bytes_to_number::<i64>(time.ok_or(RedisError::Syntax)?)?;
+        if time <= 0 {
+            return Err(invalid());
         }
+
+        // Seconds are converted up front so overflow is reported as an invalid time
+        let ms = match unit {
+            b"EX" | b"EXAT" => time.checked_mul(1000).ok_or_else(invalid)?,
+            _ => time,
+        } as u64;
+
+        Ok(match unit {
+            b"EX" | b"PX" => SetExpiry::In(Duration::from_millis(ms)),
+            _ => SetExpiry::At(ms),
+        })
     }
 
-    pub fn expired(&self) -> bool {
-        match self.ttl {
-            None => false,
-            Some(ex) => self.created.elapsed().as_millis() > ex.as_millis(),
+    /// Options accepted by GETEX. The TTL is left alone unless one is given and PERSIST
+    /// removes it, the same as SET without KEEPTTL
+    pub fn parse_getex(args: &[Bytes]) -> Result<Self, RedisError> {
+        let mut expiry = SetExpiry::KeepTtl;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let arg = arg.to_ascii_uppercase();
+            match &arg[..] {
+                b"PERSIST" if expiry == SetExpiry::KeepTtl => expiry = SetExpiry::None,
+                b"EX" | b"PX" | b"EXAT" | b"PXAT" if expiry == SetExpiry::KeepTtl => {
+                    expiry = SetExpiry::parse(&arg, args.next(), "getex")?;
+                }
+                _ => return Err(RedisError::Syntax),
+            }
         }
+
+        Ok(expiry)
     }
 }
 
+/// Outcome of a SET, `previous` is the value held before the call
+#[derive(Debug, Default)]
+pub struct SetResult {
+    pub written: bool,
+    pub previous: Option<Bytes>,
+}
+
+/// Plain byte map, used for the server config
 pub struct MapStore {
-    map: BTreeMap<Bytes, MapStoreValue>,
+    map: BTreeMap<Bytes, Bytes>,
 }
 
 impl MapStore {
@@ -40,96 +126,553 @@ impl MapStore {
         }
     }
 
-    pub fn contains(&self, key: &Bytes) -> bool {
-        self.map.contains_key(key)
+    pub fn get(&self, key: &Bytes) -> Option<&Bytes> {
+        self.map.get(key)
     }
 
-    pub fn get(&self, key: &Bytes) -> Option<&Bytes> {
-        let value = self.map.get(key)?;
+    pub fn insert(&mut self, key: &Bytes, value: &Bytes) {
+        self.map.insert(key.clone(), value.clone());
+    }
+}
+
+// String commands. A missing key reads as an empty string and values written back to an
+// existing key keep its TTL
+impl Keyspace {
+    fn string_or_empty(&self, key: &Bytes) -> Result<&[u8], RedisError> {
+        Ok(self.get::<Bytes>(key)?.map_or(&[][..], |v| &v[..]))
+    }
+
+    fn put_string(&mut self, key: &Bytes, value: Bytes) -> Result<(), RedisError> {
+        *self.get_or_insert::<Bytes>(key)? = value;
+        Ok(())
+    }
+
+    /// Changes a string in place, creating it empty if the key doesn't exist. Its buffer is
+    /// only copied while something else, like a reply being written, still shares it
+    fn update_string<T>(
+        &mut self,
+        key: &Bytes,
+        update: impl FnOnce(&mut BytesMut) -> T,
+    ) -> Result<T, RedisError> {
+        let value = self.get_or_insert::<Bytes>(key)?;
+        let mut buffer = BytesMut::from(std::mem::take(value));
+        let result = update(&mut buffer);
+        *value = buffer.freeze();
+        Ok(result)
+    }
+
+    pub fn strlen(&self, key: &Bytes) -> Result<usize, RedisError> {
+        Ok(self.string_or_empty(key)?.len())
+    }
+
+    /// Returns the length of the string after appending, `max_len` is the largest allowed
+    pub fn append(
+        &mut self,
+        key: &Bytes,
+        value: &Bytes,
+        max_len: usize,
+    ) -> Result<usize, RedisError> {
+        let current = self.string_or_empty(key)?;
+        check_string_len(current.len() + value.len(), max_len)?;
+
+        self.update_string(key, |current| {
+            current.extend_from_slice(value);
+            current.len()
+        })
+    }
+
+    /// Substring between two inclusive offsets, negative offsets count from the end
+    pub fn getrange(&self, key: &Bytes, start: i64, end: i64) -> Result<Bytes, RedisError> {
+        let Some(value) = self.get::<Bytes>(key)? else {
+            return Ok(Bytes::new());
+        };
+
+        let len = value.len() as i64;
+        if start < 0 && end < 0 && start > end {
+            return Ok(Bytes::new());
+        }
+
+        let start = if start < 0 { len + start } else { start }.max(0);
+        let end = if end < 0 { len + end } else { end }.max(0).min(len - 1);
+        if start > end || len == 0 {
+            return Ok(Bytes::new());
+        }
+
+        Ok(value.slice(start as usize..=end as usize))
+    }
+
+    /// Overwrites part of a string starting at `offset`, padding with zero bytes when the
+    /// string is shorter. Returns the length of the string afterwards
+    pub fn setrange(
+        &mut self,
+        key: &Bytes,
+        offset: usize,
+        value: &Bytes,
+        max_len: usize,
+    ) -> Result<usize, RedisError> {
+        let current = self.string_or_empty(key)?;
+        if value.is_empty() {
+            return Ok(current.len());
+        }
+
+        let end = offset.saturating_add(value.len());
+        check_string_len(end, max_len)?;
+
+        self.update_string(key, |current| {
+            if current.len() < end {
+                current.resize(end, 0);
+            }
+            current[offset..end].copy_from_slice(value);
+            current.len()
+        })
+    }
+
+    /// Sets a bit of the value, creating or growing it as needed, and returns the previous bit
+    pub fn setbit(&mut self, key: &Bytes, offset: u64, bit: bool) -> Result<bool, RedisError> {
+        self.update_string(key, |value| bitmap::setbit(value, offset, bit))
+    }
+
+    /// Runs BITFIELD operations in order. A key is only created or grown when there are
+    /// writes, even if they all fail on overflow
+    pub fn bitfield(
+        &mut self,
+        key: &Bytes,
+        ops: &[BitfieldOp],
+    ) -> Result<Vec<Option<i64>>, RedisError> {
+        let writes = ops.iter().filter(|op| op.is_write());
+        let Some(len) = writes.map(BitfieldOp::required_len).max() else {
+            let value = self.string_or_empty(key)?;
+            return Ok(ops.iter().map(|op| Some(op.read(value))).collect());
+        };
+
+        self.update_string(key, |value| {
+            if value.len() < len {
+                value.resize(len, 0);
+            }
+            ops.iter().map(|op| op.apply(value)).collect()
+        })
+    }
+
+    /// BITOP, storing the result in `dest` and removing its TTL. An empty result deletes it
+    pub fn bitop(
+        &mut self,
+        op: BitOp,
+        dest: &Bytes,
+        sources: &[Bytes],
+    ) -> Result<usize, RedisError> {
+        let values = sources
+            .iter()
+            .map(|key| self.string_or_empty(key))
+            .collect::<Result<Vec<_>, _>>()?;
+        let result = op.apply(&values);
+        let len = result.len();
+
+        match len {
+            0 => {
+                self.remove(dest);
+            }
+            _ => self.insert(dest, Data::String(result.into())),
+        }
+
+        Ok(len)
+    }
+
+    /// PFADD, returning whether the HyperLogLog was created or changed
+    pub fn pfadd(
+        &mut self,
+        key: &Bytes,
+        elements: &[Bytes],
+        sparse_max: usize,
+    ) -> Result<bool, RedisError> {
+        let (mut hll, mut updated) = match self.get::<Bytes>(key)? {
+            Some(value) => (HyperLogLog::parse(value)?, false),
+            None => (HyperLogLog::new(), true),
+        };
+
+        for element in elements {
+            updated |= hll.add(element, sparse_max)?;
+        }
 
-        // TODO: Cleanup of expired values
-        if value.expired() {
-            return None;
+        if updated {
+            hll.invalidate_cache();
+            self.put_string(key, hll.into_bytes())?;
         }
 
-        Some(&value.value)
+        Ok(updated)
     }
 
-    pub fn list(&self) -> Vec<&Bytes> {
-        self.map.keys().collect()
+    /// PFCOUNT. A single key keeps the computed cardinality cached in its header, several keys
+    /// are counted as their union
+    pub fn pfcount(&mut self, keys: &[Bytes]) -> Result<u64, RedisError> {
+        if let [key] = keys {
+            let Some(value) = self.get::<Bytes>(key)? else {
+                return Ok(0);
+            };
+
+            let mut hll = HyperLogLog::parse(value)?;
+            let count = hll.count()?;
+            if hll.as_bytes() != &value[..] {
+                self.put_string(key, hll.into_bytes())?;
+            }
+            return Ok(count);
+        }
+
+        let mut registers = hyperloglog::empty_registers();
+        for key in keys {
+            if let Some(value) = self.get::<Bytes>(key)? {
+                HyperLogLog::parse(value)?.merge_into(&mut registers)?;
+            }
+        }
+        Ok(hyperloglog::count_registers(&registers))
     }
 
+    /// PFMERGE, the destination is part of the union and becomes dense if any input is
+    pub fn pfmerge(
+        &mut self,
+        dest: &Bytes,
+        sources: &[Bytes],
+        sparse_max: usize,
+    ) -> Result<(), RedisError> {
+        let mut registers = hyperloglog::empty_registers();
+        let mut dense = false;
+        for key in std::iter::once(dest).chain(sources) {
+            if let Some(value) = self.get::<Bytes>(key)? {
+                let hll = HyperLogLog::parse(value)?;
+                dense |= hll.is_dense();
+                hll.merge_into(&mut registers)?;
+            }
+        }
+
+        let mut hll = match self.get::<Bytes>(dest)? {
+            Some(value) => HyperLogLog::parse(value)?,
+            None => HyperLogLog::new(),
+        };
+        hll.merge_from(&registers, dense, sparse_max)?;
+        self.put_string(dest, hll.into_bytes())
+    }
+
+    /// SET overwrites a key of any type. `previous` is only looked up for the GET option,
+    /// which fails on keys that don't hold a string
     pub fn set(
         &mut self,
         key: &Bytes,
         value: &Bytes,
-        ttl: Option<Bytes>,
-    ) -> Result<(), RedisError> {
-        let ttl = if let Some(ex) = ttl {
-            let ms_str = str::from_utf8(&ex).map_err(|_| RedisError::StringConversion)?;
-            let ms = ms_str.parse::<u64>().map_err(|_| RedisError::NumberParse)?;
+        options: &SetOptions,
+    ) -> Result<SetResult, RedisError> {
+        let previous = match options.get {
+            true => self.get::<Bytes>(key)?.cloned(),
+            false => None,
+        };
 
-            Some(Duration::from_millis(ms))
-        } else {
-            None
+        let exists = self.contains(key);
+        let written = match options.condition {
+            SetCondition::Always => true,
+            SetCondition::NotExists => !exists,
+            SetCondition::Exists => exists,
         };
 
-        let store_value = MapStoreValue::new(value.clone(), ttl);
+        if written {
+            let ttl = self.expiry(key);
+            self.insert(key, Data::String(value.clone()));
+            if options.expiry == SetExpiry::KeepTtl {
+                self.set_expiry(key, ttl);
+            }
+            self.apply_expiry(key, options.expiry);
+        }
 
-        self.map.insert(key.clone(), store_value);
-        Ok(())
+        Ok(SetResult { written, previous })
     }
 
-    pub fn incr(&mut self, key: &Bytes) -> Result<i64, RedisError> {
-        let thing = self
-            .map
-            .entry(key.clone())
-            .or_insert(MapStoreValue::new("0".into(), None));
+    /// MSET and MSETNX. With `only_new` nothing is written if any of the keys exist
+    pub fn mset(&mut self, pairs: &[(Bytes, Bytes)], only_new: bool) -> bool {
+        if only_new && pairs.iter().any(|(key, _)| self.contains(key)) {
+            return false;
+        }
+
+        for (key, value) in pairs {
+            self.insert(key, Data::String(value.clone()));
+        }
+        true
+    }
 
-        let n = match bytes_to_number::<i64>(&thing.value) {
-            Ok(value) => {
-                thing.value = format!("{}", value + 1).into();
-                value + 1
+    fn apply_expiry(&mut self, key: &Bytes, expiry: SetExpiry) {
+        match expiry {
+            SetExpiry::None => {
+                self.set_expiry(key, None);
+            }
+            SetExpiry::KeepTtl => {}
+            SetExpiry::In(ttl) => {
+                let at = self.now_ms() + ttl.as_millis() as u64;
+                self.set_expiry(key, Some(at));
+            }
+            SetExpiry::At(at) => {
+                self.set_expiry(key, Some(at));
             }
-            Err(_) => return Err(RedisError::NumberParse),
+        }
+    }
+
+    /// GETEX, returns the value and updates its TTL if the key exists
+    pub fn get_ex(&mut self, key: &Bytes, expiry: SetExpiry) -> Result<Option<Bytes>, RedisError> {
+        let value = self.get::<Bytes>(key)?.cloned();
+        if value.is_some() {
+            self.apply_expiry(key, expiry);
+            self.expire_if_needed(key);
+        }
+
+        Ok(value)
+    }
+
+    /// GETDEL, removes the key along with its TTL
+    pub fn get_del(&mut self, key: &Bytes) -> Result<Option<Bytes>, RedisError> {
+        let value = self.get::<Bytes>(key)?.cloned();
+        if value.is_some() {
+            self.remove(key);
+        }
+
+        Ok(value)
+    }
+
+    pub fn incr_by(&mut self, key: &Bytes, delta: i64) -> Result<i64, RedisError> {
+        let current = match self.get::<Bytes>(key)? {
+            Some(value) => bytes_to_number::<i64>(value)?,
+            None => 0,
         };
 
-        Ok(n)
+        let value = current.checked_add(delta).ok_or(RedisError::Overflow)?;
+        self.put_string(key, format!("{value}").into())?;
+        Ok(value)
+    }
+
+    /// Returns the new value formatted the way it's stored
+    pub fn incr_by_float(&mut self, key: &Bytes, delta: f64) -> Result<Bytes, RedisError> {
+        let current = match self.get::<Bytes>(key)? {
+            Some(value) => parse_float(value)?,
+            None => 0.0,
+        };
+
+        let value = current + delta;
+        if !value.is_finite() {
+            return Err(RedisError::Custom(
+                "increment would produce NaN or Infinity".into(),
+            ));
+        }
+
+        let value: Bytes = human_float(value).into();
+        self.put_string(key, value.clone())?;
+        Ok(value)
+    }
+}
+
+fn check_string_len(len: usize, max_len: usize) -> Result<(), RedisError> {
+    if len > max_len {
+        return Err(RedisError::Custom(
+            "string exceeds maximum allowed size (proto-max-bulk-len)".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Formats a float like Redis does for INCRBYFLOAT: fixed point with trailing zeros removed.
+/// Redis adds in long double precision, so rounding to 15 significant digits first keeps
+/// results like 0.1 + 0.2 from showing binary rounding errors
+fn human_float(value: f64) -> String {
+    let rounded = format!("{value:.14e}").parse::<f64>().unwrap_or(value);
+    format!("{rounded}")
+}
+
+/// Parses a float the way INCRBYFLOAT does, NaN and surrounding spaces aren't allowed
+pub fn parse_float(b: &Bytes) -> Result<f64, RedisError> {
+    let s = std::str::from_utf8(b).map_err(|_| RedisError::FloatParse)?;
+    match s.parse::<f64>() {
+        Ok(value) if !value.is_nan() && s.trim() == s => Ok(value),
+        _ => Err(RedisError::FloatParse),
     }
 }
 
 #[cfg(test)]
 mod map_store_tests {
+    use std::sync::Arc;
+
     use super::*;
+    use crate::redis::utils::clock::SystemClock;
 
-    #[test]
-    fn expired_values() {
-        let v1 = MapStoreValue::new(Bytes::new(), None);
-        assert!(!v1.expired());
+    fn keyspace() -> Keyspace {
+        Keyspace::new(Arc::new(SystemClock))
+    }
 
-        let v2 = MapStoreValue::new(Bytes::new(), Some(Duration::from_millis(200)));
-        assert!(!v2.expired());
-        std::thread::sleep(Duration::from_millis(300));
-        assert!(v2.expired());
+    fn string(value: &str) -> Data {
+        Data::String(Bytes::copy_from_slice(value.as_bytes()))
     }
 
     #[test]
-    fn store_test() -> Result<(), RedisError> {
+    fn store_test() {
         let mut ms = MapStore::new();
-        ms.set(&"hello".into(), &"there".into(), None)?;
+        ms.insert(&"hello".into(), &"there".into());
         assert!(ms.get(&"hello".into()).is_some());
         assert!(ms.get(&"not present".into()).is_none());
+    }
+
+    #[test]
+    fn set_options() -> Result<(), RedisError> {
+        let parse = |args: &[&'static str]| {
+            let args: Vec<Bytes> = args.iter().map(|a| Bytes::from(*a)).collect();
+            SetOptions::parse(&args)
+        };
+
+        let options = parse(&["nx", "EX", "10", "GET"])?;
+        assert_eq!(options.condition, SetCondition::NotExists);
+        assert_eq!(options.expiry, SetExpiry::In(Duration::from_secs(10)));
+        assert!(options.get);
+
+        assert_eq!(parse(&["PXAT", "1000"])?.expiry, SetExpiry::At(1000));
+        assert!(matches!(parse(&["NX", "XX"]), Err(RedisError::Syntax)));
+        assert!(matches!(
+            parse(&["EX", "1", "KEEPTTL"]),
+            Err(RedisError::Syntax)
+        ));
+        assert!(matches!(parse(&["EX"]), Err(RedisError::Syntax)));
+        assert!(matches!(
+            parse(&["EX", "ten"]),
+            Err(RedisError::NumberParse)
+        ));
+        assert!(matches!(parse(&["PX", "0"]), Err(RedisError::Custom(_))));
+
+        let getex = |args: &[&'static str]| {
+            let args: Vec<Bytes> = args.iter().map(|a| Bytes::from(*a)).collect();
+            SetExpiry::parse_getex(&args)
+        };
+        assert_eq!(getex(&[])?, SetExpiry::KeepTtl);
+        assert_eq!(getex(&["persist"])?, SetExpiry::None);
+        assert_eq!(getex(&["EXAT", "5"])?, SetExpiry::At(5000));
+        assert!(matches!(
+            getex(&["PERSIST", "EX", "1"]),
+            Err(RedisError::Syntax)
+        ));
+        assert!(matches!(getex(&["NX"]), Err(RedisError::Syntax)));
 
         Ok(())
     }
 
     #[test]
-    fn store_expired_values() -> Result<(), RedisError> {
-        let mut ms = MapStore::new();
-        ms.set(&"hello".into(), &"there".into(), Some("200".into()))?;
-        assert!(ms.get(&"hello".into()).is_some());
-        std::thread::sleep(Duration::from_millis(300));
-        assert!(ms.get(&"hello".into()).is_none());
+    fn increments() -> Result<(), RedisError> {
+        let mut ms = keyspace();
+        let key: Bytes = "counter".into();
+
+        assert_eq!(ms.incr_by(&key, 5)?, 5);
+        assert_eq!(ms.incr_by(&key, -7)?, -2);
+        ms.insert(&key, string(&i64::MAX.to_string()));
+        assert!(matches!(ms.incr_by(&key, 1), Err(RedisError::Overflow)));
+        assert_eq!(ms.get::<Bytes>(&key)?, Some(&i64::MAX.to_string().into()));
+
+        ms.insert(&key, string("10.50"));
+        assert_eq!(ms.incr_by_float(&key, 0.1)?, "10.6");
+        assert_eq!(ms.incr_by_float(&key, -5.0)?, "5.6");
+        ms.insert(&key, string("5.0e3"));
+        assert_eq!(ms.incr_by_float(&key, 2.0e2)?, "5200");
+        assert!(ms.incr_by_float(&key, f64::INFINITY).is_err());
+        ms.insert(&key, string("0.1"));
+        assert_eq!(ms.incr_by_float(&key, 0.2)?, "0.3");
+
+        ms.insert(&key, string("abc"));
+        assert!(matches!(ms.incr_by(&key, 1), Err(RedisError::NumberParse)));
+        assert!(matches!(
+            ms.incr_by_float(&key, 1.0),
+            Err(RedisError::FloatParse)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn ranges() -> Result<(), RedisError> {
+        let mut ms = keyspace();
+        let key: Bytes = "key".into();
+
+        assert_eq!(ms.append(&key, &"This is".into(), 100)?, 7);
+        assert_eq!(ms.append(&key, &" a string".into(), 100)?, 16);
+        assert!(ms.append(&key, &"!".into(), 16).is_err());
+
+        assert_eq!(ms.getrange(&key, 0, 3)?, "This");
+        assert_eq!(ms.getrange(&key, -3, -1)?, "ing");
+        assert_eq!(ms.getrange(&key, 0, -1)?, "This is a string");
+        assert_eq!(ms.getrange(&key, 10, 100)?, "string");
+        assert_eq!(ms.getrange(&key, -1, -5)?, "");
+        assert_eq!(ms.getrange(&"missing".into(), 0, -1)?, "");
+
+        assert_eq!(ms.setrange(&key, 10, &"Redis!".into(), 100)?, 16);
+        assert_eq!(ms.get::<Bytes>(&key)?, Some(&"This is a Redis!".into()));
+
+        let padded: Bytes = "padded".into();
+        assert_eq!(ms.setrange(&padded, 3, &"abc".into(), 100)?, 6);
+        assert_eq!(ms.get::<Bytes>(&padded)?, Some(&"\0\0\0abc".into()));
+        assert_eq!(ms.setrange(&"empty".into(), 5, &"".into(), 100)?, 0);
+        assert!(!ms.contains(&"empty".into()));
+        assert!(ms.setrange(&padded, 98, &"abc".into(), 100).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn writes_in_place() -> Result<(), RedisError> {
+        let mut ms = keyspace();
+        let key: Bytes = "key".into();
+        ms.setrange(&key, 0, &"x".repeat(64).into(), 100)?;
+        let buffer = ms.get::<Bytes>(&key)?.unwrap().as_ptr();
+
+        // Once the value owns its buffer, later writes reuse it
+        ms.setrange(&key, 8, &"y".into(), 100)?;
+        ms.setbit(&key, 0, true)?;
+        let ops = BitfieldOp::parse_all(
+            &["SET".into(), "u8".into(), "16".into(), "7".into()],
+            100,
+            false,
+        )?;
+        ms.bitfield(&key, &ops)?;
+        let value = ms.get::<Bytes>(&key)?.unwrap();
+        assert_eq!(value.as_ptr(), buffer);
+        assert_eq!(&value[..3], [0xf8, b'x', 7]);
+        assert_eq!(value[8], b'y');
+
+        // Appends grow the buffer rather than copying it each time
+        let mut buffers = std::collections::HashSet::new();
+        for _ in 0..1000 {
+            ms.append(&key, &"z".into(), 2000)?;
+            buffers.insert(ms.get::<Bytes>(&key)?.unwrap().as_ptr());
+        }
+        assert!(buffers.len() < 20);
+
+        Ok(())
+    }
+
+    #[test]
+    fn set_conditions() -> Result<(), RedisError> {
+        let mut ms = keyspace();
+        let (key, value): (Bytes, Bytes) = ("key".into(), "one".into());
+
+        let xx = SetOptions {
+            condition: SetCondition::Exists,
+            ..Default::default()
+        };
+        assert!(!ms.set(&key, &value, &xx)?.written);
+
+        let nx = SetOptions {
+            condition: SetCondition::NotExists,
+            ..Default::default()
+        };
+        assert!(ms.set(&key, &value, &nx)?.written);
+        assert!(!ms.set(&key, &"two".into(), &nx)?.written);
+
+        let get = SetOptions { get: true, ..xx };
+        let result = ms.set(&key, &"two".into(), &get)?;
+        assert!(result.written);
+        assert_eq!(result.previous, Some(value));
+
+        // SET replaces a key of another type, but GET needs the old value to be a string
+        ms.insert(&"list".into(), Data::List(vec!["a".into()]));
+        assert!(ms.set(&"list".into(), &"three".into(), &get).is_err());
+        assert!(ms.set(&"list".into(), &"three".into(), &xx)?.written);
+        assert_eq!(ms.key_type(&"list".into()), "string");
 
         Ok(())
     }
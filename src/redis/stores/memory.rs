@@ -0,0 +1,385 @@
+use std::collections::{HashMap, HashSet};
+
+use bytes::Bytes;
+
+use super::keyspace::{Data, Keyspace};
+use super::sorted_set::SortedSet;
+use super::stream::Stream;
+
+// Redis picks a compact encoding for small values and converts to a general purpose one as
+// they grow. The values here are kept in one representation each, so the encoding reported
+// is the one Redis would use with its default thresholds, and memory is estimated as the
+// 64-bit Redis layout of that encoding
+
+const EMBSTR_MAX_LEN: usize = 44;
+/// Integers Redis shares between all keys rather than allocating
+const SHARED_INTEGERS: i64 = 10_000;
+const LISTPACK_MAX_ENTRIES: usize = 128;
+const LISTPACK_MAX_VALUE: usize = 64;
+const INTSET_MAX_ENTRIES: usize = 512;
+/// Size of a quicklist node's listpack, list-max-listpack-size -2
+const LIST_NODE_BYTES: usize = 8 * 1024;
+
+const OBJECT_HEADER: usize = 16;
+const DICT_ENTRY: usize = 24;
+const DICT_HEADER: usize = 56;
+const POINTER: usize = 8;
+const SKIPLIST_NODE: usize = 48;
+const QUICKLIST_HEADER: usize = 40;
+const QUICKLIST_NODE: usize = 32;
+/// Elements MEMORY USAGE looks at in a collection unless SAMPLES says otherwise
+pub const DEFAULT_SAMPLES: usize = 5;
+
+fn as_integer(value: &[u8]) -> Option<i64> {
+    let number: i64 = std::str::from_utf8(value).ok()?.parse().ok()?;
+    // Only the canonical form is stored as an integer, "007" stays a string
+    (number.to_string().as_bytes() == value).then_some(number)
+}
+
+fn is_small<'a>(mut values: impl Iterator<Item = &'a Bytes>, count: usize) -> bool {
+    count <= LISTPACK_MAX_ENTRIES && values.all(|value| value.len() <= LISTPACK_MAX_VALUE)
+}
+
+fn list_bytes(list: &[Bytes]) -> usize {
+    list.iter().map(|value| listpack_entry(value)).sum()
+}
+
+impl Data {
+    /// Name reported by OBJECT ENCODING
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Self::String(value) if value.len() <= 20 && as_integer(value).is_some() => "int",
+            Self::String(value) if value.len() <= EMBSTR_MAX_LEN => "embstr",
+            Self::String(_) => "raw",
+            // Each entry takes at least two bytes, a long list can't fit in one node
+            Self::List(list) if list.len() * 2 <= LIST_NODE_BYTES => {
+                if list_bytes(list) <= LIST_NODE_BYTES {
+                    "listpack"
+                } else {
+                    "quicklist"
+                }
+            }
+            Self::List(_) => "quicklist",
+            Self::Set(set)
+                if set.len() <= INTSET_MAX_ENTRIES
+                    && set.iter().all(|member| as_integer(member).is_some()) =>
+            {
+                "intset"
+            }
+            Self::Set(set) if is_small(set.iter(), set.len()) => "listpack",
+            Self::Set(_) => "hashtable",
+            Self::Hash(hash) if is_small(hash.iter().flat_map(|(k, v)| [k, v]), hash.len()) => {
+                "listpack"
+            }
+            Self::Hash(_) => "hashtable",
+            Self::SortedSet(set) if is_small(set.iter().map(|(m, _)| m), set.len()) => "listpack",
+            Self::SortedSet(_) => "skiplist",
+            Self::Stream(_) => "stream",
+        }
+    }
+
+    /// Number of references OBJECT REFCOUNT reports, shared integers are never freed
+    pub fn refcount(&self) -> i64 {
+        match self {
+            Self::String(value)
+                if as_integer(value).is_some_and(|n| (0..SHARED_INTEGERS).contains(&n)) =>
+            {
+                i32::MAX as i64
+            }
+            _ => 1,
+        }
+    }
+
+    /// Estimated bytes taken by the value, looking at up to `samples` elements of a
+    /// collection and assuming the rest are alike. All of them are looked at with 0
+    pub fn memory_usage(&self, samples: usize) -> usize {
+        let encoding = self.encoding();
+        OBJECT_HEADER
+            + match self {
+                Self::String(value) => match encoding {
+                    "int" => 0,
+                    _ => sds_size(value.len()),
+                },
+                Self::List(list) => match encoding {
+                    "listpack" => listpack_size(list_bytes(list)),
+                    _ => {
+                        let bytes =
+                            sampled(list.iter().map(|v| listpack_entry(v)), list.len(), samples);
+                        let nodes = bytes.div_ceil(LIST_NODE_BYTES);
+                        QUICKLIST_HEADER + nodes * (QUICKLIST_NODE + listpack_size(0)) + bytes
+                    }
+                },
+                Self::Set(set) => set_usage(set, encoding, samples),
+                Self::Hash(hash) => hash_usage(hash, encoding, samples),
+                Self::SortedSet(set) => zset_usage(set, encoding, samples),
+                Self::Stream(stream) => stream_usage(stream, samples),
+            }
+    }
+}
+
+fn set_usage(set: &HashSet<Bytes>, encoding: &str, samples: usize) -> usize {
+    match encoding {
+        "intset" => 8 + set.len() * 8,
+        "listpack" => listpack_size(set.iter().map(|m| listpack_entry(m)).sum()),
+        _ => {
+            dict_size(set.len())
+                + sampled(
+                    set.iter().map(|m| DICT_ENTRY + sds_size(m.len())),
+                    set.len(),
+                    samples,
+                )
+        }
+    }
+}
+
+fn hash_usage(hash: &HashMap<Bytes, Bytes>, encoding: &str, samples: usize) -> usize {
+    match encoding {
+        "listpack" => listpack_size(
+            hash.iter()
+                .map(|(k, v)| listpack_entry(k) + listpack_entry(v))
+                .sum(),
+        ),
+        _ => {
+            dict_size(hash.len())
+                + sampled(
+                    hash.iter()
+                        .map(|(k, v)| DICT_ENTRY + sds_size(k.len()) + sds_size(v.len())),
+                    hash.len(),
+                    samples,
+                )
+        }
+    }
+}
+
+fn zset_usage(set: &SortedSet, encoding: &str, samples: usize) -> usize {
+    match encoding {
+        "listpack" => listpack_size(
+            set.iter()
+                .map(|(m, score)| listpack_entry(m) + listpack_entry(score.to_string().as_bytes()))
+                .sum(),
+        ),
+        _ => {
+            dict_size(set.len())
+                + sampled(
+                    set.iter()
+                        .map(|(m, _)| DICT_ENTRY + sds_size(m.len()) + SKIPLIST_NODE),
+                    set.len(),
+                    samples,
+                )
+        }
+    }
+}
+
+// Entries are kept in listpacks hanging off a radix tree of their ids
+fn stream_usage(stream: &Stream, samples: usize) -> usize {
+    let entries = stream.values().map(|fields| {
+        let fields: usize = fields
+            .iter()
+            .map(|(k, v)| listpack_entry(k) + listpack_entry(v))
+            .sum();
+        listpack_size(fields) + 2 * POINTER + 16
+    });
+    QUICKLIST_HEADER + sampled(entries, stream.len(), samples)
+}
+
+/// Sums the first `samples` sizes and scales the sum up to all `count` of them
+fn sampled(sizes: impl Iterator<Item = usize>, count: usize, samples: usize) -> usize {
+    let samples = if samples == 0 { count } else { samples };
+    let (mut seen, mut total) = (0, 0);
+    for size in sizes.take(samples) {
+        seen += 1;
+        total += size;
+    }
+
+    match seen {
+        0 => 0,
+        _ => total * count / seen,
+    }
+}
+
+/// Allocation holding a string of `len` bytes, with its header and terminator
+pub fn sds_size(len: usize) -> usize {
+    let header = match len {
+        0..0x100 => 3,
+        0x100..0x10000 => 5,
+        0x10000..0x1_0000_0000 => 9,
+        _ => 17,
+    };
+    (header + len + 1).next_multiple_of(8)
+}
+
+fn listpack_entry(value: &[u8]) -> usize {
+    let encoding = match value.len() {
+        _ if as_integer(value).is_some() => return 1 + 8 + 1,
+        0..64 => 1,
+        64..4096 => 2,
+        _ => 5,
+    };
+    let total = encoding + value.len();
+    let backlen = match total {
+        0..128 => 1,
+        128..16384 => 2,
+        _ => 3,
+    };
+    total + backlen
+}
+
+fn listpack_size(entries: usize) -> usize {
+    (6 + entries + 1).next_multiple_of(8)
+}
+
+fn dict_size(len: usize) -> usize {
+    DICT_HEADER + len.next_power_of_two() * POINTER
+}
+
+/// Totals over the whole server for MEMORY STATS and MEMORY DOCTOR
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MemoryStats {
+    pub peak_allocated: usize,
+    pub total_allocated: usize,
+    pub startup_allocated: usize,
+    pub keys: usize,
+    /// Hash table overhead of the keys and the keys with a TTL of each non-empty database
+    pub databases: Vec<(usize, usize, usize)>,
+}
+
+impl MemoryStats {
+    /// Bookkeeping that isn't the data itself, memory in use at startup plus the key tables
+    pub fn overhead(&self) -> usize {
+        let tables: usize = self
+            .databases
+            .iter()
+            .map(|(_, main, expires)| main + expires)
+            .sum();
+        self.startup_allocated + tables
+    }
+
+    pub fn dataset(&self) -> usize {
+        self.total_allocated.saturating_sub(self.overhead())
+    }
+
+    /// Memory in use past startup averaged over the keys
+    pub fn bytes_per_key(&self) -> usize {
+        match self.keys {
+            0 => 0,
+            keys => self.total_allocated.saturating_sub(self.startup_allocated) / keys,
+        }
+    }
+
+    pub fn dataset_percentage(&self) -> f64 {
+        let net = self.total_allocated.saturating_sub(self.startup_allocated);
+        match net {
+            0 => 0.0,
+            net => self.dataset() as f64 * 100.0 / net as f64,
+        }
+    }
+
+    pub fn peak_percentage(&self) -> f64 {
+        match self.peak_allocated {
+            0 => 0.0,
+            peak => self.total_allocated as f64 * 100.0 / peak as f64,
+        }
+    }
+
+    /// Report of MEMORY DOCTOR on anything that looks off
+    pub fn doctor(&self) -> String {
+        const EMPTY_BELOW: usize = 5 * 1024 * 1024;
+        if self.total_allocated < EMPTY_BELOW {
+            return "Hi Sam, this instance is empty or is using very little memory, my issues \
+                    detector can't be used in these conditions. Please, leave for your mission \
+                    on Earth and fill it with some data. The new Sam and I will be back to our \
+                    programming as soon as I finished rebooting."
+                .into();
+        }
+
+        let mut issues = Vec::new();
+        if self.peak_allocated as f64 > self.total_allocated as f64 * 1.5 {
+            issues.push(format!(
+                " * Peak memory: In the past this instance used more than 150% the memory that \
+                 is currently using. The allocator is normally not able to release memory after \
+                 a peak, so you can expect to see a big fragmentation ratio, however this is \
+                 actually harmless and is only due to the memory peak, and if the Redis instance \
+                 Resident Set Size (RSS) is currently bigger than expected, the memory will be \
+                 used as soon as you fill the Redis instance with more data. If the memory peak \
+                 was only occasional and you want to try to reclaim memory, please try the \
+                 MEMORY PURGE command, otherwise the only other option is to shutdown and \
+                 restart the instance.\n\nPeak: {}, current: {}",
+                self.peak_allocated, self.total_allocated
+            ));
+        }
+
+        if issues.is_empty() {
+            return "Hi Sam, I can't find any memory issue in your instance. I can only account \
+                    for what occurs on this base."
+                .into();
+        }
+
+        format!(
+            "Sam, I detected a few issues in this Redis instance memory implants:\n\n{}\n\n\
+             I'm here to keep you safe, Sam. I want to help you.",
+            issues.join("\n\n")
+        )
+    }
+}
+
+impl Keyspace {
+    /// Estimated bytes taken by the key, its value and its entry in the keyspace
+    pub fn memory_usage(&self, key: &Bytes, samples: usize) -> Option<usize> {
+        let data = self.peek(key)?;
+        Some(DICT_ENTRY + sds_size(key.len()) + data.memory_usage(samples))
+    }
+
+    /// Overhead of the hash tables of the keys and of the keys with a TTL
+    pub fn table_overhead(&self) -> (usize, usize) {
+        let main = self.key_count() * DICT_ENTRY + dict_size(self.key_count());
+        let expires = self.volatile_count() * DICT_ENTRY + dict_size(self.volatile_count());
+        (main, expires)
+    }
+}
+
+#[cfg(test)]
+mod memory_tests {
+    use super::*;
+
+    fn list(len: usize, value: &str) -> Data {
+        Data::List(vec![Bytes::from(value.to_string()); len])
+    }
+
+    #[test]
+    fn encodings() {
+        assert_eq!(Data::String("12345".into()).encoding(), "int");
+        assert_eq!(Data::String("012".into()).encoding(), "embstr");
+        assert_eq!(Data::String("x".repeat(45).into()).encoding(), "raw");
+        assert_eq!(Data::String("100".into()).refcount(), i32::MAX as i64);
+        assert_eq!(Data::String("-1".into()).refcount(), 1);
+
+        assert_eq!(list(100, "a").encoding(), "listpack");
+        assert_eq!(list(1000, "abcdefghij").encoding(), "quicklist");
+
+        let set = |members: &[&str]| {
+            Data::Set(members.iter().map(|m| Bytes::from(m.to_string())).collect())
+        };
+        assert_eq!(set(&["1", "2"]).encoding(), "intset");
+        assert_eq!(set(&["1", "a"]).encoding(), "listpack");
+        assert_eq!(set(&[&"a".repeat(65)]).encoding(), "hashtable");
+
+        let mut zset = SortedSet::default();
+        zset.add(&"a".into(), 1.0);
+        assert_eq!(Data::SortedSet(zset.clone().into()).encoding(), "listpack");
+        zset.add(&"b".repeat(65).into(), 2.0);
+        assert_eq!(Data::SortedSet(zset.into()).encoding(), "skiplist");
+    }
+
+    #[test]
+    fn usage_grows_with_the_value() {
+        let small = list(1000, "a").memory_usage(0);
+        let large = list(2000, "a").memory_usage(0);
+        assert!(large > small && small > 1000);
+
+        // Sampling extrapolates from the first elements
+        let mut values = vec![Bytes::from("a"); 1000];
+        values[999] = "b".repeat(10_000).into();
+        let mixed = Data::List(values);
+        assert!(mixed.memory_usage(0) > mixed.memory_usage(DEFAULT_SAMPLES) + 9_000);
+    }
+}
@@ -1,84 +1,148 @@
+mod access;
+mod bitmap;
+mod client;
+mod events;
+mod eviction;
+mod expiry;
+mod hyperloglog;
+mod keyspace;
 mod list;
 mod map;
+mod memory;
 mod notifier;
 mod pubsub;
 mod queue;
 mod rdb;
+mod scan;
+mod sort;
 mod sorted_set;
 mod stream;
 mod user;
 
+pub use bitmap::{bitcount, bitpos, getbit, parse_bit, parse_offset, BitOp, BitRange, BitfieldOp};
 use bytes::Bytes;
-use list::ListStore;
+use client::ClientStore;
+pub use events::{EventClass, KeyspaceEvent, KeyspaceEvents};
+pub use eviction::{EvictionPolicy, MaxMemory};
+pub use expiry::ExpireOptions;
+pub use keyspace::{Data, Keyspace};
+pub use map::{parse_float, SetExpiry, SetOptions};
+pub use memory::{MemoryStats, DEFAULT_SAMPLES};
+pub use rdb::RestoreOptions;
+pub use scan::ScanOptions;
+pub use sort::SortOptions;
+
 use map::MapStore;
 use notifier::Notifier;
 use pubsub::PubSubStore;
 use queue::TransactionStore;
 use rdb::RdbFile;
-use sorted_set::SortedSetStore;
-use stream::StreamStore;
 use user::UserStore;
 
 use kanal::{AsyncReceiver, AsyncSender};
 
+use std::path::PathBuf;
 use std::sync::{
-    atomic::{AtomicUsize, Ordering},
-    RwLock, RwLockReadGuard, RwLockWriteGuard,
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
 };
+use std::time::{Duration, Instant};
+
+use super::protocol::{ProtocolLimits, ProtocolVersion, RedisError};
+use super::utils::clock::{Clock, SystemClock};
+use super::utils::{alloc, bytes_to_number};
+
+const ACTIVE_EXPIRE_KEYS_PER_LOOP: usize = 20;
+/// Databases available to SELECT unless the databases config says otherwise
+pub const DEFAULT_DATABASES: usize = 16;
 
-use super::protocol::RedisError;
+// A command that panics while holding a lock poisons it. The store itself is still usable
+// so the guard is recovered rather than failing every request that follows
+fn read_lock<T>(lock: &RwLock<T>) -> Result<RwLockReadGuard<'_, T>, RedisError> {
+    Ok(lock.read().unwrap_or_else(PoisonError::into_inner))
+}
+
+fn write_lock<T>(lock: &RwLock<T>) -> Result<RwLockWriteGuard<'_, T>, RedisError> {
+    Ok(lock.write().unwrap_or_else(PoisonError::into_inner))
+}
 
 pub struct GlobalStore {
     replicas: AtomicUsize,
     notifier: RwLock<Notifier>,
-    maps: RwLock<MapStore>,
-    lists: RwLock<ListStore>,
-    streams: RwLock<StreamStore>,
+    /// One keyspace per logical database, selected with SELECT
+    databases: Vec<RwLock<Keyspace>>,
     txns: RwLock<TransactionStore>,
     rdb: RwLock<RdbFile>,
     config: RwLock<MapStore>,
     pubsub: RwLock<PubSubStore>,
-    sorted_set: RwLock<SortedSetStore>,
     users: RwLock<UserStore>,
+    clients: RwLock<ClientStore>,
+    clock: Arc<dyn Clock>,
+    /// Bytes allocated before any data was loaded
+    startup_allocated: usize,
+    max_memory: RwLock<MaxMemory>,
+    evicted_keys: AtomicU64,
+    keyspace_events: RwLock<KeyspaceEvents>,
 }
 
 impl GlobalStore {
-    pub fn new() -> Self {
+    pub fn new(databases: usize) -> Self {
+        Self::with_clock(Arc::new(SystemClock), databases)
+    }
+
+    pub fn with_clock(clock: Arc<dyn Clock>, databases: usize) -> Self {
         Self {
             replicas: AtomicUsize::new(0),
             notifier: RwLock::new(Notifier::new()),
-            maps: RwLock::new(MapStore::new()),
-            lists: RwLock::new(ListStore::new()),
-            streams: RwLock::new(StreamStore::new()),
+            databases: (0..databases.max(1))
+                .map(|_| RwLock::new(Keyspace::new(clock.clone())))
+                .collect(),
             txns: RwLock::new(TransactionStore::new()),
             rdb: RwLock::new(RdbFile::new()),
             config: RwLock::new(MapStore::new()),
             pubsub: RwLock::new(PubSubStore::new()),
-            sorted_set: RwLock::new(SortedSetStore::new()),
             users: RwLock::new(UserStore::new()),
+            clients: RwLock::new(ClientStore::new()),
+            clock,
+            startup_allocated: alloc::allocated(),
+            max_memory: RwLock::new(MaxMemory::default()),
+            evicted_keys: AtomicU64::new(0),
+            keyspace_events: RwLock::new(KeyspaceEvents::default()),
         }
     }
 
     pub async fn register_interest(
         &self,
         id: Bytes,
+        db: usize,
         interest: &[Bytes],
     ) -> Result<AsyncReceiver<Bytes>, RedisError> {
-        let mut notifier = self.notifier.write().map_err(|_| RedisError::WriteLock)?;
-        let receiver = notifier.register_client(id, interest);
+        let mut notifier = write_lock(&self.notifier)?;
+        let receiver = notifier.register_client(id, db, interest);
 
         Ok(receiver)
     }
 
     pub fn unregister_interest(&self, id: &Bytes) -> Result<(), RedisError> {
-        let mut notifier = self.notifier.write().map_err(|_| RedisError::WriteLock)?;
+        let mut notifier = write_lock(&self.notifier)?;
         notifier.unregister_client(id);
         Ok(())
     }
 
-    pub fn client_sender(&self, msg: &Bytes) -> Result<Option<AsyncSender<Bytes>>, RedisError> {
-        let notifier = self.notifier.read().map_err(|_| RedisError::ReadLock)?;
-        Ok(notifier.client_sender(msg))
+    /// Forgets everything held for a client once its connection closes
+    pub fn disconnect(&self, client_id: &Bytes) -> Result<(), RedisError> {
+        self.unregister_interest(client_id)?;
+        write_lock(&self.clients)?.unregister(client_id);
+        Ok(())
+    }
+
+    pub fn client_sender(
+        &self,
+        db: usize,
+        msg: &Bytes,
+    ) -> Result<Option<AsyncSender<Bytes>>, RedisError> {
+        let notifier = read_lock(&self.notifier)?;
+        Ok(notifier.client_sender(db, msg))
     }
 
     pub fn add_replica(&self) {
@@ -91,102 +155,456 @@ impl GlobalStore {
 
     #[allow(dead_code)]
     pub fn notifier_reader(&self) -> Result<RwLockReadGuard<'_, Notifier>, RedisError> {
-        self.notifier.read().map_err(|_| RedisError::ReadLock)
+        read_lock(&self.notifier)
     }
 
     pub fn notifier_writer(&self) -> Result<RwLockWriteGuard<'_, Notifier>, RedisError> {
-        self.notifier.write().map_err(|_| RedisError::WriteLock)
+        write_lock(&self.notifier)
     }
 
-    pub fn map_reader(&self) -> Result<RwLockReadGuard<'_, MapStore>, RedisError> {
-        self.maps.read().map_err(|_| RedisError::ReadLock)
+    pub fn database_count(&self) -> usize {
+        self.databases.len()
     }
 
-    pub fn map_writer(&self) -> Result<RwLockWriteGuard<'_, MapStore>, RedisError> {
-        self.maps.write().map_err(|_| RedisError::WriteLock)
+    fn database(&self, db: usize) -> Result<&RwLock<Keyspace>, RedisError> {
+        self.databases.get(db).ok_or(RedisError::DbOutOfRange)
     }
 
-    pub fn list_reader(&self) -> Result<RwLockReadGuard<'_, ListStore>, RedisError> {
-        self.lists.read().map_err(|_| RedisError::ReadLock)
+    pub fn keyspace_reader(&self, db: usize) -> Result<RwLockReadGuard<'_, Keyspace>, RedisError> {
+        read_lock(self.database(db)?)
     }
 
-    pub fn list_writer(&self) -> Result<RwLockWriteGuard<'_, ListStore>, RedisError> {
-        self.lists.write().map_err(|_| RedisError::WriteLock)
+    pub fn keyspace_writer(&self, db: usize) -> Result<RwLockWriteGuard<'_, Keyspace>, RedisError> {
+        write_lock(self.database(db)?)
     }
 
-    pub fn stream_reader(&self) -> Result<RwLockReadGuard<'_, StreamStore>, RedisError> {
-        self.streams.read().map_err(|_| RedisError::ReadLock)
+    /// Locks two different databases, always in the same order so two commands locking the
+    /// same pair can't deadlock
+    pub fn keyspace_pair(
+        &self,
+        a: usize,
+        b: usize,
+    ) -> Result<
+        (
+            RwLockWriteGuard<'_, Keyspace>,
+            RwLockWriteGuard<'_, Keyspace>,
+        ),
+        RedisError,
+    > {
+        if a < b {
+            let first = self.keyspace_writer(a)?;
+            Ok((first, self.keyspace_writer(b)?))
+        } else {
+            let second = self.keyspace_writer(b)?;
+            Ok((self.keyspace_writer(a)?, second))
+        }
+    }
+
+    /// Empties one database or all of them, handing back what they held so the caller can
+    /// choose where it's freed
+    pub fn flush(&self, db: Option<usize>) -> Result<Vec<Keyspace>, RedisError> {
+        let databases = match db {
+            Some(db) => std::slice::from_ref(self.database(db)?),
+            None => &self.databases[..],
+        };
+
+        let mut flushed = Vec::with_capacity(databases.len());
+        for database in databases {
+            let empty = Keyspace::new(self.clock.clone());
+            flushed.push(std::mem::replace(&mut *write_lock(database)?, empty));
+        }
+
+        Ok(flushed)
     }
 
-    pub fn stream_writer(&self) -> Result<RwLockWriteGuard<'_, StreamStore>, RedisError> {
-        self.streams.write().map_err(|_| RedisError::WriteLock)
+    pub fn memory_stats(&self) -> Result<MemoryStats, RedisError> {
+        let mut stats = MemoryStats {
+            peak_allocated: alloc::peak_allocated(),
+            total_allocated: alloc::allocated(),
+            startup_allocated: self.startup_allocated,
+            ..Default::default()
+        };
+
+        for db in 0..self.databases.len() {
+            let keyspace = self.keyspace_reader(db)?;
+            if keyspace.key_count() > 0 {
+                let (main, expires) = keyspace.table_overhead();
+                stats.keys += keyspace.key_count();
+                stats.databases.push((db, main, expires));
+            }
+        }
+
+        Ok(stats)
     }
 
     pub fn _transaction_reader(&self) -> Result<RwLockReadGuard<'_, TransactionStore>, RedisError> {
-        self.txns.read().map_err(|_| RedisError::ReadLock)
+        read_lock(&self.txns)
     }
 
     pub fn transaction_writer(&self) -> Result<RwLockWriteGuard<'_, TransactionStore>, RedisError> {
-        self.txns.write().map_err(|_| RedisError::WriteLock)
+        write_lock(&self.txns)
     }
 
     pub fn rdb_reader(&self) -> Result<RwLockReadGuard<'_, RdbFile>, RedisError> {
-        self.rdb.read().map_err(|_| RedisError::ReadLock)
+        read_lock(&self.rdb)
     }
 
     pub fn rdb_writer(&self) -> Result<RwLockWriteGuard<'_, RdbFile>, RedisError> {
-        self.rdb.write().map_err(|_| RedisError::WriteLock)
+        write_lock(&self.rdb)
     }
 
     pub fn config_reader(&self) -> Result<RwLockReadGuard<'_, MapStore>, RedisError> {
-        self.config.read().map_err(|_| RedisError::ReadLock)
+        read_lock(&self.config)
     }
 
     pub fn config_writer(&self) -> Result<RwLockWriteGuard<'_, MapStore>, RedisError> {
-        self.config.write().map_err(|_| RedisError::WriteLock)
+        write_lock(&self.config)
     }
 
     pub fn pubsub_reader(&self) -> Result<RwLockReadGuard<'_, PubSubStore>, RedisError> {
-        self.pubsub.read().map_err(|_| RedisError::ReadLock)
+        read_lock(&self.pubsub)
     }
 
     pub fn pubsub_writer(&self) -> Result<RwLockWriteGuard<'_, PubSubStore>, RedisError> {
-        self.pubsub.write().map_err(|_| RedisError::WriteLock)
+        write_lock(&self.pubsub)
     }
 
-    pub fn sorted_set_reader(&self) -> Result<RwLockReadGuard<'_, SortedSetStore>, RedisError> {
-        self.sorted_set.read().map_err(|_| RedisError::ReadLock)
+    pub fn user_reader(&self) -> Result<RwLockReadGuard<'_, UserStore>, RedisError> {
+        read_lock(&self.users)
     }
 
-    pub fn sorted_set_writer(&self) -> Result<RwLockWriteGuard<'_, SortedSetStore>, RedisError> {
-        self.sorted_set.write().map_err(|_| RedisError::WriteLock)
+    pub fn user_writer(&self) -> Result<RwLockWriteGuard<'_, UserStore>, RedisError> {
+        write_lock(&self.users)
     }
 
-    pub fn user_reader(&self) -> Result<RwLockReadGuard<'_, UserStore>, RedisError> {
-        self.users.read().map_err(|_| RedisError::ReadLock)
+    pub fn client_writer(&self) -> Result<RwLockWriteGuard<'_, ClientStore>, RedisError> {
+        write_lock(&self.clients)
     }
 
-    pub fn user_writer(&self) -> Result<RwLockWriteGuard<'_, UserStore>, RedisError> {
-        self.users.write().map_err(|_| RedisError::WriteLock)
+    pub fn client_protocol(&self, client_id: &Bytes) -> Result<ProtocolVersion, RedisError> {
+        let clients = read_lock(&self.clients)?;
+        Ok(clients.protocol(client_id))
+    }
+
+    /// Database the client has selected
+    pub fn client_db(&self, client_id: &Bytes) -> Result<usize, RedisError> {
+        let clients = read_lock(&self.clients)?;
+        Ok(clients.db(client_id))
+    }
+
+    pub fn select(&self, client_id: &Bytes, db: usize) -> Result<(), RedisError> {
+        self.database(db)?;
+        self.client_writer()?.get_or_register(client_id).db = db;
+        Ok(())
+    }
+
+    /// Current unix time in milliseconds
+    pub fn now_ms(&self) -> u64 {
+        self.clock.now_ms()
+    }
+
+    /// Loads an RDB file and adds its keys to the keyspace, skipping any that have expired
+    pub fn load_rdb(&self, path: Option<PathBuf>) -> Result<(), RedisError> {
+        let mut rdb = self.rdb_writer()?;
+        rdb.load(path)?;
+
+        let now = self.now_ms();
+        for database in rdb.databases() {
+            let Some(mut keyspace) = usize::try_from(database.id)
+                .ok()
+                .and_then(|db| self.keyspace_writer(db).ok())
+            else {
+                return Err(RedisError::RdbParse(format!(
+                    "database {} is out of range for the databases config",
+                    database.id
+                )));
+            };
+
+            for (key, entry) in database.entries.iter() {
+                if entry.expiry.is_some_and(|at| at < now) {
+                    continue;
+                }
+
+                keyspace.insert(key, Data::from(entry.value.clone()));
+                keyspace.set_expiry(key, entry.expiry);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes a key ahead of a command using it if the key has expired, returning whether
+    /// it did
+    pub fn prepare_key(&self, db: usize, key: &Bytes) -> Result<bool, RedisError> {
+        if !self.keyspace_reader(db)?.is_expired(key) {
+            return Ok(false);
+        }
+
+        Ok(self.keyspace_writer(db)?.expire_if_needed(key))
+    }
+
+    pub fn exists(&self, db: usize, key: &Bytes) -> Result<bool, RedisError> {
+        Ok(self.keyspace_reader(db)?.contains(key))
+    }
+
+    /// Expiry deadline of a key as unix time in milliseconds
+    pub fn expiry(&self, db: usize, key: &Bytes) -> Result<Option<u64>, RedisError> {
+        Ok(self.keyspace_reader(db)?.expiry(key))
+    }
+
+    /// Sets the deadline of an existing key, returning false if the key is missing or the
+    /// options don't allow it. A deadline that has already passed deletes the key
+    pub fn expire(
+        &self,
+        db: usize,
+        key: &Bytes,
+        at: i64,
+        options: &ExpireOptions,
+    ) -> Result<bool, RedisError> {
+        let mut keyspace = self.keyspace_writer(db)?;
+        if !keyspace.contains(key) || !options.allows(keyspace.expiry(key), at) {
+            return Ok(false);
+        }
+
+        if at <= self.now_ms() as i64 {
+            keyspace.remove(key);
+        } else {
+            keyspace.set_expiry(key, Some(at as u64));
+        }
+
+        Ok(true)
+    }
+
+    /// Removes the TTL from a key, returning whether it had one
+    pub fn persist(&self, db: usize, key: &Bytes) -> Result<bool, RedisError> {
+        let mut keyspace = self.keyspace_writer(db)?;
+        Ok(keyspace.expiry(key).is_some() && keyspace.set_expiry(key, None))
+    }
+
+    /// Keys that haven't expired and match the glob-style pattern
+    pub fn keys(&self, db: usize, pattern: &[u8]) -> Result<Vec<Bytes>, RedisError> {
+        Ok(self.keyspace_reader(db)?.keys(pattern))
+    }
+
+    /// Largest string a command may create, from the proto-max-bulk-len config
+    pub fn max_string_len(&self) -> Result<usize, RedisError> {
+        self.config_number("proto-max-bulk-len", ProtocolLimits::default().max_bulk_len)
+    }
+
+    /// Size past which a sparse HyperLogLog is converted to dense
+    pub fn hll_sparse_max_bytes(&self) -> Result<usize, RedisError> {
+        self.config_number("hll-sparse-max-bytes", hyperloglog::SPARSE_MAX_BYTES)
     }
 
-    pub fn key_type(&self, key: &Bytes) -> Result<Bytes, RedisError> {
-        let map = self.map_reader()?;
-        if map.contains(key) {
-            return Ok("string".into());
+    fn config_number(&self, name: &str, default: usize) -> Result<usize, RedisError> {
+        let config = self.config_reader()?;
+        match config.get(&Bytes::copy_from_slice(name.as_bytes())) {
+            Some(value) => bytes_to_number(value),
+            None => Ok(default),
         }
+    }
 
-        let list = self.list_reader()?;
-        if list.contains(key) {
-            return Ok("list".into());
+    /// Samples keys with a TTL in each database and deletes the expired ones, repeating
+    /// until few sampled keys are expired or the time budget is spent. Locks are released
+    /// between rounds so workers aren't stalled. Returns the deleted keys by database
+    pub fn active_expire_cycle(&self, budget: Duration) -> Result<Vec<(usize, Bytes)>, RedisError> {
+        let start = Instant::now();
+        let mut expired = Vec::new();
+
+        for (db, database) in self.databases.iter().enumerate() {
+            loop {
+                let mut keyspace = write_lock(database)?;
+                let sampled = keyspace.sample_volatile(ACTIVE_EXPIRE_KEYS_PER_LOOP);
+                let before = expired.len();
+                for key in sampled.iter() {
+                    if keyspace.expire_if_needed(key) {
+                        expired.push((db, key.clone()));
+                    }
+                }
+                drop(keyspace);
+
+                if start.elapsed() >= budget {
+                    return Ok(expired);
+                }
+
+                // Keep going only while more than a quarter of the sampled keys had expired
+                if sampled.is_empty() || (expired.len() - before) * 4 <= sampled.len() {
+                    break;
+                }
+            }
         }
 
-        let stream = self.stream_reader()?;
+        Ok(expired)
+    }
+
+    pub fn key_type(&self, db: usize, key: &Bytes) -> Result<Bytes, RedisError> {
+        Ok(self.keyspace_reader(db)?.key_type(key).into())
+    }
+}
+
+#[cfg(test)]
+mod global_store_tests {
+    use super::*;
+    use crate::redis::utils::clock::ManualClock;
+
+    fn store() -> (GlobalStore, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        (
+            GlobalStore::with_clock(clock.clone(), DEFAULT_DATABASES),
+            clock,
+        )
+    }
+
+    #[test]
+    fn lazy_expiry() -> Result<(), RedisError> {
+        let (store, clock) = store();
+        let key: Bytes = "key".into();
+        let options = SetOptions::parse(&["PX".into(), "100".into()])?;
+
+        store
+            .keyspace_writer(0)?
+            .set(&key, &"value".into(), &options)?;
+        assert_eq!(store.expiry(0, &key)?, Some(1_000_100));
 
-        if stream.contains(key) {
-            return Ok("stream".into());
+        clock.advance(100);
+        assert!(!store.prepare_key(0, &key)?);
+        assert!(store.exists(0, &key)?);
+
+        clock.advance(1);
+        assert_eq!(store.keys(0, b"*")?, Vec::<Bytes>::new());
+        assert!(store.prepare_key(0, &key)?);
+        assert!(!store.exists(0, &key)?);
+        assert_eq!(store.expiry(0, &key)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn mset_all_or_nothing() -> Result<(), RedisError> {
+        let (store, _) = store();
+        let pairs = |keys: &[&'static str]| -> Vec<(Bytes, Bytes)> {
+            keys.iter()
+                .map(|k| (Bytes::from(*k), Bytes::from("v")))
+                .collect()
+        };
+
+        let mut keyspace = store.keyspace_writer(0)?;
+        keyspace.rpush(&"list".into(), &["a".into()])?;
+        assert!(!keyspace.mset(&pairs(&["a", "list"]), true));
+        assert!(!keyspace.contains(&"a".into()));
+
+        let options = SetOptions::parse(&["EX".into(), "10".into()])?;
+        keyspace.set(&"a".into(), &"old".into(), &options)?;
+        assert!(keyspace.mset(&pairs(&["a", "b"]), false));
+        assert_eq!(keyspace.get::<Bytes>(&"a".into())?, Some(&"v".into()));
+        drop(keyspace);
+        assert_eq!(store.expiry(0, &"a".into())?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn expire_and_persist() -> Result<(), RedisError> {
+        let (store, _) = store();
+        let key: Bytes = "list".into();
+        let options = ExpireOptions::default();
+
+        assert!(!store.expire(0, &key, 2_000_000, &options)?);
+        store.keyspace_writer(0)?.rpush(&key, &["a".into()])?;
+        assert!(store.expire(0, &key, 2_000_000, &options)?);
+        assert!(store.persist(0, &key)?);
+        assert!(!store.persist(0, &key)?);
+
+        // A deadline in the past deletes the key straight away
+        assert!(store.expire(0, &key, 1_000_000, &options)?);
+        assert!(!store.exists(0, &key)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn databases() -> Result<(), RedisError> {
+        let (store, _) = store();
+        let key: Bytes = "key".into();
+
+        store
+            .keyspace_writer(1)?
+            .insert(&key, Data::String("value".into()));
+        assert!(!store.exists(0, &key)?);
+
+        let (mut source, mut target) = store.keyspace_pair(1, 0)?;
+        assert!(source.move_to(&key, &mut target));
+        drop((source, target));
+        assert!(store.exists(0, &key)?);
+        assert!(!store.exists(1, &key)?);
+
+        let client: Bytes = "client".into();
+        store.select(&client, 3)?;
+        assert_eq!(store.client_db(&client)?, 3);
+        assert!(matches!(
+            store.select(&client, DEFAULT_DATABASES),
+            Err(RedisError::DbOutOfRange)
+        ));
+        let id = store.client_writer()?.get_or_register(&client).id;
+        store.disconnect(&client)?;
+        assert_eq!(store.client_db(&client)?, 0);
+        assert_ne!(store.client_writer()?.get_or_register(&client).id, id);
+
+        assert_eq!(store.flush(None)?.len(), DEFAULT_DATABASES);
+        assert!(!store.exists(0, &key)?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn waiters_are_per_database() -> Result<(), RedisError> {
+        let (store, _) = store();
+        let (client, key): (Bytes, Bytes) = ("client".into(), "list".into());
+        let _receiver = store
+            .register_interest(client.clone(), 0, std::slice::from_ref(&key))
+            .await?;
+
+        // A push to the same key in another database doesn't wake the waiter
+        assert!(store.client_sender(1, &key)?.is_none());
+        assert!(store.client_sender(0, &key)?.is_some());
+
+        store.unregister_interest(&client)?;
+        assert!(store.client_sender(0, &key)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn active_expiry() -> Result<(), RedisError> {
+        let (store, clock) = store();
+        for i in 0..100 {
+            let key: Bytes = format!("key:{i}").into();
+            store
+                .keyspace_writer(0)?
+                .insert(&key, Data::String("value".into()));
+            store.expire(0, &key, 1_000_100 + i, &ExpireOptions::default())?;
         }
+        store
+            .keyspace_writer(0)?
+            .insert(&"persistent".into(), Data::String("value".into()));
+
+        assert!(store
+            .active_expire_cycle(Duration::from_secs(1))?
+            .is_empty());
+
+        // Rounds continue while most sampled keys are expired
+        clock.advance(1000);
+        assert_eq!(
+            store.active_expire_cycle(Duration::from_secs(1))?.len(),
+            100
+        );
+        assert_eq!(store.keys(0, b"*")?, vec![Bytes::from("persistent")]);
+        assert_eq!(
+            store.keys(0, b"per[a-z]ist*")?,
+            vec![Bytes::from("persistent")]
+        );
+        assert_eq!(store.keys(0, b"key:*")?, Vec::<Bytes>::new());
 
-        Ok("none".into())
+        Ok(())
     }
 }
@@ -6,15 +6,17 @@ use std::{
 use bytes::Bytes;
 use kanal::{AsyncReceiver, AsyncSender};
 
+// Keys are watched along with their database, since the same key in another database is a
+// different key
 struct Interest {
-    interest: BTreeSet<Bytes>,
+    interest: BTreeSet<(usize, Bytes)>,
     timestamp: Instant,
     sender: AsyncSender<Bytes>,
 }
 
 pub struct Notifier {
     clients: BTreeMap<Bytes, Interest>,
-    pub backlog: Vec<Bytes>,
+    pub backlog: Vec<(usize, Bytes)>,
 }
 
 impl Notifier {
@@ -25,17 +27,22 @@ impl Notifier {
         }
     }
 
-    pub fn add_to_backlog(&mut self, item: Bytes) {
-        self.backlog.push(item);
+    pub fn add_to_backlog(&mut self, db: usize, item: Bytes) {
+        self.backlog.push((db, item));
     }
 
-    pub fn register_client(&mut self, id: Bytes, interest: &[Bytes]) -> AsyncReceiver<Bytes> {
+    pub fn register_client(
+        &mut self,
+        id: Bytes,
+        db: usize,
+        interest: &[Bytes],
+    ) -> AsyncReceiver<Bytes> {
         let (sender, receiver) = kanal::unbounded_async();
 
         self.clients.insert(
             id,
             Interest {
-                interest: BTreeSet::from_iter(interest.iter().cloned()),
+                interest: interest.iter().map(|key| (db, key.clone())).collect(),
                 timestamp: Instant::now(),
                 sender: sender.clone(),
             },
@@ -48,10 +55,10 @@ impl Notifier {
         self.clients.remove(id);
     }
 
-    pub fn client_sender(&self, msg: &Bytes) -> Option<AsyncSender<Bytes>> {
+    pub fn client_sender(&self, db: usize, msg: &Bytes) -> Option<AsyncSender<Bytes>> {
         match self.longest_waiting_client() {
             Some(client) => {
-                if client.interest.contains(msg) {
+                if client.interest.contains(&(db, msg.clone())) {
                     return Some(client.sender.clone());
                 }
 
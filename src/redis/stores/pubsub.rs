@@ -3,24 +3,25 @@ use std::collections::HashMap;
 use bytes::Bytes;
 use kanal::AsyncSender;
 
-use crate::redis::protocol::{RedisError, Value};
+use crate::redis::protocol::{ProtocolVersion, RedisError, Value};
 
 type SubscriberChannel = AsyncSender<Vec<Value>>;
 
 #[derive(Debug, Clone)]
 pub struct Topic {
     name: Bytes,
-    subscribers: HashMap<Bytes, SubscriberChannel>,
+    subscribers: HashMap<Bytes, (SubscriberChannel, ProtocolVersion)>,
 }
 
 impl Topic {
     pub async fn publish_message(&self, msg: Bytes) -> Result<usize, RedisError> {
-        for sub in self.subscribers.values() {
-            let msg = Value::Array(vec![
+        for (sub, protocol) in self.subscribers.values() {
+            let msg = Value::Push(vec![
                 Value::String("message".into()),
                 Value::String(self.name.clone()),
                 Value::String(msg.clone()),
-            ]);
+            ])
+            .into_protocol(*protocol);
 
             sub.send(vec![msg])
                 .await
@@ -54,15 +55,17 @@ impl PubSubStore {
         channel_name: Bytes,
         client_id: &Bytes,
         responder: SubscriberChannel,
+        protocol: ProtocolVersion,
     ) -> usize {
         self.topics
             .entry(channel_name.clone())
             .and_modify(|ch| {
-                ch.subscribers.insert(client_id.clone(), responder.clone());
+                ch.subscribers
+                    .insert(client_id.clone(), (responder.clone(), protocol));
             })
             .or_insert_with(|| {
                 let mut subs = HashMap::new();
-                subs.insert(client_id.clone(), responder);
+                subs.insert(client_id.clone(), (responder, protocol));
 
                 Topic {
                     name: channel_name,
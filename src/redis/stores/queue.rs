@@ -19,7 +19,7 @@ impl TransactionStore {
     }
 
     pub fn has_transaction(&self, client_id: &Bytes) -> bool {
-        self.map.get(client_id).is_some()
+        self.map.contains_key(client_id)
     }
 
     pub fn remove_transaction(&mut self, client_id: &Bytes) -> Option<Transaction> {
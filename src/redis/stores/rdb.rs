@@ -1,11 +1,13 @@
-use std::{
-    path::PathBuf,
-    time::{SystemTime, UNIX_EPOCH},
-};
+use std::path::PathBuf;
 
+use super::keyspace::{Data, Keyspace};
+use super::sorted_set::SortedSet;
 use crate::redis::{
     protocol::RedisError,
-    rdb::{empty_rdb, parse_rdb, RdbInner},
+    rdb::{
+        dump, empty_rdb, parse_rdb, restore, RdbDatabase, RdbInner, RdbStream, RdbValue, StreamId,
+    },
+    utils::bytes_to_number,
 };
 use anyhow::Result;
 use bytes::Bytes;
@@ -32,7 +34,7 @@ impl RdbFile {
                 } else {
                     let content =
                         std::fs::read(&path).map_err(|e| RedisError::FileRead(e.to_string()))?;
-                    Bytes::from_iter(content.into_iter())
+                    Bytes::from_iter(content)
                 };
 
                 self.raw = raw;
@@ -53,33 +55,216 @@ impl RdbFile {
         self.raw.clone()
     }
 
-    pub fn list(&self) -> Vec<&Bytes> {
-        self.inner
-            .databases
-            .iter()
-            .map(|db| db.entries.keys().collect::<Vec<&Bytes>>())
-            .flatten()
-            .collect::<Vec<&Bytes>>()
+    pub fn databases(&self) -> &[RdbDatabase] {
+        &self.inner.databases
     }
+}
 
-    pub fn get(&self, key: &Bytes) -> Option<Bytes> {
-        for db in self.inner.databases.iter() {
-            match db.entries.get(key) {
-                Some(entry) => {
-                    if let Some(ex) = entry.expiry {
-                        let start = SystemTime::now();
-                        let now = start.duration_since(UNIX_EPOCH).expect("time goes forward");
-                        if now.as_millis() > ex.as_millis() {
-                            return None;
-                        }
-                    }
+fn stream_id(id: &Bytes) -> Option<StreamId> {
+    let (ms, seq) = std::str::from_utf8(id).ok()?.split_once('-')?;
+    Some((ms.parse().ok()?, seq.parse().ok()?))
+}
+
+impl From<&Data> for RdbValue {
+    fn from(data: &Data) -> Self {
+        match data {
+            Data::String(value) => Self::String(value.clone()),
+            Data::List(list) => Self::List(list.clone()),
+            Data::Set(set) => Self::Set(set.iter().cloned().collect()),
+            Data::Hash(hash) => Self::Hash(
+                hash.iter()
+                    .map(|(field, value)| (field.clone(), value.clone()))
+                    .collect(),
+            ),
+            Data::SortedSet(set) => Self::SortedSet(set.ordered()),
+            Data::Stream(stream) => {
+                let mut entries: Vec<_> = stream
+                    .iter()
+                    .filter_map(|(id, fields)| {
+                        Some((stream_id(id)?, fields.iter().cloned().collect()))
+                    })
+                    .collect();
+                entries.sort_by_key(|(id, _)| *id);
+                let last_id = entries.last().map_or((0, 0), |(id, _)| *id);
+                Self::Stream(RdbStream { entries, last_id })
+            }
+        }
+    }
+}
+
+impl From<RdbValue> for Data {
+    fn from(value: RdbValue) -> Self {
+        match value {
+            RdbValue::String(value) => Data::String(value),
+            RdbValue::List(list) => Data::List(list),
+            RdbValue::Set(set) => Data::Set(set.into_iter().collect()),
+            RdbValue::Hash(hash) => Data::Hash(hash.into_iter().collect()),
+            RdbValue::SortedSet(members) => {
+                let mut set = SortedSet::default();
+                for (member, score) in &members {
+                    set.add(member, *score);
+                }
+                Data::SortedSet(Box::new(set))
+            }
+            RdbValue::Stream(stream) => Data::Stream(
+                stream
+                    .entries
+                    .into_iter()
+                    .map(|((ms, seq), fields)| {
+                        (format!("{ms}-{seq}").into(), fields.into_iter().collect())
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Options of RESTORE after the payload
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RestoreOptions {
+    pub replace: bool,
+    /// The TTL is a unix time in milliseconds rather than milliseconds from now
+    pub absttl: bool,
+    pub idle_ms: Option<u64>,
+    pub frequency: Option<u8>,
+}
 
-                    return Some(entry.value.clone());
+impl RestoreOptions {
+    pub fn parse(args: &[Bytes]) -> Result<Self, RedisError> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match &arg.to_ascii_uppercase()[..] {
+                b"REPLACE" => options.replace = true,
+                b"ABSTTL" => options.absttl = true,
+                // IDLETIME and FREQ can't be given together
+                b"IDLETIME" if options.frequency.is_none() => {
+                    let seconds: i64 = bytes_to_number(args.next().ok_or(RedisError::Syntax)?)?;
+                    if seconds < 0 {
+                        return Err(RedisError::Custom(
+                            "Invalid IDLETIME value, must be >= 0".into(),
+                        ));
+                    }
+                    options.idle_ms = Some((seconds as u64).saturating_mul(1000));
+                }
+                b"FREQ" if options.idle_ms.is_none() => {
+                    let frequency: i64 = bytes_to_number(args.next().ok_or(RedisError::Syntax)?)?;
+                    let frequency = u8::try_from(frequency).map_err(|_| {
+                        RedisError::Custom("Invalid FREQ value, must be >= 0 and <= 255".into())
+                    })?;
+                    options.frequency = Some(frequency);
                 }
-                None => return None,
+                _ => return Err(RedisError::Syntax),
             }
         }
 
-        None
+        Ok(options)
+    }
+}
+
+impl Keyspace {
+    /// The key's value serialized as DUMP returns it
+    pub fn dump(&self, key: &Bytes) -> Option<Bytes> {
+        let data = self.data(key)?;
+        Some(dump(&RdbValue::from(data), data.encoding()))
+    }
+
+    /// Creates the key from a DUMP payload. A TTL of 0 means none, and a TTL that has
+    /// already passed leaves the key deleted rather than created
+    pub fn restore(
+        &mut self,
+        key: &Bytes,
+        ttl: &Bytes,
+        payload: &Bytes,
+        options: &RestoreOptions,
+    ) -> Result<(), RedisError> {
+        if !options.replace && self.contains(key) {
+            return Err(RedisError::BusyKey);
+        }
+
+        let ttl: i64 = bytes_to_number(ttl)?;
+        if ttl < 0 {
+            return Err(RedisError::Custom("Invalid TTL value, must be >= 0".into()));
+        }
+
+        let data = Data::from(restore(payload)?);
+        if options.replace {
+            self.remove(key);
+        }
+
+        let now = self.now_ms();
+        let expires_at = match ttl as u64 {
+            0 => None,
+            at if options.absttl => Some(at),
+            ttl => Some(now.saturating_add(ttl)),
+        };
+        if expires_at.is_some_and(|at| at <= now) {
+            return Ok(());
+        }
+
+        self.insert(key, data);
+        self.set_expiry(key, expires_at);
+        self.set_access(key, options.idle_ms, options.frequency);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod rdb_tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::redis::utils::clock::ManualClock;
+
+    fn options(args: &[&str]) -> Result<RestoreOptions, RedisError> {
+        let args: Vec<Bytes> = args
+            .iter()
+            .map(|arg| Bytes::from(arg.to_string()))
+            .collect();
+        RestoreOptions::parse(&args)
+    }
+
+    #[test]
+    fn dump_and_restore() -> Result<(), RedisError> {
+        let mut keyspace = Keyspace::new(Arc::new(ManualClock::new(1_000_000)));
+        let mut set = SortedSet::default();
+        set.add(&"b".into(), 2.5);
+        set.add(&"a".into(), 1.0);
+        keyspace.insert(&"zset".into(), Data::SortedSet(Box::new(set)));
+        let payload = keyspace.dump(&"zset".into()).unwrap();
+        assert_eq!(keyspace.dump(&"missing".into()), None);
+
+        let copy = Bytes::from("copy");
+        keyspace.restore(&copy, &"0".into(), &payload, &options(&["IDLETIME", "60"])?)?;
+        assert_eq!(keyspace.idle_ms(&copy), Some(60_000));
+        assert_eq!(
+            keyspace.get::<SortedSet>(&copy)?.unwrap().ordered(),
+            [(Bytes::from("a"), 1.0), (Bytes::from("b"), 2.5)]
+        );
+        assert_eq!(keyspace.expiry(&copy), None);
+
+        assert!(matches!(
+            keyspace.restore(&copy, &"0".into(), &payload, &options(&[])?),
+            Err(RedisError::BusyKey)
+        ));
+        keyspace.restore(&copy, &"500".into(), &payload, &options(&["REPLACE"])?)?;
+        assert_eq!(keyspace.expiry(&copy), Some(1_000_500));
+
+        // Already expired, so the key is replaced by nothing
+        keyspace.restore(
+            &copy,
+            &"999".into(),
+            &payload,
+            &options(&["REPLACE", "ABSTTL"])?,
+        )?;
+        assert!(!keyspace.contains(&copy));
+
+        assert!(options(&["IDLETIME", "1", "FREQ", "1"]).is_err());
+        assert!(options(&["FREQ", "256"]).is_err());
+        assert!(keyspace
+            .restore(&copy, &"0".into(), &"nope".into(), &options(&[])?)
+            .is_err());
+
+        Ok(())
     }
 }
@@ -0,0 +1,269 @@
+use std::collections::{hash_map::DefaultHasher, BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use bytes::Bytes;
+
+use super::keyspace::Keyspace;
+use super::sorted_set::SortedSet;
+use crate::redis::protocol::{CommandType, RedisError};
+use crate::redis::utils::{bytes_to_number, glob::glob_match, random_u64};
+
+// Keys are visited in the order of their hash with the bits reversed, the order Redis
+// visits the buckets of a hash table in. The order doesn't depend on what else is stored,
+// so a cursor stays valid however the keyspace changes in between calls, and every key
+// present for the whole scan is returned at least once. The cursor handed to clients is
+// the hash of the next key to visit, as it would be a bucket index in Redis
+
+const DEFAULT_COUNT: usize = 10;
+
+/// Position of a key in scan order
+pub fn position(key: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish().reverse_bits()
+}
+
+pub fn position_of_cursor(cursor: u64) -> u64 {
+    cursor.reverse_bits()
+}
+
+/// Takes items in scan order until `count` have been visited, keeping items with the same
+/// position together. Returns the cursor to continue from, 0 once everything is visited
+pub fn batch<T>(visited: impl Iterator<Item = (u64, T)>, count: usize) -> (u64, Vec<T>) {
+    let mut batch = Vec::new();
+    let mut last = None;
+    for (position, item) in visited {
+        if batch.len() >= count && last != Some(position) {
+            return (position.reverse_bits(), batch);
+        }
+
+        last = Some(position);
+        batch.push(item);
+    }
+
+    (0, batch)
+}
+
+/// Up to `count` keys picked by seeking to random positions. Positions are spread evenly
+/// by the hash, so this is close enough to uniform for sampling
+pub fn random_sample(order: &BTreeSet<(u64, Bytes)>, count: usize) -> Vec<Bytes> {
+    let mut picked: Vec<Bytes> = Vec::with_capacity(count);
+    for _ in 0..count {
+        let start = (random_u64(), Bytes::new());
+        let Some((_, key)) = order.range(start..).next().or_else(|| order.first()) else {
+            break;
+        };
+
+        if !picked.contains(key) {
+            picked.push(key.clone());
+        }
+    }
+
+    picked
+}
+
+/// Cursor and options of SCAN, SSCAN, HSCAN and ZSCAN
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanOptions {
+    pub cursor: u64,
+    pub pattern: Option<Bytes>,
+    pub count: usize,
+    /// Only for SCAN
+    pub type_name: Option<String>,
+    /// Only for HSCAN, leaves out the values of fields
+    pub no_values: bool,
+}
+
+impl ScanOptions {
+    /// Parses the arguments from the cursor on
+    pub fn parse(cmd: CommandType, args: &[Bytes]) -> Result<Self, RedisError> {
+        let cursor = args.first().ok_or(RedisError::InsufficientArugments(cmd))?;
+        let cursor = bytes_to_number::<u64>(cursor)
+            .map_err(|_| RedisError::Custom("invalid cursor".into()))?;
+
+        let mut options = Self {
+            cursor,
+            pattern: None,
+            count: DEFAULT_COUNT,
+            type_name: None,
+            no_values: false,
+        };
+
+        let mut args = args[1..].iter();
+        while let Some(arg) = args.next() {
+            match &arg.to_ascii_uppercase()[..] {
+                b"MATCH" => options.pattern = Some(args.next().ok_or(RedisError::Syntax)?.clone()),
+                b"COUNT" => {
+                    let count = args.next().ok_or(RedisError::Syntax)?;
+                    options.count = bytes_to_number::<usize>(count)?;
+                    if options.count == 0 {
+                        return Err(RedisError::Syntax);
+                    }
+                }
+                b"TYPE" if cmd == CommandType::Scan => {
+                    let type_name = args.next().ok_or(RedisError::Syntax)?;
+                    options.type_name = Some(String::from_utf8_lossy(type_name).to_lowercase());
+                }
+                b"NOVALUES" if cmd == CommandType::HScan => options.no_values = true,
+                _ => return Err(RedisError::Syntax),
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Whether a visited key or member passes the MATCH and TYPE filters
+    pub fn matches(&self, key: &[u8], type_name: Option<&str>) -> bool {
+        let pattern_matches = self
+            .pattern
+            .as_ref()
+            .is_none_or(|pattern| glob_match(pattern, key));
+        let type_matches = match (&self.type_name, type_name) {
+            (Some(wanted), Some(type_name)) => wanted == type_name,
+            _ => true,
+        };
+
+        pattern_matches && type_matches
+    }
+}
+
+/// Scans the members of a collection. There's no index kept in scan order for them, so
+/// each call sorts the members that are left
+fn scan_members<'a, T>(
+    members: impl Iterator<Item = (&'a Bytes, T)>,
+    options: &ScanOptions,
+) -> (u64, Vec<(&'a Bytes, T)>) {
+    let start = position_of_cursor(options.cursor);
+    let mut left: Vec<(u64, (&Bytes, T))> = members
+        .map(|member| (position(member.0), member))
+        .filter(|(position, _)| *position >= start)
+        .collect();
+    left.sort_unstable_by(|a, b| (a.0, a.1 .0).cmp(&(b.0, b.1 .0)));
+
+    let (cursor, members) = batch(left.into_iter(), options.count);
+    let members = members
+        .into_iter()
+        .filter(|(member, _)| options.matches(member, None))
+        .collect();
+
+    (cursor, members)
+}
+
+// Per-collection cursors, each returns the cursor to continue from and the members found
+impl Keyspace {
+    pub fn sscan(
+        &self,
+        key: &Bytes,
+        options: &ScanOptions,
+    ) -> Result<(u64, Vec<Bytes>), RedisError> {
+        let Some(set) = self.get::<HashSet<Bytes>>(key)? else {
+            return Ok((0, Vec::new()));
+        };
+
+        let (cursor, members) = scan_members(set.iter().map(|member| (member, ())), options);
+        let members = members.into_iter().map(|(member, _)| member.clone());
+        Ok((cursor, members.collect()))
+    }
+
+    /// Fields are followed by their values unless NOVALUES was given
+    pub fn hscan(
+        &self,
+        key: &Bytes,
+        options: &ScanOptions,
+    ) -> Result<(u64, Vec<Bytes>), RedisError> {
+        let Some(hash) = self.get::<HashMap<Bytes, Bytes>>(key)? else {
+            return Ok((0, Vec::new()));
+        };
+
+        let (cursor, fields) = scan_members(hash.iter(), options);
+        let mut reply = Vec::with_capacity(fields.len() * 2);
+        for (field, value) in fields {
+            reply.push(field.clone());
+            if !options.no_values {
+                reply.push(value.clone());
+            }
+        }
+
+        Ok((cursor, reply))
+    }
+
+    pub fn zscan(
+        &self,
+        key: &Bytes,
+        options: &ScanOptions,
+    ) -> Result<(u64, Vec<(Bytes, f64)>), RedisError> {
+        let Some(set) = self.get::<SortedSet>(key)? else {
+            return Ok((0, Vec::new()));
+        };
+
+        let (cursor, members) = scan_members(set.iter(), options);
+        let members = members
+            .into_iter()
+            .map(|(member, score)| (member.clone(), score));
+        Ok((cursor, members.collect()))
+    }
+}
+
+#[cfg(test)]
+mod scan_tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::redis::stores::keyspace::Data;
+    use crate::redis::utils::clock::ManualClock;
+
+    fn options(args: &[&str]) -> Result<ScanOptions, RedisError> {
+        let args: Vec<Bytes> = args
+            .iter()
+            .map(|arg| Bytes::from(arg.to_string()))
+            .collect();
+        ScanOptions::parse(CommandType::Scan, &args)
+    }
+
+    #[test]
+    fn parse_options() -> Result<(), RedisError> {
+        let parsed = options(&["7", "match", "k*", "COUNT", "100", "TYPE", "String"])?;
+        assert_eq!(parsed.cursor, 7);
+        assert_eq!(parsed.pattern, Some("k*".into()));
+        assert_eq!(parsed.count, 100);
+        assert_eq!(parsed.type_name.as_deref(), Some("string"));
+
+        assert!(options(&["-1"]).is_err());
+        assert!(options(&["0", "COUNT", "0"]).is_err());
+        assert!(options(&["0", "NOVALUES"]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn full_coverage_while_changing() -> Result<(), RedisError> {
+        let mut keyspace = Keyspace::new(Arc::new(ManualClock::new(0)));
+        for i in 0..100 {
+            keyspace.insert(&format!("key:{i}").into(), Data::String("v".into()));
+        }
+
+        let mut seen = HashSet::new();
+        let mut options = options(&["0", "COUNT", "7"])?;
+        let mut rounds = 0;
+        loop {
+            let (cursor, keys) = keyspace.scan(&options);
+            seen.extend(keys);
+
+            // Keys added and removed part way through don't stop the others being visited
+            keyspace.insert(&format!("new:{rounds}").into(), Data::String("v".into()));
+            keyspace.remove(&format!("key:{}", 99 - rounds).into());
+
+            rounds += 1;
+            options.cursor = cursor;
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        for i in 0..100 - rounds {
+            assert!(seen.contains(&Bytes::from(format!("key:{i}"))));
+        }
+
+        Ok(())
+    }
+}
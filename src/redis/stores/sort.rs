@@ -0,0 +1,273 @@
+use std::cmp::Ordering;
+
+use bytes::Bytes;
+
+use super::keyspace::{Data, Keyspace};
+use super::map::parse_float;
+use crate::redis::protocol::{CommandType, RedisError};
+use crate::redis::utils::bytes_to_number;
+
+/// Options of SORT and SORT_RO after the key
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortOptions {
+    /// Pattern of the keys holding the weights, the elements themselves are compared if
+    /// there's none. A pattern without `*` leaves the elements unsorted
+    pub by: Option<Bytes>,
+    /// Offset and count, a negative count takes everything after the offset
+    pub limit: Option<(i64, i64)>,
+    /// Patterns looked up for each element in place of it, `#` being the element itself
+    pub get: Vec<Bytes>,
+    pub desc: bool,
+    pub alpha: bool,
+    /// Only for SORT
+    pub store: Option<Bytes>,
+}
+
+impl SortOptions {
+    pub fn parse(cmd: CommandType, args: &[Bytes]) -> Result<Self, RedisError> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match &arg.to_ascii_uppercase()[..] {
+                b"ASC" => options.desc = false,
+                b"DESC" => options.desc = true,
+                b"ALPHA" => options.alpha = true,
+                b"BY" => options.by = Some(args.next().ok_or(RedisError::Syntax)?.clone()),
+                b"GET" => options
+                    .get
+                    .push(args.next().ok_or(RedisError::Syntax)?.clone()),
+                b"LIMIT" => {
+                    let offset = args.next().ok_or(RedisError::Syntax)?;
+                    let count = args.next().ok_or(RedisError::Syntax)?;
+                    options.limit = Some((bytes_to_number(offset)?, bytes_to_number(count)?));
+                }
+                b"STORE" if cmd == CommandType::Sort => {
+                    options.store = Some(args.next().ok_or(RedisError::Syntax)?.clone());
+                }
+                _ => return Err(RedisError::Syntax),
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn sorts(&self) -> bool {
+        self.by
+            .as_ref()
+            .is_none_or(|pattern| pattern.contains(&b'*'))
+    }
+}
+
+/// What an element is compared by, a missing weight sorts first
+#[derive(Debug)]
+enum Weight {
+    Score(f64),
+    Bytes(Option<Bytes>),
+}
+
+fn compare(a: &(Weight, Bytes), b: &(Weight, Bytes)) -> Ordering {
+    let by_weight = match (&a.0, &b.0) {
+        (Weight::Score(a), Weight::Score(b)) => a.total_cmp(b),
+        (Weight::Bytes(a), Weight::Bytes(b)) => a.cmp(b),
+        _ => Ordering::Equal,
+    };
+
+    // Equal weights fall back on the elements so the order is always the same
+    by_weight.then_with(|| a.1.cmp(&b.1))
+}
+
+impl Keyspace {
+    /// Sorted elements of a list, set or sorted set, or what the GET patterns found for
+    /// them. Patterns that find nothing give None
+    pub fn sort(
+        &self,
+        key: &Bytes,
+        options: &SortOptions,
+    ) -> Result<Vec<Option<Bytes>>, RedisError> {
+        let mut elements: Vec<Bytes> = match self.data(key) {
+            None => Vec::new(),
+            Some(Data::List(list)) => list.clone(),
+            Some(Data::Set(set)) => set.iter().cloned().collect(),
+            Some(Data::SortedSet(set)) => set.iter().map(|(member, _)| member.clone()).collect(),
+            Some(_) => return Err(RedisError::WrongType),
+        };
+
+        if options.sorts() {
+            let mut weighted = Vec::with_capacity(elements.len());
+            for element in elements {
+                let weight = match &options.by {
+                    Some(pattern) => self.lookup(pattern, &element),
+                    None => Some(element.clone()),
+                };
+                let weight = if options.alpha {
+                    Weight::Bytes(weight)
+                } else {
+                    Weight::Score(match weight {
+                        Some(weight) => parse_float(&weight).map_err(|_| {
+                            RedisError::Custom(
+                                "One or more scores can't be converted into double".into(),
+                            )
+                        })?,
+                        None => 0.0,
+                    })
+                };
+                weighted.push((weight, element));
+            }
+
+            weighted.sort_by(compare);
+            elements = weighted.into_iter().map(|(_, element)| element).collect();
+            if options.desc {
+                elements.reverse();
+            }
+        } else {
+            // Left in their own order, but a set has none to keep so it's made stable
+            match self.data(key) {
+                Some(Data::Set(_)) => elements.sort(),
+                Some(Data::SortedSet(_)) if options.desc => elements.reverse(),
+                _ => {}
+            }
+        }
+
+        if let Some((offset, count)) = options.limit {
+            let start = (offset.max(0) as usize).min(elements.len());
+            let end = match usize::try_from(count) {
+                Ok(count) => start.saturating_add(count).min(elements.len()),
+                Err(_) => elements.len(),
+            };
+            elements = elements.drain(start..end).collect();
+        }
+
+        if options.get.is_empty() {
+            return Ok(elements.into_iter().map(Some).collect());
+        }
+
+        let mut found = Vec::with_capacity(elements.len() * options.get.len());
+        for element in &elements {
+            for pattern in &options.get {
+                found.push(self.lookup(pattern, element));
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Value a BY or GET pattern points at for an element. The first `*` is replaced by
+    /// the element, and `key->field` reads a field of a hash instead of a string
+    fn lookup(&self, pattern: &Bytes, element: &Bytes) -> Option<Bytes> {
+        if &pattern[..] == b"#" {
+            return Some(element.clone());
+        }
+
+        let star = pattern.iter().position(|&c| c == b'*')?;
+        let arrow = pattern[star + 1..]
+            .windows(2)
+            .position(|window| window == b"->")
+            .map(|at| star + 1 + at)
+            .filter(|at| at + 2 < pattern.len());
+
+        let key_end = arrow.unwrap_or(pattern.len());
+        let key = [&pattern[..star], &element[..], &pattern[star + 1..key_end]].concat();
+        let key = Bytes::from(key);
+
+        match (self.data(&key)?, arrow) {
+            (Data::String(value), None) => Some(value.clone()),
+            (Data::Hash(hash), Some(at)) => hash.get(&pattern[at + 2..]).cloned(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod sort_tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::redis::utils::clock::ManualClock;
+
+    fn options(args: &[&str]) -> Result<SortOptions, RedisError> {
+        let args: Vec<Bytes> = args
+            .iter()
+            .map(|arg| Bytes::from(arg.to_string()))
+            .collect();
+        SortOptions::parse(CommandType::Sort, &args)
+    }
+
+    fn sorted(keyspace: &Keyspace, args: &[&str]) -> Result<Vec<Option<Bytes>>, RedisError> {
+        keyspace.sort(&"key".into(), &options(args)?)
+    }
+
+    fn values(values: &[&str]) -> Vec<Option<Bytes>> {
+        values
+            .iter()
+            .map(|v| (!v.is_empty()).then(|| Bytes::from(v.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn sort_elements() -> Result<(), RedisError> {
+        let mut keyspace = Keyspace::new(Arc::new(ManualClock::new(0)));
+        let list = ["10", "2", "b", "1"].map(Bytes::from).to_vec();
+        keyspace.insert(&"key".into(), Data::List(list));
+
+        assert!(sorted(&keyspace, &[]).is_err());
+        assert_eq!(
+            sorted(&keyspace, &["ALPHA"])?,
+            values(&["1", "10", "2", "b"])
+        );
+        assert_eq!(
+            sorted(&keyspace, &["ALPHA", "DESC", "LIMIT", "1", "2"])?,
+            values(&["2", "10"])
+        );
+        assert_eq!(
+            sorted(&keyspace, &["BY", "nosort"])?,
+            values(&["10", "2", "b", "1"])
+        );
+
+        keyspace
+            .get_mut::<Vec<Bytes>>(&"key".into())?
+            .unwrap()
+            .retain(|v| v != "b");
+        assert_eq!(sorted(&keyspace, &[])?, values(&["1", "2", "10"]));
+        assert!(options(&["LIMIT", "0"]).is_err());
+        assert!(SortOptions::parse(CommandType::SortRo, &["STORE".into(), "x".into()]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn patterns() -> Result<(), RedisError> {
+        let mut keyspace = Keyspace::new(Arc::new(ManualClock::new(0)));
+        let list = ["1", "2", "3"].map(Bytes::from).to_vec();
+        keyspace.insert(&"key".into(), Data::List(list));
+        for (id, weight) in [("1", "30"), ("2", "10")] {
+            keyspace.insert(&format!("weight_{id}").into(), Data::String(weight.into()));
+            keyspace.insert(
+                &format!("obj_{id}").into(),
+                Data::String(format!("o{id}").into()),
+            );
+        }
+        let hash = HashMap::from([(Bytes::from("name"), Bytes::from("three"))]);
+        keyspace.insert(&"h_3".into(), Data::Hash(hash));
+
+        // The missing weight of 3 counts as 0
+        assert_eq!(
+            sorted(
+                &keyspace,
+                &[
+                    "BY",
+                    "weight_*",
+                    "GET",
+                    "obj_*",
+                    "GET",
+                    "#",
+                    "GET",
+                    "h_*->name"
+                ]
+            )?,
+            values(&["", "3", "three", "o2", "2", "", "o1", "1", ""])
+        );
+
+        Ok(())
+    }
+}
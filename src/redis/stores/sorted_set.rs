@@ -4,75 +4,66 @@ use ordered_float::OrderedFloat;
 
 use std::collections::HashMap;
 
-type SetEntry = (OrderedFloat<f64>, Bytes);
+use super::keyspace::Keyspace;
+use crate::redis::protocol::RedisError;
 
-#[derive(Debug)]
-pub struct SortedSetStore {
-    sets: HashMap<Bytes, SortedSet>,
-}
+type SetEntry = (OrderedFloat<f64>, Bytes);
 
-impl SortedSetStore {
-    pub fn new() -> Self {
-        Self {
-            sets: HashMap::new(),
-        }
+// Sorted set commands
+impl Keyspace {
+    pub fn zadd(&mut self, set: &Bytes, name: &Bytes, score: f64) -> Result<usize, RedisError> {
+        let entry = self.get_or_insert::<SortedSet>(set)?;
+        Ok(entry.add(name, score))
     }
 
-    pub fn zadd(&mut self, set: &Bytes, name: &Bytes, score: f64) -> usize {
-        let entry = self.sets.entry(set.clone()).or_insert(SortedSet::new());
-        entry.add(name, score)
+    pub fn zrank(&self, set: &Bytes, name: &Bytes) -> Result<Option<usize>, RedisError> {
+        Ok(self.get::<SortedSet>(set)?.and_then(|set| set.rank(name)))
     }
 
-    pub fn zrank(&self, set: &Bytes, name: &Bytes) -> Option<usize> {
-        match self.sets.get(set) {
-            Some(set) => set.rank(name),
-            None => None,
-        }
+    pub fn zrange(&self, set: &Bytes, start: i32, end: i32) -> Result<Vec<Bytes>, RedisError> {
+        Ok(self
+            .get::<SortedSet>(set)?
+            .map_or_else(Vec::new, |set| set.range(start, end)))
     }
 
-    pub fn zrange(&self, set: &Bytes, start: i32, end: i32) -> Vec<Bytes> {
-        match self.sets.get(set) {
-            Some(set) => set.range(start, end),
-            None => Vec::new(),
-        }
+    pub fn zcard(&self, set: &Bytes) -> Result<usize, RedisError> {
+        Ok(self.get::<SortedSet>(set)?.map_or(0, SortedSet::len))
     }
 
-    pub fn zcard(&self, set: &Bytes) -> usize {
-        match self.sets.get(set) {
-            Some(set) => set.len(),
-            None => 0,
-        }
+    pub fn zscore(&self, set: &Bytes, name: &Bytes) -> Result<Option<f64>, RedisError> {
+        Ok(self.get::<SortedSet>(set)?.and_then(|set| set.get(name)))
     }
 
-    pub fn zscore(&self, set: &Bytes, name: &Bytes) -> Option<f64> {
-        match self.sets.get(set) {
-            Some(set) => set.get(name),
-            None => None,
-        }
-    }
+    /// Removing the last member deletes the key
+    pub fn zrem(&mut self, set: &Bytes, name: &Bytes) -> Result<usize, RedisError> {
+        let Some(entry) = self.get_mut::<SortedSet>(set)? else {
+            return Ok(0);
+        };
 
-    pub fn zrem(&mut self, set: &Bytes, name: &Bytes) -> usize {
-        match self.sets.get_mut(set) {
-            Some(set) => set.remove(name),
-            None => 0,
-        }
+        let removed = entry.remove(name);
+        self.remove_if_empty(set);
+        Ok(removed)
     }
 }
 
-#[derive(Debug)]
-struct SortedSet {
+#[derive(Debug, Default)]
+pub struct SortedSet {
     map: HashMap<Bytes, f64>,
     set: SkipSet<SetEntry>,
 }
 
-impl SortedSet {
-    pub fn new() -> Self {
-        Self {
-            map: HashMap::new(),
-            set: SkipSet::new(),
+// The skip list can't be cloned so the copy is built up member by member
+impl Clone for SortedSet {
+    fn clone(&self) -> Self {
+        let mut set = Self::default();
+        for (name, score) in self.map.iter() {
+            set.add(name, *score);
         }
+        set
     }
+}
 
+impl SortedSet {
     pub fn add(&mut self, name: &Bytes, score: f64) -> usize {
         if self.map.contains_key(name) {
             let old_score = self
@@ -94,30 +85,33 @@ impl SortedSet {
     }
 
     pub fn get(&self, name: &Bytes) -> Option<f64> {
-        match self.map.get(name) {
-            Some(score) => {
-                let target = (OrderedFloat(*score), name.clone());
-                let Some(entry) = self.set.get(&target) else {
-                    panic!("expected entry in set - got None");
-                };
+        let score = self.map.get(name)?;
+        let target = (OrderedFloat(*score), name.clone());
+        self.set.get(&target).map(|entry| entry.0.into_inner())
+    }
 
-                Some(entry.0.into_inner())
-            }
-            None => None,
-        }
+    /// Members and their scores in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, f64)> {
+        self.map.iter().map(|(name, score)| (name, *score))
     }
 
     pub fn len(&self) -> usize {
         self.set.len()
     }
 
+    /// Members and their scores from the lowest score
+    pub fn ordered(&self) -> Vec<(Bytes, f64)> {
+        self.set
+            .iter()
+            .map(|entry| (entry.1.clone(), entry.0.into_inner()))
+            .collect()
+    }
+
     pub fn rank(&self, name: &Bytes) -> Option<usize> {
         match self.map.get(name) {
             Some(score) => {
                 let target = (OrderedFloat(*score), name.clone());
-                if self.set.get(&target).is_none() {
-                    return None;
-                }
+                self.set.get(&target)?;
 
                 let count = self.set.range(..target).count();
                 Some(count)
@@ -160,7 +154,7 @@ impl SortedSet {
 
     fn idx_converter(&self, idx: i32) -> usize {
         if idx < 0 {
-            if idx.abs() >= self.set.len() as i32 {
+            if idx.unsigned_abs() as usize >= self.set.len() {
                 return 0;
             }
 
@@ -3,37 +3,30 @@ use std::{
     time::{SystemTime, UNIX_EPOCH},
 };
 
+use super::keyspace::Keyspace;
 use crate::redis::{
     protocol::{RedisError, Value},
     utils::bytes_to_str,
 };
 use bytes::Bytes;
 
-type Stream = BTreeMap<Bytes, BTreeSet<(Bytes, Bytes)>>;
-
-pub struct StreamStore {
-    map: BTreeMap<Bytes, Stream>,
-}
-
-impl StreamStore {
-    pub fn new() -> Self {
-        Self {
-            map: BTreeMap::default(),
-        }
-    }
-
-    pub fn contains(&self, key: &Bytes) -> bool {
-        self.map.contains_key(key)
-    }
+pub type Stream = BTreeMap<Bytes, BTreeSet<(Bytes, Bytes)>>;
 
+// Stream commands
+impl Keyspace {
     pub fn add_entry<'a>(
         &mut self,
         stream_key: &'a Bytes,
         entry_key: &'a Bytes,
         values: Option<&'a [(Bytes, Bytes)]>,
     ) -> Result<Value, RedisError> {
-        let stream = self.map.entry(stream_key.clone()).or_default();
-        let entry_key = validate_entry_id(entry_key, stream)?;
+        // The ID is checked before the stream is created so a rejected XADD leaves no key
+        let entry_key = match self.get_mut::<Stream>(stream_key)? {
+            Some(stream) => validate_entry_id(entry_key, stream)?,
+            None => validate_entry_id(entry_key, &mut Stream::new())?,
+        };
+
+        let stream = self.get_or_insert::<Stream>(stream_key)?;
         let entry = stream.entry(entry_key.clone()).or_default();
         if let Some(values) = values {
             for value in values {
@@ -50,8 +43,8 @@ impl StreamStore {
         start_id: &Bytes,
         end_id: &Bytes,
     ) -> Result<Value, RedisError> {
-        let Some(stream) = self.map.get(stream_key) else {
-            todo!("error");
+        let Some(stream) = self.get::<Stream>(stream_key)? else {
+            return Ok(Value::EmptyArray);
         };
 
         let start_id_str = bytes_to_str(start_id)?;
@@ -82,13 +75,17 @@ impl StreamStore {
         Ok(Value::Array(values))
     }
 
-    pub fn xread(&self, stream_keys: &[Bytes], entry_ids: &[Bytes]) -> Value {
-        assert!(stream_keys.len() == entry_ids.len());
+    pub fn xread(&self, stream_keys: &[Bytes], entry_ids: &[Bytes]) -> Result<Value, RedisError> {
+        if stream_keys.len() != entry_ids.len() {
+            return Err(RedisError::Custom(
+                "Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified.".into(),
+            ));
+        }
 
         let mut streams = Vec::new();
         for (stream_key, entry_id) in stream_keys.iter().zip(entry_ids.iter()) {
-            let Some(stream) = self.map.get(stream_key) else {
-                todo!("error");
+            let Some(stream) = self.get::<Stream>(stream_key)? else {
+                continue;
             };
 
             let mut stream_vec = Vec::new();
@@ -123,7 +120,11 @@ impl StreamStore {
             streams.push(Value::Array(vec![stream_key, Value::Array(stream_vec)]));
         }
 
-        Value::Array(streams)
+        if streams.is_empty() {
+            return Ok(Value::NullArray);
+        }
+
+        Ok(Value::Array(streams))
     }
 }
 
@@ -145,23 +146,28 @@ pub fn validate_entry_id(entry_id: &Bytes, stream: &mut Stream) -> Result<Bytes,
 
     if entry_id_str == "0-0" {
         return Err(RedisError::StreamIdError(
-            "ERR The ID specified in XADD must be greater than 0-0".to_string(),
+            "The ID specified in XADD must be greater than 0-0".to_string(),
         ));
     }
 
-    let (timestamp, seq) = entry_id_str
-        .split_once("-")
-        .expect("this should be a valid id");
+    let invalid_id = || {
+        RedisError::StreamIdError(
+            "Invalid stream ID specified as stream command argument".to_string(),
+        )
+    };
+
+    let (timestamp, seq) = entry_id_str.split_once("-").ok_or_else(invalid_id)?;
 
     match stream.last_entry() {
         Some(last) => {
             let (l_timestamp, l_seq) = bytes_to_str(last.key())?
                 .split_once("-")
-                .expect("this should be a valid entry id");
+                .ok_or_else(invalid_id)?;
 
             if l_timestamp > timestamp {
                 return Err(RedisError::StreamIdError(
-                    "ERR The ID specified in XADD is equal or smaller than the target stream top item".to_string()
+                    "The ID specified in XADD is equal or smaller than the target stream top item"
+                        .to_string(),
                 ));
             }
 
@@ -180,7 +186,7 @@ pub fn validate_entry_id(entry_id: &Bytes, stream: &mut Stream) -> Result<Bytes,
                 if timestamp == l_timestamp {
                     if seq <= l_seq {
                         return Err(RedisError::StreamIdError(
-                            "ERR The ID specified in XADD is equal or smaller than the target stream top item".to_string()
+                            "The ID specified in XADD is equal or smaller than the target stream top item".to_string()
                         ));
                     }
 
@@ -27,10 +27,7 @@ impl UserStore {
     }
 
     pub fn get(&self, user: &Bytes) -> Option<Value> {
-        match self.users.get(user) {
-            Some(user) => Some(user.repr()),
-            None => None,
-        }
+        self.users.get(user).map(|user| user.repr())
     }
 
     pub fn set_password(&mut self, user: &Bytes, pass: &Bytes, client_id: &Bytes) {
@@ -84,32 +81,29 @@ impl RedisUser {
     }
 
     pub fn repr(&self) -> Value {
-        let mut values: Vec<Value> = vec![Value::String("flags".into())];
         let flags = self
             .flags
             .iter()
             .map(|flag| Value::String(flag.to_string().into()))
             .collect::<Vec<Value>>();
 
-        values.push(Value::Array(flags));
-        values.push(Value::String("passwords".into()));
-
         let passwords = self
             .passwords
             .iter()
             .map(|pass| Value::String(pass.clone()))
             .collect::<Vec<Value>>();
 
-        values.push(Value::Array(passwords));
-
-        Value::Array(values)
+        Value::Map(vec![
+            (Value::String("flags".into()), Value::Array(flags)),
+            (Value::String("passwords".into()), Value::Array(passwords)),
+        ])
     }
 }
 
 impl Default for RedisUser {
     fn default() -> Self {
         Self {
-            flags: HashSet::from_iter([UserFlag::NoPass].into_iter()),
+            flags: HashSet::from_iter([UserFlag::NoPass]),
             passwords: HashSet::new(),
             authed_clients: HashSet::new(),
         }
@@ -121,10 +115,10 @@ pub enum UserFlag {
     NoPass,
 }
 
-impl ToString for UserFlag {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for UserFlag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::NoPass => "nopass".to_string(),
+            Self::NoPass => write!(f, "nopass"),
         }
     }
 }
@@ -10,7 +10,7 @@ const LONGITUDE_RANGE: f64 = MAX_LONGITUDE - MIN_LONGITUDE;
 const EARTH_RADIUS: f64 = 6372797.560856;
 
 pub fn validate_latlon(lat: f64, lon: f64) -> bool {
-    (lat >= MIN_LATITUDE && lat <= MAX_LATITUDE) && (lon >= MIN_LONGITUDE && lon <= MAX_LONGITUDE)
+    (MIN_LATITUDE..=MAX_LATITUDE).contains(&lat) && (MIN_LONGITUDE..=MAX_LONGITUDE).contains(&lon)
 }
 
 pub fn encode_latlon(lat: f64, lon: f64) -> u64 {
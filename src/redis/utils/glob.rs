@@ -0,0 +1,147 @@
+// Glob-style patterns as Redis matches them for KEYS, SCAN MATCH, PSUBSCRIBE and ACL keys:
+//  * `*` matches any run of characters, `?` any single character
+//  * `[abc]` one of the listed characters, `[^abc]` anything else, `[a-z]` a range
+//  * `\x` the character x itself, also inside brackets
+
+// Each star recurses, this bounds the depth for patterns made of thousands of them
+const MAX_NESTING: usize = 1000;
+
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let mut skip_longer = false;
+    matches(pattern, string, 0, &mut skip_longer)
+}
+
+fn matches(mut pattern: &[u8], mut string: &[u8], nesting: usize, skip_longer: &mut bool) -> bool {
+    if nesting > MAX_NESTING {
+        return false;
+    }
+
+    while let Some(&c) = pattern.first() {
+        match c {
+            b'*' => {
+                while pattern.first() == Some(&b'*') {
+                    pattern = &pattern[1..];
+                }
+                if pattern.is_empty() {
+                    return true;
+                }
+
+                for start in 0..=string.len() {
+                    if matches(pattern, &string[start..], nesting + 1, skip_longer) {
+                        return true;
+                    }
+                    // The rest of the pattern failed on every suffix of this one, it won't
+                    // match the shorter suffixes an outer star would try next either
+                    if *skip_longer {
+                        return false;
+                    }
+                }
+
+                *skip_longer = true;
+                return false;
+            }
+            b'?' => {
+                if string.is_empty() {
+                    return false;
+                }
+                pattern = &pattern[1..];
+            }
+            b'[' => {
+                let Some(&ch) = string.first() else {
+                    return false;
+                };
+
+                let (matched, rest) = match_class(&pattern[1..], ch);
+                if !matched {
+                    return false;
+                }
+                pattern = rest;
+            }
+            _ => {
+                let (literal, rest) = match pattern {
+                    [b'\\', escaped, rest @ ..] => (*escaped, rest),
+                    [literal, rest @ ..] => (*literal, rest),
+                    [] => unreachable!("pattern isn't empty"),
+                };
+                if string.first() != Some(&literal) {
+                    return false;
+                }
+                pattern = rest;
+            }
+        }
+
+        string = &string[1..];
+    }
+
+    string.is_empty()
+}
+
+/// Matches a character against the class after a `[`, returning whether it matched and
+/// the pattern after the closing `]`. An unclosed class runs to the end of the pattern
+fn match_class(mut pattern: &[u8], ch: u8) -> (bool, &[u8]) {
+    let negated = pattern.first() == Some(&b'^');
+    if negated {
+        pattern = &pattern[1..];
+    }
+
+    let mut matched = false;
+    loop {
+        match pattern {
+            [] => break,
+            [b']', rest @ ..] => {
+                pattern = rest;
+                break;
+            }
+            [b'\\', escaped, rest @ ..] => {
+                matched |= *escaped == ch;
+                pattern = rest;
+            }
+            [start, b'-', end, rest @ ..] => {
+                let (low, high) = if start <= end {
+                    (*start, *end)
+                } else {
+                    (*end, *start)
+                };
+                matched |= (low..=high).contains(&ch);
+                pattern = rest;
+            }
+            [literal, rest @ ..] => {
+                matched |= *literal == ch;
+                pattern = rest;
+            }
+        }
+    }
+
+    (matched != negated, pattern)
+}
+
+#[cfg(test)]
+mod glob_tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"*", b"anything"));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(!glob_match(b"h?llo", b"hllo"));
+        assert!(glob_match(b"h*llo", b"heeeello"));
+        assert!(glob_match(b"user:*:name", b"user:42:name"));
+        assert!(!glob_match(b"user:*:name", b"user:42:age"));
+        assert!(!glob_match(b"a*a*a*a*a*a*a*a*a*a*b", &[b'a'; 64]));
+    }
+
+    #[test]
+    fn classes_and_escapes() {
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[ae]llo", b"hillo"));
+        assert!(glob_match(b"h[^e]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+        assert!(glob_match(b"h[b-a]llo", b"hallo"));
+        assert!(glob_match(b"h[\\]]llo", b"h]llo"));
+        assert!(glob_match(b"h[ab", b"hb"));
+        assert!(glob_match(b"\\*key\\?", b"*key?"));
+        assert!(!glob_match(b"\\*key", b"akey"));
+    }
+}
@@ -1,9 +1,14 @@
+use std::cell::Cell;
 use std::str::FromStr;
 
 use crate::redis::{RedisCommand, RedisError};
 use bytes::Bytes;
+use uuid::Uuid;
 
+pub mod alloc;
+pub mod clock;
 pub mod geo;
+pub mod glob;
 
 pub fn bytes_to_str(b: &Bytes) -> Result<&str, RedisError> {
     str::from_utf8(&b[..]).map_err(|_| RedisError::StringConversion)
@@ -14,6 +19,47 @@ pub fn bytes_to_number<T: FromStr>(b: &Bytes) -> Result<T, RedisError> {
     str.parse::<T>().map_err(|_| RedisError::NumberParse)
 }
 
+/// Parses a memory size in the same format as Redis config e.g. '512mb', '1gb', '100'
+pub fn parse_memory_size(s: &str) -> Result<usize, RedisError> {
+    let s = s.trim().to_ascii_lowercase();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+
+    let (digits, unit) = s.split_at(split);
+    let multiplier = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return Err(RedisError::NumberParse),
+    };
+
+    let value = digits
+        .parse::<usize>()
+        .map_err(|_| RedisError::NumberParse)?;
+    value.checked_mul(multiplier).ok_or(RedisError::NumberParse)
+}
+
+thread_local! {
+    // Seeded from the OS once per thread, `| 1` since xorshift gets stuck at zero
+    static RANDOM_STATE: Cell<u64> = Cell::new(Uuid::new_v4().as_u64_pair().0 | 1);
+}
+
+/// Cheap non-cryptographic random number (xorshift64*), for hot paths such as counting
+/// key accesses where asking the OS every time would be too slow
+pub fn random_u64() -> u64 {
+    RANDOM_STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    })
+}
+
 pub fn validate_args_len(req: &RedisCommand, len: usize) -> Result<(), RedisError> {
     if req.args.len() < len {
         return Err(RedisError::InsufficientArugments(req.cmd));